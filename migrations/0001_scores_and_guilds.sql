@@ -0,0 +1,20 @@
+-- Discord ids are stored as INTEGER, they fit in an i64.
+
+CREATE TABLE IF NOT EXISTS scores (
+    guild_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    voice_time INTEGER NOT NULL DEFAULT 0,
+    afk_time INTEGER NOT NULL DEFAULT 0,
+    longest_session INTEGER NOT NULL DEFAULT 0,
+    stream_time INTEGER NOT NULL DEFAULT 0,
+    video_time INTEGER NOT NULL DEFAULT 0,
+    stage_time INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (guild_id, user_id)
+);
+
+CREATE TABLE IF NOT EXISTS guilds (
+    guild_id INTEGER PRIMARY KEY,
+    afk_channel INTEGER,
+    graveyard INTEGER,
+    achievement_channel INTEGER
+);
@@ -0,0 +1,8 @@
+-- When a user was first credited in any guild, kept until their last score is deleted.
+-- Members credited before this table existed have no row.
+
+CREATE TABLE IF NOT EXISTS players (
+    user_id INTEGER PRIMARY KEY,
+    -- Unix seconds.
+    first_seen INTEGER NOT NULL
+);
@@ -0,0 +1,75 @@
+-- What pocketbase keeps besides scores and configs, so a bot on SQLite needs nothing else.
+-- Times are unix seconds.
+
+CREATE TABLE IF NOT EXISTS sessions (
+    guild_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    channel_id INTEGER NOT NULL,
+    -- `voice` or `afk`.
+    kind TEXT NOT NULL,
+    joined_at INTEGER NOT NULL,
+    started_at INTEGER NOT NULL,
+    PRIMARY KEY (guild_id, user_id)
+);
+
+CREATE TABLE IF NOT EXISTS voice_sessions (
+    guild_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    channel_id INTEGER NOT NULL,
+    kind TEXT NOT NULL,
+    started_at INTEGER NOT NULL,
+    ended_at INTEGER NOT NULL,
+    UNIQUE (guild_id, user_id, started_at)
+);
+
+CREATE TABLE IF NOT EXISTS period_scores (
+    guild_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    period TEXT NOT NULL,
+    bucket TEXT NOT NULL,
+    voice_time INTEGER NOT NULL DEFAULT 0,
+    afk_time INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (guild_id, period, bucket, user_id)
+);
+
+CREATE TABLE IF NOT EXISTS seasons (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    guild_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    started_at INTEGER NOT NULL,
+    -- NULL while the season is running.
+    ended_at INTEGER
+);
+
+-- At most one running season per guild.
+CREATE UNIQUE INDEX IF NOT EXISTS idx_seasons_running ON seasons (guild_id) WHERE ended_at IS NULL;
+
+CREATE TABLE IF NOT EXISTS achievements (
+    guild_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    achievement TEXT NOT NULL,
+    unlocked_at INTEGER NOT NULL,
+    PRIMARY KEY (guild_id, user_id, achievement)
+);
+
+CREATE TABLE IF NOT EXISTS channel_scores (
+    guild_id INTEGER NOT NULL,
+    channel_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    time INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (guild_id, channel_id, user_id)
+);
+
+CREATE TABLE IF NOT EXISTS channels (
+    guild_id INTEGER NOT NULL,
+    channel_id INTEGER NOT NULL,
+    peak_users INTEGER NOT NULL,
+    peak_at INTEGER NOT NULL,
+    PRIMARY KEY (guild_id, channel_id)
+);
+
+-- A JSON array of `{"hours", "role_id"}`, NULL when none were set.
+ALTER TABLE guilds ADD COLUMN milestone_roles TEXT;
+ALTER TABLE guilds ADD COLUMN milestone_mode TEXT NOT NULL DEFAULT 'all';
+-- A JSON object, NULL when none were set.
+ALTER TABLE guilds ADD COLUMN activity_rules TEXT;
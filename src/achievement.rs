@@ -0,0 +1,218 @@
+use std::collections::HashSet;
+
+use anyhow::Result;
+use chrono::{DateTime, Timelike, Utc};
+use tracing::{info, warn};
+
+use crate::{
+    config::Configs,
+    score::{GuildUser, Score, ScoreType},
+    Data, VoiceSession,
+};
+
+const HOUR: u64 = 60 * 60;
+
+/// An achievement a member has unlocked, by `Achievement::id`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Unlocked {
+    pub achievement: String,
+    pub unlocked_at: DateTime<Utc>,
+}
+
+/// An achievement members unlock by spending time in voice.
+#[derive(Debug)]
+pub struct Achievement {
+    /// Kept by the storage, never change it once released.
+    pub id: &'static str,
+    pub name: &'static str,
+    pub description: &'static str,
+    rule: Rule,
+}
+
+#[derive(Debug)]
+enum Rule {
+    /// Total voice time of at least this many seconds.
+    VoiceTime(u64),
+    /// Total AFK time of at least this many seconds.
+    AfkTime(u64),
+    /// A single voice session of at least this many seconds.
+    SessionLength(u64),
+    /// In voice at some point between midnight and this hour, in UTC.
+    VoiceBefore(u32),
+}
+
+pub static CATALOGUE: &[Achievement] = &[
+    Achievement {
+        id: "voice_1h",
+        name: "Warming Up",
+        description: "Spend 1 hour in voice",
+        rule: Rule::VoiceTime(HOUR),
+    },
+    Achievement {
+        id: "voice_10h",
+        name: "Regular",
+        description: "Spend 10 hours in voice",
+        rule: Rule::VoiceTime(10 * HOUR),
+    },
+    Achievement {
+        id: "voice_100h",
+        name: "Penghuni",
+        description: "Spend 100 hours in voice",
+        rule: Rule::VoiceTime(100 * HOUR),
+    },
+    Achievement {
+        id: "voice_1000h",
+        name: "Touch Grass",
+        description: "Spend 1000 hours in voice",
+        rule: Rule::VoiceTime(1000 * HOUR),
+    },
+    Achievement {
+        id: "session_10h",
+        name: "Marathon",
+        description: "Stay in voice for 10 hours in one go",
+        rule: Rule::SessionLength(10 * HOUR),
+    },
+    Achievement {
+        id: "night_owl",
+        name: "Night Owl",
+        description: "Be in voice between midnight and 5 AM (UTC)",
+        rule: Rule::VoiceBefore(5),
+    },
+    Achievement {
+        id: "afk_24h",
+        name: "AFK Champion",
+        description: "Spend 24 hours in the AFK channel",
+        rule: Rule::AfkTime(24 * HOUR),
+    },
+];
+
+impl Achievement {
+    fn is_unlocked(&self, progress: &Progress) -> bool {
+        match self.rule {
+            Rule::VoiceTime(secs) => progress.score.score.as_secs() >= secs,
+            Rule::AfkTime(secs) => progress.score.afk_score.as_secs() >= secs,
+            Rule::SessionLength(secs) => {
+                let length = (progress.now - progress.session.joined_at).num_seconds();
+                progress.score_type == ScoreType::Voice && length >= secs as i64
+            }
+            Rule::VoiceBefore(hour) => {
+                let from = progress.session.started_at;
+                let to = progress.now;
+                // Crossing a date means the session went through midnight.
+                progress.score_type == ScoreType::Voice
+                    && (from.date_naive() != to.date_naive()
+                        || from.hour() < hour
+                        || to.hour() < hour)
+            }
+        }
+    }
+}
+
+/// What a member has done so far, right after their time got credited.
+struct Progress<'a> {
+    score: &'a Score,
+    score_type: ScoreType,
+    session: VoiceSession,
+    now: DateTime<Utc>,
+}
+
+/// Unlocks every achievement the member has earned with the time just credited,
+/// and announces them in the guild's achievement channel.
+pub async fn evaluate(
+    data: &Data,
+    guild_user: GuildUser,
+    score_type: ScoreType,
+    score: &Score,
+    session: VoiceSession,
+    now: DateTime<Utc>,
+) -> Result<()> {
+    let progress = Progress {
+        score,
+        score_type,
+        session,
+        now,
+    };
+    let earned = CATALOGUE
+        .iter()
+        .filter(|achievement| achievement.is_unlocked(&progress))
+        .collect::<Vec<_>>();
+    if earned.is_empty() {
+        return Ok(());
+    }
+
+    let unlocked = get_unlocked(data, guild_user).await?;
+    let new = earned
+        .into_iter()
+        .filter(|achievement| !unlocked.contains(achievement.id))
+        .collect::<Vec<_>>();
+
+    for achievement in new {
+        data.storage
+            .unlock_achievement(guild_user, achievement.id, now)
+            .await?;
+
+        {
+            let mut cache = data.cache.lock().await;
+            if let Some(unlocked) = cache.get_mut_achievements(guild_user) {
+                unlocked.insert(achievement.id.to_string());
+            }
+        }
+
+        info!(achievement = achievement.id, "Unlocked an achievement");
+        announce(data, guild_user, achievement).await?;
+    }
+
+    Ok(())
+}
+
+/// Gets the ids of the achievements a member has unlocked.
+pub async fn get_unlocked(data: &Data, guild_user: GuildUser) -> Result<HashSet<String>> {
+    let cached = {
+        let cache = data.cache.lock().await;
+        cache.get_achievements(guild_user).cloned()
+    };
+    if let Some(unlocked) = cached {
+        return Ok(unlocked);
+    }
+
+    let unlocked = data
+        .storage
+        .achievements(guild_user)
+        .await?
+        .into_iter()
+        .map(|unlocked| unlocked.achievement)
+        .collect::<HashSet<_>>();
+
+    {
+        let mut cache = data.cache.lock().await;
+        cache.insert_achievements(guild_user, unlocked.clone());
+    }
+
+    Ok(unlocked)
+}
+
+async fn announce(data: &Data, guild_user: GuildUser, achievement: &Achievement) -> Result<()> {
+    let config = {
+        let cache = data.cache.lock().await;
+        cache.get_config(guild_user.0).copied()
+    };
+    let config = match config {
+        Some(config) => config,
+        None => Configs::get_guild_config(data, guild_user.0).await?,
+    };
+
+    let Some(channel_id) = config.achievement_channel else {
+        return Ok(());
+    };
+
+    let content = format!(
+        "<@{}> unlocked **{}**: {}!",
+        guild_user.1, achievement.name, achievement.description
+    );
+    // A missing permission shouldn't stop the time from being credited.
+    if let Err(err) = channel_id.say(&data.http, content).await {
+        warn!(?err, "Can't announce an achievement");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use poise::serenity_prelude::{ChannelId, ChannelType, Guild, GuildId, UserId, VoiceState};
+use serde::{Deserialize, Serialize};
+
+use crate::{score::ScoreType, Data};
+
+/// Where a member's time goes right now, `None` when it isn't credited at all.
+pub type Presence = Option<(ScoreType, ChannelId)>;
+
+/// What a member is doing in a voice channel on top of being there, credited alongside
+/// their voice time.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Extras {
+    pub stream: bool,
+    pub video: bool,
+    /// On stage as a speaker, not in the audience.
+    pub stage: bool,
+}
+
+impl Extras {
+    fn from_voice_state(vs: &VoiceState, is_stage: bool) -> Self {
+        Extras {
+            stream: vs.self_stream.unwrap_or_default(),
+            video: vs.self_video,
+            stage: is_stage && !vs.suppress,
+        }
+    }
+}
+
+/// Where a member's time goes right now, and what else they are doing there.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MemberVoice {
+    pub presence: Presence,
+    /// Only ever set for members credited with voice time.
+    pub extras: Extras,
+}
+
+/// A reason for a member in voice to not be credited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum ActivityRule {
+    #[name = "self-deaf"]
+    SelfDeaf,
+    #[name = "server-deaf"]
+    ServerDeaf,
+    #[name = "self-mute"]
+    SelfMute,
+    #[name = "solo"]
+    Solo,
+    #[name = "bots-only"]
+    BotsOnly,
+}
+
+impl ActivityRule {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ActivityRule::SelfDeaf => "self-deaf",
+            ActivityRule::ServerDeaf => "server-deaf",
+            ActivityRule::SelfMute => "self-mute",
+            ActivityRule::Solo => "solo",
+            ActivityRule::BotsOnly => "bots-only",
+        }
+    }
+}
+
+/// Which members in a regular voice channel don't get credited. Everyone does by default.
+/// The AFK channel is never affected, time there always counts as AFK time.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ActivityRules {
+    pub self_deaf: bool,
+    pub server_deaf: bool,
+    pub self_mute: bool,
+    /// Nobody else is in the channel.
+    pub solo: bool,
+    /// No other human is in the channel, bots don't count as company.
+    pub bots_only: bool,
+}
+
+impl ActivityRules {
+    pub fn get(&self, rule: ActivityRule) -> bool {
+        match rule {
+            ActivityRule::SelfDeaf => self.self_deaf,
+            ActivityRule::ServerDeaf => self.server_deaf,
+            ActivityRule::SelfMute => self.self_mute,
+            ActivityRule::Solo => self.solo,
+            ActivityRule::BotsOnly => self.bots_only,
+        }
+    }
+
+    pub fn set(&mut self, rule: ActivityRule, enabled: bool) {
+        let flag = match rule {
+            ActivityRule::SelfDeaf => &mut self.self_deaf,
+            ActivityRule::ServerDeaf => &mut self.server_deaf,
+            ActivityRule::SelfMute => &mut self.self_mute,
+            ActivityRule::Solo => &mut self.solo,
+            ActivityRule::BotsOnly => &mut self.bots_only,
+        };
+        *flag = enabled;
+    }
+
+    /// Whether a member with `vs` counts as active, given `others` are the bot flags of everyone
+    /// else in their channel.
+    fn is_active(&self, vs: &VoiceState, others: &[bool]) -> bool {
+        !(self.self_deaf && vs.self_deaf
+            || self.server_deaf && vs.deaf
+            || self.self_mute && vs.self_mute
+            || self.solo && others.is_empty()
+            || self.bots_only && others.iter().all(|is_bot| *is_bot))
+    }
+}
+
+/// Gets a guild's activity rules.
+pub async fn get_rules(data: &Data, guild_id: GuildId) -> Result<ActivityRules> {
+    let cached = {
+        let cache = data.cache.lock().await;
+        cache.get_activity_rules(guild_id).copied()
+    };
+    if let Some(rules) = cached {
+        return Ok(rules);
+    }
+
+    let rules = data.storage.get_activity_rules(guild_id).await?;
+
+    {
+        let mut cache = data.cache.lock().await;
+        cache.insert_activity_rules(guild_id, rules);
+    }
+
+    Ok(rules)
+}
+
+/// Saves a guild's activity rules.
+pub async fn set_rules(data: &Data, guild_id: GuildId, rules: ActivityRules) -> Result<()> {
+    data.storage.set_activity_rules(guild_id, rules).await?;
+
+    {
+        let mut cache = data.cache.lock().await;
+        cache.insert_activity_rules(guild_id, rules);
+    }
+
+    Ok(())
+}
+
+/// Works out where the time of every member in a voice channel of the guild goes.
+/// Members that aren't in the returned map aren't in voice.
+pub fn presences(
+    guild: &Guild,
+    afk_channel: Option<ChannelId>,
+    rules: ActivityRules,
+) -> HashMap<UserId, MemberVoice> {
+    let is_bot = |user_id: &UserId, vs: &VoiceState| {
+        vs.member
+            .as_ref()
+            .or_else(|| guild.members.get(user_id))
+            .is_some_and(|member| member.user.bot)
+    };
+
+    let mut occupants: HashMap<ChannelId, Vec<(UserId, bool)>> = HashMap::new();
+    for (user_id, vs) in guild.voice_states.iter() {
+        if let Some(channel_id) = vs.channel_id {
+            occupants
+                .entry(channel_id)
+                .or_default()
+                .push((*user_id, is_bot(user_id, vs)));
+        }
+    }
+
+    guild
+        .voice_states
+        .iter()
+        .filter_map(|(user_id, vs)| {
+            let channel_id = vs.channel_id?;
+            if Some(channel_id) == afk_channel {
+                let voice = MemberVoice {
+                    presence: Some((ScoreType::Afk, channel_id)),
+                    extras: Extras::default(),
+                };
+                return Some((*user_id, voice));
+            }
+
+            let others = occupants[&channel_id]
+                .iter()
+                .filter(|(other_id, _)| other_id != user_id)
+                .map(|(_, is_bot)| *is_bot)
+                .collect::<Vec<_>>();
+            if !rules.is_active(vs, &others) {
+                return Some((*user_id, MemberVoice::default()));
+            }
+
+            let is_stage = guild
+                .channels
+                .get(&channel_id)
+                .is_some_and(|ch| ch.kind == ChannelType::Stage);
+            let voice = MemberVoice {
+                presence: Some((ScoreType::Voice, channel_id)),
+                extras: Extras::from_voice_state(vs, is_stage),
+            };
+
+            Some((*user_id, voice))
+        })
+        .collect()
+}
@@ -1,17 +1,27 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     sync::Arc,
     time::{Duration, Instant},
 };
 
 use poise::serenity_prelude::GuildId;
 
-use crate::{config::Config, score::Score};
+use crate::{
+    activity::ActivityRules,
+    config::Config,
+    milestone::Milestones,
+    period::Season,
+    score::{GuildUser, Score},
+};
 
 #[derive(Debug, Default)]
 pub struct DataCache {
     configs: HashMap<GuildId, Config>,
     scores: HashMap<GuildId, Cache<Arc<[Score]>>>,
+    seasons: HashMap<GuildId, Option<Season>>,
+    achievements: HashMap<GuildUser, HashSet<String>>,
+    milestones: HashMap<GuildId, Milestones>,
+    activity_rules: HashMap<GuildId, ActivityRules>,
 }
 
 impl DataCache {
@@ -19,10 +29,6 @@ impl DataCache {
         self.configs.get(&guild_id)
     }
 
-    pub fn get_mut_config(&mut self, guild_id: GuildId) -> Option<&mut Config> {
-        self.configs.get_mut(&guild_id)
-    }
-
     pub fn insert_config(&mut self, guild_id: GuildId, config: Config) -> Option<Config> {
         self.configs.insert(guild_id, config)
     }
@@ -39,6 +45,61 @@ impl DataCache {
     pub fn rem_scores(&mut self, guild_id: GuildId) -> Option<Cache<Arc<[Score]>>> {
         self.scores.remove(&guild_id)
     }
+
+    pub fn get_season(&self, guild_id: GuildId) -> Option<&Option<Season>> {
+        self.seasons.get(&guild_id)
+    }
+
+    pub fn insert_season(&mut self, guild_id: GuildId, season: Option<Season>) {
+        self.seasons.insert(guild_id, season);
+    }
+
+    pub fn get_achievements(&self, guild_user: GuildUser) -> Option<&HashSet<String>> {
+        self.achievements.get(&guild_user)
+    }
+
+    pub fn get_mut_achievements(&mut self, guild_user: GuildUser) -> Option<&mut HashSet<String>> {
+        self.achievements.get_mut(&guild_user)
+    }
+
+    pub fn insert_achievements(&mut self, guild_user: GuildUser, achievements: HashSet<String>) {
+        self.achievements.insert(guild_user, achievements);
+    }
+
+    pub fn get_milestones(&self, guild_id: GuildId) -> Option<&Milestones> {
+        self.milestones.get(&guild_id)
+    }
+
+    pub fn insert_milestones(&mut self, guild_id: GuildId, milestones: Milestones) {
+        self.milestones.insert(guild_id, milestones);
+    }
+
+    pub fn rem_milestones(&mut self, guild_id: GuildId) -> Option<Milestones> {
+        self.milestones.remove(&guild_id)
+    }
+
+    pub fn get_activity_rules(&self, guild_id: GuildId) -> Option<&ActivityRules> {
+        self.activity_rules.get(&guild_id)
+    }
+
+    pub fn insert_activity_rules(&mut self, guild_id: GuildId, rules: ActivityRules) {
+        self.activity_rules.insert(guild_id, rules);
+    }
+
+    /// Drops everything cached from a guild's settings, it gets loaded again when next needed.
+    pub fn rem_guild(&mut self, guild_id: GuildId) {
+        self.configs.remove(&guild_id);
+        self.milestones.remove(&guild_id);
+        self.activity_rules.remove(&guild_id);
+    }
+
+    /// Drops every guild's settings and scores.
+    pub fn clear_guilds(&mut self) {
+        self.configs.clear();
+        self.scores.clear();
+        self.milestones.clear();
+        self.activity_rules.clear();
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
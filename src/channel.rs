@@ -0,0 +1,80 @@
+use std::{cmp::Reverse, time::Duration};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use poise::serenity_prelude::{ChannelId, GuildId};
+
+use crate::{score::GuildUser, Data};
+
+#[derive(Debug, Clone, Copy)]
+pub struct ChannelStats {
+    pub channel_id: ChannelId,
+    /// Time every member has spent in the channel, added up.
+    pub time: Duration,
+    pub peak_users: u64,
+    pub peak_at: Option<DateTime<Utc>>,
+}
+
+impl ChannelStats {
+    pub fn new(channel_id: ChannelId) -> Self {
+        ChannelStats {
+            channel_id,
+            time: Duration::ZERO,
+            peak_users: 0,
+            peak_at: None,
+        }
+    }
+}
+
+/// Adds `delta` seconds to the time a member has spent in a channel.
+pub async fn incr_channel_score(
+    data: &Data,
+    guild_user: GuildUser,
+    channel_id: ChannelId,
+    delta: u64,
+) -> Result<()> {
+    data.storage
+        .incr_channel_score(guild_user, channel_id, delta)
+        .await
+}
+
+/// Records how many members are credited in a channel right now, if it's the most ever.
+pub async fn record_peak(
+    data: &Data,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    at: DateTime<Utc>,
+) -> Result<()> {
+    let users = {
+        let voice_state = data.voice_state.lock().await;
+        voice_state
+            .sessions
+            .iter()
+            .filter(|(guild_user, session)| {
+                guild_user.0 == guild_id && session.is_some_and(|s| s.channel_id == channel_id)
+            })
+            .count() as u64
+    };
+
+    data.storage
+        .set_channel_peak(guild_id, channel_id, users, at)
+        .await
+}
+
+/// Gets every channel of a guild that has been used, sorted from the most time spent in it.
+pub async fn get_channel_stats(data: &Data, guild_id: GuildId) -> Result<Vec<ChannelStats>> {
+    let mut stats = data.storage.channel_stats(guild_id).await?;
+    stats.sort_by_key(|s| Reverse(s.time));
+
+    Ok(stats)
+}
+
+/// Gets the channel a member has spent the most time in.
+pub async fn get_favourite_channel(
+    data: &Data,
+    guild_user: GuildUser,
+) -> Result<Option<(ChannelId, Duration)>> {
+    let times = data.storage.channel_times(guild_user).await?;
+
+    Ok(times.into_iter().max_by_key(|(_, time)| *time))
+}
@@ -0,0 +1,57 @@
+use poise::{
+    serenity_prelude::{CreateEmbed, Member},
+    CreateReply,
+};
+
+use crate::{achievement::CATALOGUE, score::GuildUser, Context, Error};
+
+/// Show the achievements a member has unlocked.
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn achievements(
+    ctx: Context<'_>,
+    #[description = "Member to look up, defaults to you"] member: Option<Member>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let user = match member {
+        Some(member) => member.user,
+        None => ctx.author().clone(),
+    };
+
+    let unlocked = ctx
+        .data()
+        .storage
+        .achievements(GuildUser(guild_id, user.id))
+        .await?;
+
+    let lines = CATALOGUE
+        .iter()
+        .map(|achievement| {
+            match unlocked
+                .iter()
+                .find(|unlocked| unlocked.achievement == achievement.id)
+            {
+                Some(unlocked) => format!(
+                    "✅ **{}**: {}, unlocked <t:{}:D>",
+                    achievement.name,
+                    achievement.description,
+                    unlocked.unlocked_at.timestamp()
+                ),
+                None => format!("🔒 **{}**: {}", achievement.name, achievement.description),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let embed = CreateEmbed::new()
+        .title(format!(
+            "{}'s achievements ({}/{})",
+            user.name,
+            unlocked.len(),
+            CATALOGUE.len()
+        ))
+        .description(lines);
+
+    ctx.send(CreateReply::default().embed(embed)).await?;
+
+    Ok(())
+}
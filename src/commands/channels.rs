@@ -0,0 +1,57 @@
+use poise::CreateReply;
+
+use crate::{channel, Context, Error};
+
+const MAX_CHANNELS: usize = 20;
+
+/// Voice channel usage. Enter subcommand `stats`.
+#[poise::command(slash_command, prefix_command, guild_only, subcommands("stats"))]
+pub async fn channels(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.say("Enter subcommand `stats`").await?;
+
+    Ok(())
+}
+
+/// Ranks voice channels by the time members have spent in them.
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn stats(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.defer().await?;
+
+    let guild_id = ctx.guild_id().unwrap();
+    let stats = channel::get_channel_stats(ctx.data(), guild_id).await?;
+
+    let content = if stats.is_empty() {
+        "Nobody has been in voice yet.".to_string()
+    } else {
+        let lines = stats
+            .iter()
+            .take(MAX_CHANNELS)
+            .enumerate()
+            .map(|(i, stat)| {
+                let hours = stat.time.as_secs_f64() / 3600.0;
+                let peak = match stat.peak_at {
+                    Some(peak_at) => format!(
+                        "peak of {} on <t:{}:D>",
+                        stat.peak_users,
+                        peak_at.timestamp()
+                    ),
+                    None => "no peak yet".to_string(),
+                };
+                format!(
+                    "{}. <#{}> - {:.1} member-hours, {}",
+                    i + 1,
+                    stat.channel_id,
+                    hours,
+                    peak
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        format!("**Voice channels by member-hours**\n{}", lines)
+    };
+
+    ctx.send(CreateReply::default().content(content)).await?;
+
+    Ok(())
+}
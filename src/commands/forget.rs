@@ -0,0 +1,42 @@
+use poise::CreateReply;
+
+use crate::{score::GuildUser, Context, Error};
+
+/// Erase your score, history and achievements in this server.
+#[poise::command(slash_command, prefix_command, guild_only, ephemeral)]
+pub async fn forget(
+    ctx: Context<'_>,
+    #[description = "Type `yes` to confirm, this can't be undone"] confirm: String,
+) -> Result<(), Error> {
+    if !confirm.eq_ignore_ascii_case("yes") {
+        ctx.say("Nothing was erased, type `yes` to confirm.").await?;
+        return Ok(());
+    }
+
+    let member = GuildUser(ctx.guild_id().unwrap(), ctx.author().id);
+    ctx.defer_ephemeral().await?;
+
+    // Whatever failed to be written would be replayed after the delete, and bring it back.
+    if let Some(outbox) = &ctx.data().outbox {
+        outbox.purge(member).await?;
+    }
+    ctx.data().storage.delete_score(member).await?;
+    ctx.data().storage.forget_member(member).await?;
+
+    {
+        let mut cache = ctx.data().cache.lock().await;
+        cache.rem_scores(member.0);
+        if let Some(achievements) = cache.get_mut_achievements(member) {
+            achievements.clear();
+        }
+    }
+
+    ctx.send(CreateReply {
+        content: Some("Done, everything about you in this server is gone.".to_string()),
+        ephemeral: Some(true),
+        ..Default::default()
+    })
+    .await?;
+
+    Ok(())
+}
@@ -1,20 +1,21 @@
-use std::{sync::Arc, time::Instant};
+use std::sync::Arc;
 
+use chrono::{DateTime, Utc};
 use poise::serenity_prelude::{Cache, ChannelType, GuildChannel, GuildId, Http};
-use tokio::{sync::oneshot, task::JoinSet};
+use tokio::task::JoinSet;
 
 use crate::{
-    config::Config,
-    event::{go_out_and_in_afk, go_out_and_in_voice},
-    pocketbase as pb,
-    score::GuildUser,
+    activity,
+    config::Configs,
+    event::{apply, flush_afk, flush_voice},
+    score::{GuildUser, ScoreType},
     Context, Data, Error,
 };
 
 /// Records everyone's score, NOW.
 #[poise::command(prefix_command, owners_only)]
 pub async fn gtfo(ctx: Context<'_>) -> Result<(), Error> {
-    let now = Instant::now();
+    let now = Utc::now();
     let http = ctx.serenity_context().http.clone();
     let cache = ctx.serenity_context().cache.clone();
 
@@ -27,7 +28,7 @@ pub async fn score_update(
     data: Data,
     http: Arc<Http>,
     cache: Arc<Cache>,
-    now: Instant,
+    now: DateTime<Utc>,
 ) -> Result<(), Error> {
     let guild_infos = http.get_guilds(None, None).await?;
     let guild_ids: Vec<GuildId> = guild_infos.iter().map(|g| g.id).collect();
@@ -35,14 +36,7 @@ pub async fn score_update(
     let mut handles = JoinSet::new();
     for guild_id in guild_ids.into_iter() {
         let http = http.clone();
-        handles.spawn(async move {
-            let channels = guild_id.channels(http).await?;
-            let voice_channels = channels
-                .into_values()
-                .filter(|ch| ch.kind == ChannelType::Voice)
-                .collect::<Vec<GuildChannel>>();
-            Ok::<_, Error>(voice_channels)
-        });
+        handles.spawn(async move { voice_channels(&http, guild_id).await });
     }
 
     let mut guild_vcs = Vec::new();
@@ -52,48 +46,73 @@ pub async fn score_update(
     }
 
     for ch in guild_vcs.iter() {
-        let mems = ch
-            .members(cache.clone())?
-            .iter()
-            .map(|mem| GuildUser(mem.guild_id, mem.user.id))
-            .collect::<Vec<_>>();
-
-        for mem in mems.iter() {
-            let config = {
-                let cache = data.cache.lock().await;
-                cache.get_config(mem.0).cloned()
-            };
-
-            let config = match config {
-                Some(config) => config,
-                None => {
-                    let tx = data.tx.clone();
-                    let (resp_tx, resp_rx) = oneshot::channel();
-                    let cmd = pb::Command::new_get_config(mem.0, resp_tx);
-                    tx.send(cmd).await?;
-
-                    let guild_rec = resp_rx.await??;
-                    let config = Config::new(
-                        guild_rec.graveyard.parse::<u64>().ok(),
-                        guild_rec.afk_channel.parse::<u64>().ok(),
-                    );
-
-                    {
-                        let mut cache = data.cache.lock().await;
-                        cache.insert_config(mem.0, config);
-                    }
-
-                    config
-                }
-            };
-
-            if let Some(afk_ch) = config.afk_channel {
-                if ch.id == afk_ch {
-                    go_out_and_in_afk(&data, mem.0, mem.1, now).await?;
-                    continue;
-                }
+        channel_score_update(&data, &cache, ch, now).await?;
+    }
+
+    Ok(())
+}
+
+/// Records the score of everyone in one guild's voice channels, like `score_update`.
+pub async fn guild_score_update(
+    data: &Data,
+    http: &Http,
+    cache: &Arc<Cache>,
+    guild_id: GuildId,
+    now: DateTime<Utc>,
+) -> Result<(), Error> {
+    for ch in voice_channels(http, guild_id).await?.iter() {
+        channel_score_update(data, cache, ch, now).await?;
+    }
+
+    Ok(())
+}
+
+async fn voice_channels(http: &Http, guild_id: GuildId) -> Result<Vec<GuildChannel>, Error> {
+    let channels = guild_id.channels(http).await?;
+    let voice_channels = channels
+        .into_values()
+        .filter(|ch| matches!(ch.kind, ChannelType::Voice | ChannelType::Stage))
+        .collect::<Vec<GuildChannel>>();
+
+    Ok(voice_channels)
+}
+
+async fn channel_score_update(
+    data: &Data,
+    cache: &Arc<Cache>,
+    ch: &GuildChannel,
+    now: DateTime<Utc>,
+) -> Result<(), Error> {
+    let config = {
+        let cache = data.cache.lock().await;
+        cache.get_config(ch.guild_id).cloned()
+    };
+
+    let config = match config {
+        Some(config) => config,
+        None => Configs::get_guild_config(data, ch.guild_id).await?,
+    };
+    let rules = activity::get_rules(data, ch.guild_id).await?;
+
+    let voices = match cache.guild(ch.guild_id) {
+        Some(guild) => activity::presences(&guild, config.afk_channel, rules),
+        None => return Ok(()),
+    };
+    let mems = ch
+        .members(cache.clone())?
+        .iter()
+        .map(|mem| GuildUser(mem.guild_id, mem.user.id))
+        .collect::<Vec<_>>();
+
+    for mem in mems.iter() {
+        let voice = voices.get(&mem.1).copied().unwrap_or_default();
+        match voice.presence {
+            Some((ScoreType::Afk, _)) => flush_afk(data, mem.0, mem.1, ch.id, now).await?,
+            Some((ScoreType::Voice, _)) => {
+                flush_voice(data, mem.0, mem.1, ch.id, voice.extras, now).await?
             }
-            go_out_and_in_voice(&data, mem.0, mem.1, now).await?;
+            // Time spent inactive isn't credited.
+            None => apply(data, *mem, voice, now).await?,
         }
     }
 
@@ -1,9 +1,8 @@
-use std::time::Duration;
-
 use poise::{serenity_prelude::Member, CreateReply};
 
 use crate::{
-    score::{GuildUser, Scores},
+    activity::Extras,
+    score::{GuildUser, ScoreType},
     Context, Error,
 };
 
@@ -16,13 +15,27 @@ pub async fn incr_score(
     #[rest]
     duration: String,
 ) -> Result<(), Error> {
-    let db = ctx.data().db.clone();
     let duration = humantime::parse_duration(duration.as_str())?;
     let dur_secs = duration.as_secs();
     let guild_user = GuildUser(member.guild_id, member.user.id);
 
-    let after = Scores::incr_score(db, guild_user, dur_secs).await?;
-    let after = Duration::from_secs(after);
+    let score = ctx
+        .data()
+        .storage
+        .incr_score(
+            guild_user,
+            ScoreType::Voice,
+            dur_secs,
+            None,
+            Extras::default(),
+        )
+        .await?;
+    let after = score.score;
+
+    {
+        let mut cache = ctx.data().cache.lock().await;
+        cache.rem_scores(guild_user.0);
+    }
 
     ctx.send(CreateReply {
         content: Some(format!(
@@ -0,0 +1,29 @@
+use poise::CreateReply;
+
+use crate::{migration, Context, Error};
+
+/// Moves scores and configs left in redis to the storage. Only a dry run unless told otherwise.
+#[poise::command(slash_command, prefix_command, owners_only)]
+pub async fn migrate(
+    ctx: Context<'_>,
+    #[description = "Only report what would be moved, defaults to true"] dry_run: Option<bool>,
+) -> Result<(), Error> {
+    let dry_run = dry_run.unwrap_or(true);
+    ctx.defer_ephemeral().await?;
+
+    let report = migration::migrate_redis(ctx.data(), dry_run).await?;
+    let content = if dry_run {
+        format!("Dry run, nothing was written.\n```{report}```")
+    } else {
+        format!("Migrated.\n```{report}```")
+    };
+
+    ctx.send(CreateReply {
+        content: Some(content),
+        ephemeral: Some(true),
+        ..Default::default()
+    })
+    .await?;
+
+    Ok(())
+}
@@ -6,16 +6,24 @@ macro_rules! re_export {
 }
 
 // Public commands
+re_export!(achievements);
+re_export!(channels);
+re_export!(forget);
 re_export!(graveyard);
 re_export!(hello);
+re_export!(profile);
 re_export!(rank);
 re_export!(set_afk_channel);
 
 // Admins only commands
+re_export!(season);
 re_export!(settings);
 
 // Owners only commands
 re_export!(gtfo);
 re_export!(incr_score);
+re_export!(migrate);
+re_export!(outbox);
+re_export!(pb_stats);
 re_export!(voice_state);
 re_export!(register);
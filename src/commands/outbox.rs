@@ -0,0 +1,97 @@
+use crate::{outbox, Context, Error};
+
+/// How many characters of entries `show` lists at most.
+const SHOW_LIMIT: usize = 1900;
+/// How many characters of an entry's last error `show` lists, so a single entry always fits.
+const ERROR_LIMIT: usize = 300;
+
+/// Score increments pocketbase failed, waiting to be written again.
+#[poise::command(
+    prefix_command,
+    owners_only,
+    subcommands("outbox_show", "outbox_replay", "outbox_drop")
+)]
+pub async fn outbox(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.say("Enter subcommand `show`, `replay` or `drop`")
+        .await?;
+    Ok(())
+}
+
+#[poise::command(prefix_command, owners_only, rename = "show")]
+pub async fn outbox_show(ctx: Context<'_>) -> Result<(), Error> {
+    let outbox = ctx.data().outbox()?;
+    let entries = outbox.list().await?;
+    if entries.is_empty() {
+        ctx.say("The outbox is empty").await?;
+        return Ok(());
+    }
+
+    // Kept under Discord's 2000 characters, with room for the code block and the rest count.
+    let mut text = String::new();
+    let mut shown = 0;
+    for entry in &entries {
+        let member = entry.increment.member();
+        let claimed = outbox.is_claimed(&entry.id).await?;
+        let last_error = match &entry.last_error {
+            Some(err) => err.chars().take(ERROR_LIMIT).collect(),
+            None => "none".to_string(),
+        };
+        let line = format!(
+            "{} <@{}> in {}: {}\n  {} attempts{}, last error: {}\n",
+            entry.id,
+            member.1,
+            member.0,
+            entry.increment,
+            entry.attempts,
+            if claimed { ", being written" } else { "" },
+            last_error,
+        );
+        if text.chars().count() + line.chars().count() > SHOW_LIMIT {
+            break;
+        }
+        text.push_str(&line);
+        shown += 1;
+    }
+
+    let mut content = format!("```{text}```");
+    if shown < entries.len() {
+        content.push_str(&format!("…and {} more", entries.len() - shown));
+    }
+    ctx.say(content).await?;
+    Ok(())
+}
+
+/// Writes again one entry, or every entry nobody is writing right now.
+#[poise::command(prefix_command, owners_only, rename = "replay")]
+pub async fn outbox_replay(ctx: Context<'_>, id: Option<String>) -> Result<(), Error> {
+    let data = ctx.data();
+
+    let Some(id) = id else {
+        let replayed = outbox::replay(data).await?;
+        ctx.say(replayed.to_string()).await?;
+        return Ok(());
+    };
+
+    let Some(entry) = data.outbox()?.get(&id).await? else {
+        ctx.say(format!("No outbox entry `{id}`")).await?;
+        return Ok(());
+    };
+    match outbox::replay_entry(data, entry).await {
+        Ok(true) => ctx.say(format!("`{id}` written")).await?,
+        Ok(false) => ctx.say(format!("`{id}` is being written already")).await?,
+        Err(err) => ctx.say(format!("`{id}` failed again: {err}")).await?,
+    };
+
+    Ok(())
+}
+
+/// Gives up on an entry, its time is never written.
+#[poise::command(prefix_command, owners_only, rename = "drop")]
+pub async fn outbox_drop(ctx: Context<'_>, id: String) -> Result<(), Error> {
+    if ctx.data().outbox()?.remove(&id).await? {
+        ctx.say(format!("`{id}` dropped")).await?;
+    } else {
+        ctx.say(format!("No outbox entry `{id}`")).await?;
+    }
+    Ok(())
+}
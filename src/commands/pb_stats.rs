@@ -0,0 +1,28 @@
+use crate::{Context, Error};
+
+/// Shows how the pocketbase manager keeps up with the commands sent to it.
+#[poise::command(prefix_command, owners_only)]
+pub async fn pb_stats(ctx: Context<'_>) -> Result<(), Error> {
+    let data = ctx.data();
+    let metrics = &data.pb_metrics;
+    let tx = data.tx()?;
+    let queued = tx.max_capacity() - tx.capacity();
+
+    ctx.say(format!(
+        "```Queued: {} in the channel, {} waiting\n\
+        In flight: {}\n\
+        Handled: {}, {} on average, {} at most\n\
+        Buffered: {} score increments, {} scores waiting for the next flush```",
+        queued,
+        metrics.waiting(),
+        metrics.in_flight(),
+        metrics.handled(),
+        humantime::format_duration(metrics.mean_latency()),
+        humantime::format_duration(metrics.max_latency()),
+        metrics.buffered(),
+        metrics.pending(),
+    ))
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,112 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use poise::{
+    serenity_prelude::{CreateEmbed, Member},
+    CreateReply,
+};
+
+use crate::{
+    achievement::{self, CATALOGUE},
+    channel,
+    score::GuildUser,
+    Context, Error,
+};
+
+/// Show a member's voice stats.
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn profile(
+    ctx: Context<'_>,
+    #[description = "Member to look up, defaults to you"] member: Option<Member>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let user = match member {
+        Some(member) => member.user,
+        None => ctx.author().clone(),
+    };
+
+    let scores = ctx.data().storage.leaderboard(guild_id).await?;
+    let Some(position) = scores.iter().position(|score| score.user_id == user.id) else {
+        ctx.say(format!("{} hasn't been in voice yet.", user.name))
+            .await?;
+        return Ok(());
+    };
+    let score = &scores[position];
+
+    let rank = position + 1;
+    let top_percent = rank as f64 / scores.len() as f64 * 100.0;
+
+    let current_session = {
+        let voice_state = ctx.data().voice_state.lock().await;
+        voice_state
+            .sessions
+            .get(&GuildUser(guild_id, user.id))
+            .cloned()
+            .flatten()
+    };
+    let current_session = match current_session {
+        Some(session) => {
+            let length = (Utc::now() - session.joined_at).num_seconds().max(0) as u64;
+            format!(
+                "{} in <#{}>",
+                humantime::format_duration(Duration::from_secs(length)),
+                session.channel_id
+            )
+        }
+        None => "Not in voice".to_string(),
+    };
+
+    let first_seen = match ctx.data().storage.first_seen(user.id).await? {
+        Some(first_seen) => format!("<t:{}:D>", first_seen.timestamp()),
+        None => "Unknown".to_string(),
+    };
+
+    let favourite_channel =
+        match channel::get_favourite_channel(ctx.data(), GuildUser(guild_id, user.id)).await? {
+            Some((channel_id, time)) => format!(
+                "<#{}> ({})",
+                channel_id,
+                humantime::format_duration(time)
+            ),
+            None => "None yet".to_string(),
+        };
+
+    let unlocked = achievement::get_unlocked(ctx.data(), GuildUser(guild_id, user.id)).await?;
+    let achievements = CATALOGUE
+        .iter()
+        .filter(|achievement| unlocked.contains(achievement.id))
+        .map(|achievement| achievement.name)
+        .collect::<Vec<_>>();
+    let achievements = if achievements.is_empty() {
+        "None yet".to_string()
+    } else {
+        format!(
+            "{} ({}/{})",
+            achievements.join(", "),
+            achievements.len(),
+            CATALOGUE.len()
+        )
+    };
+
+    let format_time = |time: Duration| humantime::format_duration(time).to_string();
+
+    let embed = CreateEmbed::new()
+        .title(format!("{}'s profile", user.name))
+        .thumbnail(user.face())
+        .field("Voice time", format_time(score.score), true)
+        .field("AFK time", format_time(score.afk_score), true)
+        .field(
+            "Rank",
+            format!("#{} of {} (top {:.0}%)", rank, scores.len(), top_percent.ceil()),
+            true,
+        )
+        .field("Current session", current_session, true)
+        .field("Longest session", format_time(score.longest_session), true)
+        .field("First seen in any server", first_seen, true)
+        .field("Favourite channel", favourite_channel, true)
+        .field("Achievements", achievements, false);
+
+    ctx.send(CreateReply::default().embed(embed)).await?;
+
+    Ok(())
+}
@@ -1,62 +1,325 @@
+use std::time::Duration;
+
+use chrono::Utc;
 use comfy_table::{modifiers::UTF8_ROUND_CORNERS, presets::UTF8_FULL_CONDENSED, Table};
-use poise::CreateReply;
+use poise::{
+    serenity_prelude::{
+        ButtonStyle, ComponentInteractionCollector, CreateActionRow, CreateAttachment,
+        CreateButton, CreateInteractionResponse, CreateInteractionResponseMessage,
+    },
+    CreateReply,
+};
+
+use crate::{
+    period::{self, Period},
+    render::{render_leaderboard, LeaderboardRow},
+    score::{RankKind, Score, Scores},
+    user::Username,
+    Context, Error,
+};
+
+const DEFAULT_PAGE_SIZE: usize = 10;
 
-use crate::{score::Scores, user::Username, Context, Error};
+/// How long the page buttons keep working after the last press.
+const PAGINATION_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// How the leaderboard is drawn.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum RankFormat {
+    #[default]
+    #[name = "text"]
+    Text,
+    #[name = "image"]
+    Image,
+}
 
 /// Display voice time leaderboard
 #[poise::command(slash_command, prefix_command, guild_only)]
-pub async fn rank(ctx: Context<'_>) -> Result<(), Error> {
-    let msg = ctx.say("Calculating...").await?;
+pub async fn rank(
+    ctx: Context<'_>,
+    #[description = "Time window of the leaderboard, defaults to all time"] period: Option<
+        Period,
+    >,
+    #[description = "What to rank members by, defaults to voice time"] kind: Option<RankKind>,
+    #[description = "Members per page, defaults to 10"]
+    #[min = 5]
+    #[max = 25]
+    page_size: Option<u8>,
+    #[description = "Show the leaderboard as text or as an image, defaults to text"]
+    format: Option<RankFormat>,
+) -> Result<(), Error> {
+    // Attachments can't be added by editing a response, so defer instead of
+    // sending a placeholder message and editing it later.
+    ctx.defer().await?;
 
     let guild_id = ctx.guild_id().unwrap();
     let cache = ctx.data().cache.clone();
+    let period = period.unwrap_or_default();
+    let kind = kind.unwrap_or_default();
+    let format = format.unwrap_or_default();
+    let now = Utc::now();
+
+    if period != Period::All && kind.is_all_time_only() {
+        ctx.say(format!(
+            "{} only has an all time leaderboard!",
+            kind.header()
+        ))
+        .await?;
+        return Ok(());
+    }
+
+    let (title_suffix, scores) = match period {
+        Period::All => {
+            let cached_scores = {
+                let score_cache = cache.lock().await;
+                let cached_scores = score_cache.get_scores(guild_id);
+                match cached_scores {
+                    Some(cache) => cache.get_cloned(),
+                    None => None,
+                }
+            };
+
+            let scores = match cached_scores {
+                Some(s) => s,
+                None => Scores::get_all_score(ctx.data(), guild_id).await?,
+            };
+
+            (String::new(), scores)
+        }
+        Period::Season => {
+            let Some(season) = period::get_active_season(ctx.data(), guild_id).await? else {
+                ctx.say("There is no season running right now!").await?;
+                return Ok(());
+            };
+
+            let scores =
+                period::get_period_scores(ctx.data(), guild_id, period, season.id).await?;
 
-    let cached_scores = {
-        let score_cache = cache.lock().await;
-        let cached_scores = score_cache.get_scores(guild_id);
-        match cached_scores {
-            Some(cache) => cache.get_cloned(),
-            None => None,
+            (format!(" of {}", season.name), scores)
+        }
+        Period::Day | Period::Week | Period::Month => {
+            let bucket = period.calendar_bucket(now).unwrap();
+            let scores = period::get_period_scores(ctx.data(), guild_id, period, bucket).await?;
+            let title_suffix = match period {
+                Period::Day => " of Today",
+                Period::Week => " of This Week",
+                _ => " of This Month",
+            };
+
+            (title_suffix.to_string(), scores)
         }
     };
+    let title = format!("{} Ranking{}", kind.title(), title_suffix);
+
+    let mut scores = scores.to_vec();
+    kind.sort(&mut scores);
 
-    let scores = match cached_scores {
-        Some(s) => s,
-        None => Scores::get_all_score(ctx, guild_id).await?,
+    let page_size = page_size.map(usize::from).unwrap_or(DEFAULT_PAGE_SIZE);
+    let last_page = scores.len().saturating_sub(1) / page_size;
+    let mut page = 0;
+
+    let rendered = render_page(ctx, &title, kind, format, &scores, page, page_size).await?;
+
+    // Everything fits in a single page, no need for buttons.
+    if last_page == 0 {
+        ctx.send(rendered.reply()).await?;
+        return Ok(());
+    }
+
+    let ctx_id = ctx.id();
+    let button_ids = PageButtons {
+        first: format!("{ctx_id}first"),
+        prev: format!("{ctx_id}prev"),
+        next: format!("{ctx_id}next"),
+        last: format!("{ctx_id}last"),
+        me: format!("{ctx_id}me"),
     };
 
-    let mut table = Table::new();
-    table
-        .load_preset(UTF8_FULL_CONDENSED)
-        .apply_modifier(UTF8_ROUND_CORNERS)
-        .set_content_arrangement(comfy_table::ContentArrangement::Dynamic)
-        .set_header(vec!["Rank", "Username", "Voice Time"]);
-
-    for (i, score) in scores.iter().enumerate() {
-        table.add_row([
-            (i + 1).to_string(),
-            Username::from_user_id(ctx, score.user_id)
-                .await?
-                .to_string(),
-            humantime::format_duration(score.score).to_string(),
-        ]);
+    let msg = ctx
+        .send(
+            rendered
+                .reply()
+                .components(button_ids.components(page, last_page)),
+        )
+        .await?;
+
+    while let Some(press) = ComponentInteractionCollector::new(ctx)
+        .filter(move |press| press.data.custom_id.starts_with(&ctx_id.to_string()))
+        .timeout(PAGINATION_TIMEOUT)
+        .await
+    {
+        let custom_id = press.data.custom_id.as_str();
+        page = if custom_id == button_ids.first {
+            0
+        } else if custom_id == button_ids.prev {
+            page.saturating_sub(1)
+        } else if custom_id == button_ids.next {
+            (page + 1).min(last_page)
+        } else if custom_id == button_ids.last {
+            last_page
+        } else if custom_id == button_ids.me {
+            match scores.iter().position(|s| s.user_id == press.user.id) {
+                Some(i) => i / page_size,
+                None => {
+                    press
+                        .create_response(
+                            ctx,
+                            CreateInteractionResponse::Message(
+                                CreateInteractionResponseMessage::new()
+                                    .content("You are not on this leaderboard yet!")
+                                    .ephemeral(true),
+                            ),
+                        )
+                        .await?;
+                    continue;
+                }
+            }
+        } else {
+            continue;
+        };
+
+        let rendered = render_page(ctx, &title, kind, format, &scores, page, page_size).await?;
+        press
+            .create_response(
+                ctx,
+                CreateInteractionResponse::UpdateMessage(
+                    rendered
+                        .response()
+                        .components(button_ids.components(page, last_page)),
+                ),
+            )
+            .await?;
     }
 
-    let content = format!(
-        "```md\n\
-        Voice Chat Total Time Ranking\n\
-        =============================\n\
-        > Top global penghuni voice chat.``````{}```",
-        table
-    );
-    msg.edit(
-        ctx,
-        CreateReply {
-            content: Some(content),
-            ..Default::default()
-        },
-    )
-    .await?;
+    // Nobody is pressing the buttons anymore, take them away.
+    msg.edit(ctx, CreateReply::default().components(Vec::new()))
+        .await?;
 
     Ok(())
 }
+
+struct PageButtons {
+    first: String,
+    prev: String,
+    next: String,
+    last: String,
+    me: String,
+}
+
+impl PageButtons {
+    fn components(&self, page: usize, last_page: usize) -> Vec<CreateActionRow> {
+        vec![CreateActionRow::Buttons(vec![
+            CreateButton::new(&self.first)
+                .emoji('⏮')
+                .disabled(page == 0),
+            CreateButton::new(&self.prev)
+                .emoji('◀')
+                .disabled(page == 0),
+            CreateButton::new(&self.next)
+                .emoji('▶')
+                .disabled(page == last_page),
+            CreateButton::new(&self.last)
+                .emoji('⏭')
+                .disabled(page == last_page),
+            CreateButton::new(&self.me)
+                .label("Me")
+                .style(ButtonStyle::Secondary),
+        ])]
+    }
+}
+
+struct RenderedPage {
+    content: String,
+    image: Option<CreateAttachment>,
+}
+
+impl RenderedPage {
+    fn reply(self) -> CreateReply {
+        let reply = CreateReply::default().content(self.content);
+        match self.image {
+            Some(image) => reply.attachment(image),
+            None => reply,
+        }
+    }
+
+    fn response(self) -> CreateInteractionResponseMessage {
+        let response = CreateInteractionResponseMessage::new().content(self.content);
+        match self.image {
+            Some(image) => response.files([image]),
+            None => response,
+        }
+    }
+}
+
+async fn render_page(
+    ctx: Context<'_>,
+    title: &str,
+    kind: RankKind,
+    format: RankFormat,
+    scores: &[Score],
+    page: usize,
+    page_size: usize,
+) -> Result<RenderedPage, Error> {
+    let offset = page * page_size;
+    let last_page = scores.len().saturating_sub(1) / page_size;
+    let page_scores = scores.iter().enumerate().skip(offset).take(page_size);
+
+    match format {
+        RankFormat::Text => {
+            let mut table = Table::new();
+            table
+                .load_preset(UTF8_FULL_CONDENSED)
+                .apply_modifier(UTF8_ROUND_CORNERS)
+                .set_content_arrangement(comfy_table::ContentArrangement::Dynamic)
+                .set_header(vec!["Rank", "Username", kind.header()]);
+
+            for (i, score) in page_scores {
+                table.add_row([
+                    (i + 1).to_string(),
+                    Username::from_user_id(ctx, score.user_id)
+                        .await?
+                        .to_string(),
+                    kind.format(score),
+                ]);
+            }
+
+            let content = format!(
+                "```md\n\
+                {}\n\
+                {}\n\
+                > Top global penghuni voice chat.``````{}```\n\
+                Page {}/{}",
+                title,
+                "=".repeat(title.chars().count()),
+                table,
+                page + 1,
+                last_page + 1,
+            );
+
+            Ok(RenderedPage {
+                content,
+                image: None,
+            })
+        }
+        RankFormat::Image => {
+            let mut rows = Vec::with_capacity(page_size);
+            for (i, score) in page_scores {
+                rows.push(LeaderboardRow {
+                    rank: i + 1,
+                    user_id: score.user_id,
+                    name: Username::display_name_from_user_id(ctx, score.user_id)
+                        .await?
+                        .to_string(),
+                    value: kind.format(score),
+                });
+            }
+
+            let png = render_leaderboard(title, kind.header(), &rows)?;
+
+            Ok(RenderedPage {
+                content: format!("Page {}/{}", page + 1, last_page + 1),
+                image: Some(CreateAttachment::bytes(png, "leaderboard.png")),
+            })
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use chrono::Utc;
+
+use crate::{commands::guild_score_update, Context, Error};
+
+/// Manage seasonal leaderboards. You need Manage Guild perm to run this command.
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    subcommands("start", "end")
+)]
+pub async fn season(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.say("Enter subcommand `start` or `end`").await?;
+
+    Ok(())
+}
+
+/// Starts a new named season. Only one season can run at a time.
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn start(
+    ctx: Context<'_>,
+    #[description = "Name of the season"]
+    #[rest]
+    name: String,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+
+    let season = ctx
+        .data()
+        .storage
+        .start_season(guild_id, name, Utc::now())
+        .await?;
+
+    {
+        let mut cache = ctx.data().cache.lock().await;
+        cache.insert_season(guild_id, Some(season.clone()));
+    }
+
+    ctx.say(format!(
+        "Season **{}** has started, may the longest voice chatter win!",
+        season.name
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Ends the running season and archives its final standings.
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn end(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let now = Utc::now();
+
+    // The time of members still in voice belongs to the season that is ending.
+    let serenity_ctx = ctx.serenity_context();
+    guild_score_update(
+        ctx.data(),
+        &serenity_ctx.http,
+        &serenity_ctx.cache,
+        guild_id,
+        now,
+    )
+    .await?;
+
+    let season = ctx.data().storage.end_season(guild_id, now).await?;
+
+    {
+        let mut cache = ctx.data().cache.lock().await;
+        cache.insert_season(guild_id, None);
+    }
+
+    let podium = season
+        .standings
+        .iter()
+        .take(10)
+        .enumerate()
+        .map(|(i, score)| {
+            format!(
+                "{}. <@{}> - {}",
+                i + 1,
+                score.user_id,
+                humantime::format_duration(score.score)
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let content = if podium.is_empty() {
+        format!(
+            "Season **{}** has ended, and nobody was in voice. Sad.",
+            season.name
+        )
+    } else {
+        format!(
+            "Season **{}** has ended! Final standings:\n{}",
+            season.name,
+            podium.join("\n")
+        )
+    };
+    ctx.say(content).await?;
+
+    Ok(())
+}
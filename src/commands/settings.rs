@@ -1,8 +1,12 @@
-use poise::serenity_prelude::ChannelId;
-use tokio::sync::oneshot;
+use poise::serenity_prelude::{ChannelId, Role};
 use tracing::error;
 
-use crate::{pocketbase as pb, Context, Error};
+use crate::{
+    activity::{self, ActivityRule},
+    config::Configs,
+    milestone::{self, Milestone, MilestoneMode},
+    Context, Error,
+};
 
 /// Manage bot settings. You need Manage Guild perm to run this command.
 #[poise::command(
@@ -10,11 +14,23 @@ use crate::{pocketbase as pb, Context, Error};
     prefix_command,
     guild_only,
     required_permissions = "MANAGE_GUILD",
-    subcommands("set_graveyard", "set_afkchannel")
+    subcommands(
+        "set_graveyard",
+        "set_afkchannel",
+        "set_achievementchannel",
+        "set_milestone",
+        "remove_milestone",
+        "milestones",
+        "set_milestonemode",
+        "set_activityrule"
+    )
 )]
 pub async fn settings(ctx: Context<'_>) -> Result<(), Error> {
-    ctx.say("Enter subcommand `graveyard` or `afkchannel`")
-        .await?;
+    ctx.say(
+        "Enter subcommand `graveyard`, `afkchannel`, `achievementchannel`, \
+        `milestone`, `removemilestone`, `milestones`, `milestonemode` or `activityrule`",
+    )
+    .await?;
 
     Ok(())
 }
@@ -43,18 +59,11 @@ pub async fn set_graveyard(
                     if cat.guild_id != guild_id {
                         ctx.say("Bro that channel category is in a different server.")
                             .await?;
+                        return Ok(());
                     }
 
-                    let (resp_tx, resp_rx) = oneshot::channel();
-                    let tx = ctx.data().tx.clone();
-                    tx.send(pb::Command::new_set_config(
-                        guild_id,
-                        None,
-                        Some(cat.id),
-                        resp_tx,
-                    ))
-                    .await?;
-                    let _ = resp_rx.await??;
+                    Configs::set_config(ctx.data(), guild_id, None, Some(cat.id), None)
+                        .await?;
 
                     ctx.say(format!(
                         "Ok cool, graveyard category has been set to <#{}>",
@@ -105,21 +114,68 @@ pub async fn set_afkchannel(
                     if ch.guild_id != guild_id {
                         ctx.say("Bro that channel is in a different server.")
                             .await?;
+                        return Ok(());
                     }
 
-                    let (resp_tx, resp_rx) = oneshot::channel();
-                    let tx = ctx.data().tx.clone();
-                    tx.send(pb::Command::new_set_config(
-                        guild_id,
-                        Some(ch.id),
-                        None,
-                        resp_tx,
+                    Configs::set_config(ctx.data(), guild_id, Some(ch.id), None, None)
+                        .await?;
+
+                    ctx.say(format!(
+                        "Done! <#{}> has been set as this server's AFK channel",
+                        channel_id
+                    ))
+                    .await?;
+                }
+                None => {
+                    ctx.say(format!(
+                        "{} is not a valid channel id, or is not a channel in a server.",
+                        channel_id.get(),
                     ))
                     .await?;
-                    let _ = resp_rx.await??;
+                }
+            }
+        }
+        Err(err) => {
+            error!("{}", err);
+            ctx.say("Invalid id. Are you sure that's the correct id?")
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Sets the id of the channel where unlocked achievements are announced.
+/// The id HAS to be a channel!
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    rename = "achievementchannel",
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn set_achievementchannel(
+    ctx: Context<'_>,
+    #[rename = "id"] channel_id: ChannelId,
+) -> Result<(), Error> {
+    match channel_id.to_channel(ctx).await {
+        Ok(ch) => {
+            let ch = ch.guild();
+
+            match ch {
+                Some(ch) => {
+                    let guild_id = ctx.guild_id().unwrap();
+                    if ch.guild_id != guild_id {
+                        ctx.say("Bro that channel is in a different server.")
+                            .await?;
+                        return Ok(());
+                    }
+
+                    Configs::set_config(ctx.data(), guild_id, None, None, Some(ch.id))
+                        .await?;
 
                     ctx.say(format!(
-                        "Done! <#{}> has been set as this server's AFK channel",
+                        "Nice, achievements will be announced in <#{}>",
                         channel_id
                     ))
                     .await?;
@@ -142,3 +198,177 @@ pub async fn set_afkchannel(
 
     Ok(())
 }
+
+/// Gives a role to members once they have spent some hours in voice.
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    rename = "milestone",
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn set_milestone(
+    ctx: Context<'_>,
+    #[description = "Hours in voice needed to get the role"]
+    #[min = 1]
+    #[max = 100000]
+    hours: u64,
+    #[description = "Role to give"] role: Role,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    if role.guild_id != guild_id {
+        ctx.say("Bro that role is in a different server.").await?;
+        return Ok(());
+    }
+
+    let mut tiers = milestone::get_milestones(ctx.data(), guild_id)
+        .await?
+        .tiers;
+    tiers.retain(|tier| tier.role_id != role.id);
+    tiers.push(Milestone {
+        hours,
+        role_id: role.id,
+    });
+    tiers.sort_by_key(|tier| tier.hours);
+    milestone::set_milestones(ctx.data(), guild_id, Some(tiers), None).await?;
+
+    ctx.say(format!(
+        "Got it, members with {} hours in voice will get <@&{}>",
+        hours, role.id
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Stops giving a role to members for their time in voice.
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    rename = "removemilestone",
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn remove_milestone(
+    ctx: Context<'_>,
+    #[description = "Role to stop giving"] role: Role,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+
+    let mut tiers = milestone::get_milestones(ctx.data(), guild_id)
+        .await?
+        .tiers;
+    let before = tiers.len();
+    tiers.retain(|tier| tier.role_id != role.id);
+    if tiers.len() == before {
+        ctx.say(format!("<@&{}> is not a milestone role.", role.id))
+            .await?;
+        return Ok(());
+    }
+    milestone::set_milestones(ctx.data(), guild_id, Some(tiers), None).await?;
+
+    ctx.say(format!("<@&{}> is no longer a milestone role.", role.id))
+        .await?;
+
+    Ok(())
+}
+
+/// Lists the roles given to members for their time in voice.
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn milestones(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let milestones = milestone::get_milestones(ctx.data(), guild_id).await?;
+
+    if milestones.tiers.is_empty() {
+        ctx.say("There are no milestone roles set for this server!")
+            .await?;
+        return Ok(());
+    }
+
+    let tiers = milestones
+        .tiers
+        .iter()
+        .map(|tier| format!("- {} hours: <@&{}>", tier.hours, tier.role_id))
+        .collect::<Vec<_>>()
+        .join("\n");
+    ctx.say(format!(
+        "**Milestone roles** (mode: {})\n{}",
+        milestones.mode.as_str(),
+        tiers
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Sets whether members keep every milestone role they reach, or only the highest one.
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    rename = "milestonemode",
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn set_milestonemode(
+    ctx: Context<'_>,
+    #[description = "Keep all milestone roles, or only the highest"] mode: MilestoneMode,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    milestone::set_milestones(ctx.data(), guild_id, None, Some(mode)).await?;
+
+    let content = match mode {
+        MilestoneMode::All => "Done, members will keep every milestone role they reach.",
+        MilestoneMode::Highest => "Done, members will only keep their highest milestone role.",
+    };
+    ctx.say(content).await?;
+
+    Ok(())
+}
+
+/// Sets whether members in voice stop getting credited when a rule applies to them.
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    rename = "activityrule",
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn set_activityrule(
+    ctx: Context<'_>,
+    #[description = "When to stop crediting a member"] rule: ActivityRule,
+    #[description = "Whether the rule applies"] enabled: bool,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+
+    let mut rules = activity::get_rules(ctx.data(), guild_id).await?;
+    rules.set(rule, enabled);
+    activity::set_rules(ctx.data(), guild_id, rules).await?;
+
+    let enabled_rules = [
+        ActivityRule::SelfDeaf,
+        ActivityRule::ServerDeaf,
+        ActivityRule::SelfMute,
+        ActivityRule::Solo,
+        ActivityRule::BotsOnly,
+    ]
+    .into_iter()
+    .filter(|rule| rules.get(*rule))
+    .map(|rule| format!("`{}`", rule.as_str()))
+    .collect::<Vec<_>>();
+    let content = if enabled_rules.is_empty() {
+        "Done, everyone in voice gets credited now.".to_string()
+    } else {
+        format!(
+            "Done, members in voice won't be credited when they are {}.",
+            enabled_rules.join(", ")
+        )
+    };
+    ctx.say(content).await?;
+
+    Ok(())
+}
@@ -11,7 +11,7 @@ pub async fn show(ctx: Context<'_>) -> Result<(), Error> {
     let voice_state = ctx.data().voice_state.clone();
     let text = {
         let vs = voice_state.lock().await;
-        format!("{:#?}", vs.timestamps)
+        format!("{:#?}", vs.sessions)
     };
 
     ctx.say(format!("```{text}```")).await?;
@@ -23,7 +23,7 @@ pub async fn clear(ctx: Context<'_>) -> Result<(), Error> {
     let voice_state = ctx.data().voice_state.clone();
     {
         let mut state = voice_state.lock().await;
-        state.timestamps.clear();
+        state.sessions.clear();
     }
 
     ctx.say("cleared").await?;
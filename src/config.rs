@@ -1,18 +1,16 @@
 use anyhow::Result;
 use poise::serenity_prelude::{ChannelId, GuildId};
-use redis::{from_redis_value as from_val, AsyncCommands, FromRedisValue};
+use redis::{from_redis_value as from_val, FromRedisValue};
 
-use crate::{database::Redis, Data};
+use crate::{pocketbase::records::GuildRecord, Data};
 
 pub struct Configs {}
 
 impl Configs {
     pub async fn get_guild_config(data: &Data, guild_id: GuildId) -> Result<Config> {
-        let db = data.db.clone();
         let cache = data.cache.clone();
 
-        let mut conn = Redis::get_connection(db).await?;
-        let config: Config = conn.hgetall(format!("config:{guild_id}")).await?;
+        let config = data.storage.get_config(guild_id).await?;
         {
             let mut cache = cache.lock().await;
             cache.insert_config(guild_id, config);
@@ -21,35 +19,35 @@ impl Configs {
         Ok(config)
     }
 
-    pub async fn set_afk_channel(
+    /// Saves a guild's channels, leaving whatever is `None` as it is.
+    pub async fn set_config(
         data: &Data,
         guild_id: GuildId,
-        afk_channel_id: ChannelId,
-    ) -> Result<()> {
-        let db = data.db.clone();
+        afk_channel: Option<ChannelId>,
+        graveyard: Option<ChannelId>,
+        achievement_channel: Option<ChannelId>,
+    ) -> Result<Config> {
         let cache = data.cache.clone();
 
-        let mut conn = Redis::get_connection(db).await?;
-        conn.hset(
-            format!("config:{}", guild_id.get()),
-            "afk_channel",
-            afk_channel_id.get(),
-        )
-        .await?;
+        let config = data
+            .storage
+            .set_config(guild_id, afk_channel, graveyard, achievement_channel)
+            .await?;
         {
             let mut cache = cache.lock().await;
-            match cache.get_mut_config(guild_id) {
-                Some(config) => config.afk_channel = Some(afk_channel_id),
-                None => {
-                    let config = Config {
-                        afk_channel: Some(afk_channel_id),
-                        ..Default::default()
-                    };
-                    cache.insert_config(guild_id, config);
-                }
-            };
+            cache.insert_config(guild_id, config);
         }
 
+        Ok(config)
+    }
+
+    pub async fn set_afk_channel(
+        data: &Data,
+        guild_id: GuildId,
+        afk_channel_id: ChannelId,
+    ) -> Result<()> {
+        Configs::set_config(data, guild_id, Some(afk_channel_id), None, None).await?;
+
         Ok(())
     }
 }
@@ -58,13 +56,17 @@ impl Configs {
 pub struct Config {
     pub graveyard: Option<ChannelId>,
     pub afk_channel: Option<ChannelId>,
+    pub achievement_channel: Option<ChannelId>,
 }
 
-impl Config {
-    pub fn new(graveyard: Option<u64>, afk_channel: Option<u64>) -> Self {
+impl From<&GuildRecord> for Config {
+    fn from(guild: &GuildRecord) -> Self {
+        let channel = |id: &str| id.parse::<u64>().ok().map(ChannelId::new);
+
         Config {
-            graveyard: graveyard.map(ChannelId::new),
-            afk_channel: afk_channel.map(ChannelId::new),
+            graveyard: channel(&guild.graveyard),
+            afk_channel: channel(&guild.afk_channel),
+            achievement_channel: channel(&guild.achievement_channel),
         }
     }
 }
@@ -86,6 +88,9 @@ impl FromRedisValue for Config {
                     match from_val::<String>(k)?.as_str() {
                         "graveyard" => conf.graveyard = Some(ChannelId::new(from_val(v)?)),
                         "afk_channel" => conf.afk_channel = Some(ChannelId::new(from_val(v)?)),
+                        "achievement_channel" => {
+                            conf.achievement_channel = Some(ChannelId::new(from_val(v)?))
+                        }
                         _ => println!("Unknown field {:#?} = {:#?}", k, v),
                     }
                 }
@@ -0,0 +1,206 @@
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    sync::Mutex,
+};
+
+/// Strings with when they expire, and hashes.
+#[derive(Debug, Default)]
+struct Keys {
+    strings: HashMap<String, (String, Option<Instant>)>,
+    hashes: HashMap<String, HashMap<String, String>>,
+}
+
+impl Keys {
+    fn string(&mut self, key: &str) -> Option<&String> {
+        if let Some((_, Some(expires_at))) = self.strings.get(key) {
+            if *expires_at <= Instant::now() {
+                self.strings.remove(key);
+            }
+        }
+
+        self.strings.get(key).map(|(value, _)| value)
+    }
+}
+
+enum Reply {
+    Ok,
+    Nil,
+    Int(i64),
+    Bulk(String),
+    Array(Vec<Reply>),
+    Error(String),
+}
+
+impl Reply {
+    fn write(&self, out: &mut Vec<u8>) {
+        match self {
+            Reply::Ok => out.extend_from_slice(b"+OK\r\n"),
+            Reply::Nil => out.extend_from_slice(b"$-1\r\n"),
+            Reply::Int(n) => out.extend_from_slice(format!(":{n}\r\n").as_bytes()),
+            Reply::Bulk(s) => out.extend_from_slice(format!("${}\r\n{s}\r\n", s.len()).as_bytes()),
+            Reply::Array(items) => {
+                out.extend_from_slice(format!("*{}\r\n", items.len()).as_bytes());
+                for item in items {
+                    item.write(out);
+                }
+            }
+            Reply::Error(err) => out.extend_from_slice(format!("-ERR {err}\r\n").as_bytes()),
+        }
+    }
+}
+
+/// Serves a fresh, empty redis with the commands the outbox uses, and gives back its URL.
+///
+/// `MULTI` is taken but commands run as they come, which is all the same with a single
+/// connection at a time.
+pub async fn spawn() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("redis://{}", listener.local_addr().unwrap());
+
+    let keys = Arc::new(Mutex::new(Keys::default()));
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(serve(stream, keys.clone()));
+        }
+    });
+
+    url
+}
+
+async fn serve(stream: TcpStream, keys: Arc<Mutex<Keys>>) {
+    let (read, mut write) = stream.into_split();
+    let mut read = BufReader::new(read);
+    let mut queued: Option<Vec<Reply>> = None;
+
+    while let Some(args) = read_command(&mut read).await {
+        let mut out = Vec::new();
+        let name = args[0].to_uppercase();
+        match (name.as_str(), queued.as_mut()) {
+            ("MULTI", _) => {
+                queued = Some(Vec::new());
+                Reply::Ok.write(&mut out);
+            }
+            ("EXEC", Some(_)) => Reply::Array(queued.take().unwrap()).write(&mut out),
+            (_, Some(replies)) => {
+                replies.push(run(&mut *keys.lock().await, &name, &args[1..]));
+                out.extend_from_slice(b"+QUEUED\r\n");
+            }
+            (_, None) => run(&mut *keys.lock().await, &name, &args[1..]).write(&mut out),
+        }
+        if write.write_all(&out).await.is_err() {
+            return;
+        }
+    }
+}
+
+async fn read_command(read: &mut BufReader<tokio::net::tcp::OwnedReadHalf>) -> Option<Vec<String>> {
+    let mut line = String::new();
+    read.read_line(&mut line).await.ok()?;
+    let count = line.trim_end().strip_prefix('*')?.parse::<usize>().ok()?;
+
+    let mut args = Vec::with_capacity(count);
+    for _ in 0..count {
+        line.clear();
+        read.read_line(&mut line).await.ok()?;
+        let len = line.trim_end().strip_prefix('$')?.parse::<usize>().ok()?;
+        let mut arg = vec![0; len + 2];
+        read.read_exact(&mut arg).await.ok()?;
+        arg.truncate(len);
+        args.push(String::from_utf8(arg).ok()?);
+    }
+
+    Some(args)
+}
+
+fn run(keys: &mut Keys, name: &str, args: &[String]) -> Reply {
+    match (name, args) {
+        ("GET", [key]) => keys.string(key).cloned().map_or(Reply::Nil, Reply::Bulk),
+        ("SET", [key, value, options @ ..]) => {
+            let options = options.iter().map(|o| o.to_uppercase()).collect::<Vec<_>>();
+            if options.iter().any(|o| o == "NX") && keys.string(key).is_some() {
+                return Reply::Nil;
+            }
+            let expires_at = options
+                .iter()
+                .position(|o| o == "PX")
+                .and_then(|i| options.get(i + 1)?.parse::<u64>().ok())
+                .map(|ms| Instant::now() + Duration::from_millis(ms));
+            keys.strings
+                .insert(key.clone(), (value.clone(), expires_at));
+            Reply::Ok
+        }
+        ("PSETEX", [key, ms, value]) => {
+            let Ok(ms) = ms.parse::<u64>() else {
+                return Reply::Error("not an integer".to_string());
+            };
+            let expires_at = Instant::now() + Duration::from_millis(ms);
+            keys.strings
+                .insert(key.clone(), (value.clone(), Some(expires_at)));
+            Reply::Ok
+        }
+        ("EXISTS", keys_to_check) => Reply::Int(
+            keys_to_check
+                .iter()
+                .filter(|key| keys.string(key).is_some() || keys.hashes.contains_key(*key))
+                .count() as i64,
+        ),
+        ("DEL", keys_to_delete) => Reply::Int(
+            keys_to_delete
+                .iter()
+                .filter(|key| {
+                    let string = keys.string(key).is_some();
+                    keys.strings.remove(*key);
+                    keys.hashes.remove(*key).is_some() || string
+                })
+                .count() as i64,
+        ),
+        ("HSET", [key, pairs @ ..]) if !pairs.is_empty() && pairs.len() % 2 == 0 => {
+            let hash = keys.hashes.entry(key.clone()).or_default();
+            let added = pairs
+                .chunks(2)
+                .filter(|pair| hash.insert(pair[0].clone(), pair[1].clone()).is_none())
+                .count();
+            Reply::Int(added as i64)
+        }
+        ("HGET", [key, field]) => keys
+            .hashes
+            .get(key)
+            .and_then(|hash| hash.get(field))
+            .cloned()
+            .map_or(Reply::Nil, Reply::Bulk),
+        ("HGETALL", [key]) => Reply::Array(
+            keys.hashes
+                .get(key)
+                .into_iter()
+                .flatten()
+                .flat_map(|(field, value)| [Reply::Bulk(field.clone()), Reply::Bulk(value.clone())])
+                .collect(),
+        ),
+        ("HEXISTS", [key, field]) => Reply::Int(
+            keys.hashes
+                .get(key)
+                .is_some_and(|hash| hash.contains_key(field)) as i64,
+        ),
+        ("HDEL", [key, fields @ ..]) => {
+            let Some(hash) = keys.hashes.get_mut(key) else {
+                return Reply::Int(0);
+            };
+            let removed = fields
+                .iter()
+                .filter(|field| hash.remove(*field).is_some())
+                .count();
+            if hash.is_empty() {
+                keys.hashes.remove(key);
+            }
+            Reply::Int(removed as i64)
+        }
+        _ => Reply::Error(format!("unknown command `{name}`")),
+    }
+}
@@ -1,9 +1,16 @@
 use std::sync::Arc;
 
 use anyhow::Result;
-use redis::{aio::Connection, Client};
+use redis::{
+    aio::{Connection, ConnectionManager},
+    Client,
+};
 use tokio::sync::Mutex;
 
+/// A stand-in redis for tests.
+#[cfg(test)]
+pub mod fake;
+
 #[derive(Debug)]
 pub struct Redis {
     client: Client,
@@ -24,4 +31,11 @@ impl Redis {
 
         Ok(conn)
     }
+
+    /// A single connection for callers to share, it reconnects by itself when it drops.
+    pub async fn connection_manager(db: Arc<Mutex<Redis>>) -> Result<ConnectionManager> {
+        let db = db.lock().await;
+
+        Ok(db.client.get_tokio_connection_manager().await?)
+    }
 }
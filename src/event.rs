@@ -1,18 +1,19 @@
-use std::time::Instant;
-
 use anyhow::Result;
+use chrono::{DateTime, Utc};
 use poise::{
-    serenity_prelude::{self as serenity, FullEvent, GuildId, UserId},
+    serenity_prelude::{self as serenity, ChannelId, FullEvent, GuildId, UserId},
     FrameworkContext,
 };
-use tokio::sync::oneshot;
 use tracing::{info, warn};
 
 use crate::{
+    achievement,
+    activity::{self, Extras, MemberVoice, Presence},
+    channel,
     config::Configs,
-    pocketbase as pb,
-    score::{GuildUser, ScoreType, Scores},
-    Data, Error,
+    milestone, outbox, period,
+    score::{GuildUser, ScoreType},
+    session, Data, Error, VoiceSession,
 };
 
 #[tracing::instrument(skip_all, fields(event=event.snake_case_name()))]
@@ -26,6 +27,10 @@ pub async fn event_handler(
         FullEvent::Ready { data_about_bot } => {
             info!("Bot is online as {}", data_about_bot.user.name);
         }
+        FullEvent::GuildCreate { guild, .. } => {
+            session::reconcile(data, guild).await?;
+            session::seed(data, guild).await?;
+        }
         FullEvent::Message { new_message } => {
             if new_message.author.bot {
                 return Ok(());
@@ -38,64 +43,43 @@ pub async fn event_handler(
             }
         }
         FullEvent::VoiceStateUpdate { old, new } => {
-            let now = Instant::now();
+            let now = Utc::now();
             let Some(guild_id) = new.guild_id else {
                 return Ok(());
             };
-            let user_id = new.user_id;
-            let afk_channel = {
-                let config = {
-                    let cache = data.cache.lock().await;
-                    cache.get_config(guild_id).copied()
-                };
-                let config = if let Some(config) = config {
-                    config
-                } else {
-                    Configs::get_guild_config(data, guild_id).await?
+            let afk_channel = get_afk_channel(data, guild_id).await?;
+            let rules = activity::get_rules(data, guild_id).await?;
+
+            // Joining, leaving and (un)muting can change whether everyone else in the
+            // channels involved counts as active, so they get another look too.
+            let channels = [old.as_ref().and_then(|vs| vs.channel_id), new.channel_id];
+            let updates = {
+                let Some(guild) = ctx.cache.guild(guild_id) else {
+                    warn!(%guild_id, "Guild is not cached, can't track voice");
+                    return Ok(());
                 };
-                config.afk_channel
-            };
+                let presences = activity::presences(&guild, afk_channel, rules);
 
-            match old {
-                Some(old) => {
-                    match (old.channel_id, new.channel_id) {
-                        // Match when a user go out from a voice channel, ie. the user disconnect
-                        (Some(_), None) => {
-                            if old.channel_id == afk_channel {
-                                go_out_afk(data, guild_id, user_id, now).await?;
-                            } else {
-                                go_out_voice(data, guild_id, user_id, now).await?;
-                            }
-                        }
-
-                        // Match when a user move from one voice channel to another
-                        (Some(_), Some(_)) => {
-                            if old.channel_id == afk_channel {
-                                // User move from AFK channel to a regular voice channel
-                                go_out_afk(data, guild_id, user_id, now).await?;
-                                go_in_voice(data, guild_id, user_id, now).await;
-                            } else if new.channel_id == afk_channel {
-                                // User move from a voice channel to AFK channel
-                                go_out_voice(data, guild_id, user_id, now).await?;
-                                go_in_afk(data, guild_id, user_id, now).await;
-                            }
-                        }
-
-                        _ => warn!(?old, ?new, "Supposedly unreachable"),
+                let mut updates = vec![(
+                    new.user_id,
+                    presences.get(&new.user_id).copied().unwrap_or_default(),
+                )];
+                for (user_id, vs) in guild.voice_states.iter() {
+                    if *user_id != new.user_id
+                        && vs.channel_id.is_some()
+                        && channels.contains(&vs.channel_id)
+                    {
+                        updates.push((
+                            *user_id,
+                            presences.get(user_id).copied().unwrap_or_default(),
+                        ));
                     }
                 }
-                None => match new.channel_id {
-                    // Match when a user go in to a voice channel
-                    Some(_) => {
-                        if new.channel_id == afk_channel {
-                            go_in_afk(data, guild_id, user_id, now).await;
-                        } else {
-                            go_in_voice(data, guild_id, user_id, now).await;
-                        }
-                    }
+                updates
+            };
 
-                    None => warn!(?old, ?new, "Supposedly unreachable"),
-                },
+            for (user_id, voice) in updates {
+                apply(data, GuildUser(guild_id, user_id), voice, now).await?;
             }
         }
         _ => {}
@@ -103,50 +87,163 @@ pub async fn event_handler(
     Ok(())
 }
 
+pub async fn get_afk_channel(data: &Data, guild_id: GuildId) -> Result<Option<ChannelId>> {
+    let config = {
+        let cache = data.cache.lock().await;
+        cache.get_config(guild_id).copied()
+    };
+    let config = if let Some(config) = config {
+        config
+    } else {
+        Configs::get_guild_config(data, guild_id).await?
+    };
+
+    Ok(config.afk_channel)
+}
+
+/// Starts, ends or switches a member's session so it matches where their time goes right now.
+/// Every change of channel closes the session and opens a new one in the new channel.
+pub async fn apply(
+    data: &Data,
+    guild_user: GuildUser,
+    voice: MemberVoice,
+    now: DateTime<Utc>,
+) -> Result<()> {
+    let GuildUser(guild_id, user_id) = guild_user;
+    let MemberVoice { presence, extras } = voice;
+
+    let session = {
+        let voice_state = data.voice_state.lock().await;
+        voice_state.sessions.get(&guild_user).copied().flatten()
+    };
+    let tracked: Presence = match session {
+        Some(session) => {
+            let afk_channel = get_afk_channel(data, guild_id).await?;
+            if Some(session.channel_id) == afk_channel {
+                Some((ScoreType::Afk, session.channel_id))
+            } else {
+                Some((ScoreType::Voice, session.channel_id))
+            }
+        }
+        None => None,
+    };
+
+    if tracked == presence {
+        // Starting or stopping a stream, camera or stage talk is a new stretch of time to credit.
+        if let (Some(session), Some((score_type, _))) = (session, tracked) {
+            if session.extras != extras {
+                info!(
+                    %guild_id,
+                    %user_id,
+                    stream = extras.stream,
+                    video = extras.video,
+                    stage = extras.stage,
+                    "Voice activity changed"
+                );
+                roll(data, guild_user, score_type, session, extras, now).await?;
+            }
+        }
+        return Ok(());
+    }
+
+    let transition = match (tracked, presence) {
+        (None, _) => "join",
+        (_, None) => "leave",
+        _ => "move",
+    };
+    info!(
+        transition,
+        %guild_id,
+        %user_id,
+        from_kind = tracked.map(|(score_type, _)| score_type.as_str()),
+        from_channel = tracked.map(|(_, channel_id)| channel_id.get()),
+        to_kind = presence.map(|(score_type, _)| score_type.as_str()),
+        to_channel = presence.map(|(_, channel_id)| channel_id.get()),
+        "Voice transition"
+    );
+
+    match tracked.map(|(score_type, _)| score_type) {
+        Some(ScoreType::Voice) => go_out_voice(data, guild_id, user_id, now).await?,
+        Some(ScoreType::Afk) => go_out_afk(data, guild_id, user_id, now).await?,
+        None => {}
+    }
+    match presence {
+        Some((ScoreType::Voice, channel_id)) => {
+            go_in_voice(data, guild_id, user_id, channel_id, extras, now).await?
+        }
+        Some((ScoreType::Afk, channel_id)) => {
+            go_in_afk(data, guild_id, user_id, channel_id, now).await?
+        }
+        None => {}
+    }
+
+    Ok(())
+}
+
 #[tracing::instrument(skip(data, now))]
-async fn go_in_voice(data: &Data, guild_id: GuildId, user_id: UserId, now: Instant) {
+pub async fn go_in_voice(
+    data: &Data,
+    guild_id: GuildId,
+    user_id: UserId,
+    channel_id: ChannelId,
+    extras: Extras,
+    now: DateTime<Utc>,
+) -> Result<()> {
+    let guild_user: GuildUser = (guild_id, user_id).into();
+    let voice_session = VoiceSession {
+        channel_id,
+        joined_at: now,
+        started_at: now,
+        extras,
+    };
     {
         let mut voice_state = data.voice_state.lock().await;
-        voice_state
-            .timestamps
-            .insert((guild_id, user_id).into(), Some(now));
+        voice_state.sessions.insert(guild_user, Some(voice_session));
     }
+    session::open(data, guild_user, ScoreType::Voice, voice_session).await?;
+    channel::record_peak(data, guild_id, channel_id, now).await?;
 
     info!("Entered voice");
+
+    Ok(())
 }
 
 #[tracing::instrument(skip(data, now))]
-async fn go_out_voice(data: &Data, guild_id: GuildId, user_id: UserId, now: Instant) -> Result<()> {
+pub async fn go_out_voice(
+    data: &Data,
+    guild_id: GuildId,
+    user_id: UserId,
+    now: DateTime<Utc>,
+) -> Result<()> {
     let guild_user: GuildUser = (guild_id, user_id).into();
 
-    let Some(Some(then)) = ({
+    let Some(Some(voice_session)) = ({
         let voice_state = data.voice_state.lock().await;
-        voice_state.timestamps.get(&guild_user).copied()
+        voice_state.sessions.get(&guild_user).copied()
     }) else {
         info!("Left voice after being there for god knows how long");
         return Ok(());
     };
-    let duration = now.duration_since(then);
-
     {
         let mut voice_state = data.voice_state.lock().await;
-        voice_state.timestamps.insert(guild_user, None);
+        voice_state.sessions.insert(guild_user, None);
     }
-    {
-        // Invalidate the cache, so that leaderboard doesn't show stale data.
-        let mut cache = data.cache.lock().await;
-        cache.rem_scores(guild_id);
-    }
-
-    // Old db
-    Scores::incr_score(data.db.clone(), guild_user, duration.as_secs()).await?;
 
-    // New db
-    let (tx, rx) = oneshot::channel();
-    let cmd = pb::Command::new_incr_score(guild_user, duration.as_secs(), tx, ScoreType::Voice);
-    data.tx.send(cmd).await?;
-    let _ = rx.await??;
+    // Credited before the stored session is closed, so a failed close can't lose the time.
+    let session_length = (now - voice_session.joined_at).num_seconds().max(0) as u64;
+    credit(
+        data,
+        guild_user,
+        ScoreType::Voice,
+        voice_session,
+        now,
+        Some(session_length),
+    )
+    .await?;
+    session::close(data, guild_user).await?;
+    session::log(data, guild_user, ScoreType::Voice, voice_session, now).await?;
 
+    let duration = (now - voice_session.joined_at).to_std().unwrap_or_default();
     let fmt_duration = humantime::format_duration(duration);
     info!("Left voice after being there for {fmt_duration}");
 
@@ -154,74 +251,215 @@ async fn go_out_voice(data: &Data, guild_id: GuildId, user_id: UserId, now: Inst
 }
 
 #[tracing::instrument(skip(data, now))]
-async fn go_in_afk(data: &Data, guild_id: GuildId, user_id: UserId, now: Instant) {
+pub async fn go_in_afk(
+    data: &Data,
+    guild_id: GuildId,
+    user_id: UserId,
+    channel_id: ChannelId,
+    now: DateTime<Utc>,
+) -> Result<()> {
+    let guild_user: GuildUser = (guild_id, user_id).into();
+    let voice_session = VoiceSession {
+        channel_id,
+        joined_at: now,
+        started_at: now,
+        extras: Extras::default(),
+    };
     {
         let mut voice_state = data.voice_state.lock().await;
-        voice_state
-            .timestamps
-            .insert((guild_id, user_id).into(), Some(now));
+        voice_state.sessions.insert(guild_user, Some(voice_session));
     }
+    session::open(data, guild_user, ScoreType::Afk, voice_session).await?;
+    channel::record_peak(data, guild_id, channel_id, now).await?;
 
     info!("Went AFK");
+
+    Ok(())
 }
 
 #[tracing::instrument(skip(data, now))]
-async fn go_out_afk(data: &Data, guild_id: GuildId, user_id: UserId, now: Instant) -> Result<()> {
+pub async fn go_out_afk(
+    data: &Data,
+    guild_id: GuildId,
+    user_id: UserId,
+    now: DateTime<Utc>,
+) -> Result<()> {
     let guild_user: GuildUser = (guild_id, user_id).into();
 
-    let Some(Some(then)) = ({
+    let Some(Some(voice_session)) = ({
         let voice_state = data.voice_state.lock().await;
-        voice_state.timestamps.get(&guild_user).copied()
+        voice_state.sessions.get(&guild_user).copied()
     }) else {
         info!("Left AFK after being there for god knows how long");
         return Ok(());
     };
 
-    let duration = now.duration_since(then);
-
     {
         let mut voice_state = data.voice_state.lock().await;
-        voice_state.timestamps.insert(guild_user, None);
-    }
-    {
-        // Invalidate the cache, so that leaderboard doesn't show stale data.
-        let mut cache = data.cache.lock().await;
-        cache.rem_scores(guild_id);
+        voice_state.sessions.insert(guild_user, None);
     }
 
-    let (tx, rx) = oneshot::channel();
-    let cmd = pb::Command::new_incr_score(guild_user, duration.as_secs(), tx, ScoreType::Afk);
-    data.tx.send(cmd).await?;
-    let _ = rx.await??;
+    credit(data, guild_user, ScoreType::Afk, voice_session, now, None).await?;
+    session::close(data, guild_user).await?;
+    session::log(data, guild_user, ScoreType::Afk, voice_session, now).await?;
 
+    let duration = (now - voice_session.joined_at).to_std().unwrap_or_default();
     let fmt_duration = humantime::format_duration(duration);
     info!("Left AFK after {fmt_duration}");
 
     Ok(())
 }
 
+/// Credits the time spent since the last credit, without ending the session.
+///
+/// The session is started if the member wasn't tracked yet, and split like any other move
+/// if they turn out to be in a different channel.
+async fn flush(
+    data: &Data,
+    guild_user: GuildUser,
+    score_type: ScoreType,
+    channel_id: ChannelId,
+    extras: Extras,
+    now: DateTime<Utc>,
+) -> Result<()> {
+    let voice_session = {
+        let voice_state = data.voice_state.lock().await;
+        voice_state.sessions.get(&guild_user).copied().flatten()
+    };
+    let voice_session = match voice_session {
+        Some(voice_session) if voice_session.channel_id == channel_id => voice_session,
+        _ => {
+            let voice = MemberVoice {
+                presence: Some((score_type, channel_id)),
+                extras,
+            };
+            return apply(data, guild_user, voice, now).await;
+        }
+    };
+
+    roll(data, guild_user, score_type, voice_session, extras, now).await
+}
+
+/// Credits a session up to `now` and carries on with it from there.
+async fn roll(
+    data: &Data,
+    guild_user: GuildUser,
+    score_type: ScoreType,
+    voice_session: VoiceSession,
+    extras: Extras,
+    now: DateTime<Utc>,
+) -> Result<()> {
+    credit(data, guild_user, score_type, voice_session, now, None).await?;
+
+    let voice_session = VoiceSession {
+        started_at: now,
+        extras,
+        ..voice_session
+    };
+    {
+        let mut voice_state = data.voice_state.lock().await;
+        voice_state.sessions.insert(guild_user, Some(voice_session));
+    }
+    session::open(data, guild_user, score_type, voice_session).await?;
+
+    Ok(())
+}
+
 #[inline]
-pub async fn go_out_and_in_voice(
+pub async fn flush_voice(
     data: &Data,
     guild_id: GuildId,
     user_id: UserId,
-    now: Instant,
+    channel_id: ChannelId,
+    extras: Extras,
+    now: DateTime<Utc>,
 ) -> Result<()> {
-    go_out_voice(data, guild_id, user_id, now).await?;
-    go_in_voice(data, guild_id, user_id, now).await;
-
-    Ok(())
+    flush(
+        data,
+        (guild_id, user_id).into(),
+        ScoreType::Voice,
+        channel_id,
+        extras,
+        now,
+    )
+    .await
 }
 
 #[inline]
-pub async fn go_out_and_in_afk(
+pub async fn flush_afk(
     data: &Data,
     guild_id: GuildId,
     user_id: UserId,
-    now: Instant,
+    channel_id: ChannelId,
+    now: DateTime<Utc>,
 ) -> Result<()> {
-    go_out_afk(data, guild_id, user_id, now).await?;
-    go_in_afk(data, guild_id, user_id, now).await;
+    flush(
+        data,
+        (guild_id, user_id).into(),
+        ScoreType::Afk,
+        channel_id,
+        Extras::default(),
+        now,
+    )
+    .await
+}
+
+/// Adds the time from the session's last credit up to `now` to the member's scores, and to
+/// the streaming, camera and stage counters the session has going.
+/// `session_length` is the length of the whole session, when it has just ended.
+async fn credit(
+    data: &Data,
+    guild_user: GuildUser,
+    score_type: ScoreType,
+    voice_session: VoiceSession,
+    now: DateTime<Utc>,
+    session_length: Option<u64>,
+) -> Result<()> {
+    let delta = (now - voice_session.started_at).num_seconds().max(0) as u64;
+
+    {
+        // Invalidate the cache, so that leaderboard doesn't show stale data.
+        let mut cache = data.cache.lock().await;
+        cache.rem_scores(guild_user.0);
+    }
+
+    // Writes that pocketbase failed but the outbox kept still count as done, they are retried
+    // from there and crediting them again would count the time twice.
+    let score = outbox::or_outboxed(
+        data.storage
+            .incr_score(
+                guild_user,
+                score_type,
+                delta,
+                session_length,
+                voice_session.extras,
+            )
+            .await,
+    )?;
+    outbox::or_outboxed(
+        period::incr_period_scores(
+            data,
+            guild_user,
+            score_type,
+            voice_session.started_at,
+            delta,
+        )
+        .await,
+    )?;
+    outbox::or_outboxed(
+        channel::incr_channel_score(data, guild_user, voice_session.channel_id, delta).await,
+    )?;
+
+    // Milestones and achievements wait for the next credit that knows the score.
+    let Some(score) = score else {
+        return Ok(());
+    };
+
+    if score_type == ScoreType::Voice {
+        milestone::sync_roles(data, guild_user, score.score.as_secs()).await?;
+    }
+
+    achievement::evaluate(data, guild_user, score_type, &score, voice_session, now).await?;
 
     Ok(())
 }
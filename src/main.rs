@@ -5,6 +5,7 @@ use std::{
     time::{Duration, Instant},
 };
 
+use activity::Extras;
 use anyhow::Context as _;
 use apalis::{
     cron::{CronStream, Schedule},
@@ -16,9 +17,12 @@ use chrono::{DateTime, Utc};
 use commands::score_update;
 use database::Redis;
 use once_cell::sync::Lazy;
-use poise::serenity_prelude::{self as serenity, Cache, Http, UserId};
-use shuttle_runtime::SecretStore;
-use shuttle_serenity::ShuttleSerenity;
+use outbox::Outbox;
+use poise::serenity_prelude::{self as serenity, Cache, ChannelId, Http, UserId};
+use session::SessionPolicy;
+use storage::{
+    MemoryStorage, PocketbaseStorage, RedisStorage, SqliteStorage, Storage, StorageKind,
+};
 use tokio::sync::{mpsc, Mutex};
 use tracing::info;
 
@@ -26,27 +30,72 @@ use crate::score::GuildUser;
 
 #[derive(Debug, Clone)]
 pub struct Data {
-    db: Arc<Mutex<Redis>>,
+    /// `None` without `REDIS_URL`.
+    db: Option<Arc<Mutex<Redis>>>,
+    storage: Arc<dyn Storage>,
     voice_state: Arc<Mutex<VoiceStates>>,
     cache: Arc<Mutex<DataCache>>,
-    tx: mpsc::Sender<pocketbase::Command>,
+    /// `None` unless both redis and pocketbase are set up, like `outbox`.
+    tx: Option<mpsc::Sender<pocketbase::Command>>,
+    pb_metrics: Arc<pocketbase::Metrics>,
+    outbox: Option<Outbox>,
+    http: Arc<Http>,
+    /// Serenity's cache of guilds, members and channels.
+    discord_cache: Arc<Cache>,
+    session_policy: SessionPolicy,
+}
+
+impl Data {
+    pub fn tx(&self) -> anyhow::Result<&mpsc::Sender<pocketbase::Command>> {
+        self.tx
+            .as_ref()
+            .context("Pocketbase isn't running without redis and pocketbase set up")
+    }
+
+    pub fn outbox(&self) -> anyhow::Result<&Outbox> {
+        self.outbox
+            .as_ref()
+            .context("There is no outbox without redis and pocketbase set up")
+    }
 }
 
 #[derive(Debug, Default)]
 pub struct VoiceStates {
-    pub timestamps: HashMap<GuildUser, Option<Instant>>,
+    pub sessions: HashMap<GuildUser, Option<VoiceSession>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct VoiceSession {
+    pub channel_id: ChannelId,
+    /// When the member joined, used for the session length.
+    pub joined_at: DateTime<Utc>,
+    /// When the time was last credited, moves forward on every hourly score update.
+    pub started_at: DateTime<Utc>,
+    pub extras: Extras,
 }
 
 type Error = Box<dyn std::error::Error + Send + Sync>;
 type Context<'a> = poise::Context<'a, Data, Error>;
+/// Looks a secret up by name, in `Secrets.toml` on shuttle and in the environment otherwise.
+type Secrets = Box<dyn Fn(&str) -> Option<String> + Send + Sync>;
 
+mod achievement;
+mod activity;
 mod cache;
+mod channel;
 mod commands;
 mod config;
 mod database;
 mod event;
+mod migration;
+mod milestone;
+mod outbox;
+mod period;
 mod pocketbase;
+mod render;
 mod score;
+mod session;
+mod storage;
 mod user;
 
 static IS_DEV: Lazy<bool> = Lazy::new(|| {
@@ -54,33 +103,115 @@ static IS_DEV: Lazy<bool> = Lazy::new(|| {
     is_dev == "DEV"
 });
 
+#[cfg(feature = "shuttle")]
 #[shuttle_runtime::main]
-async fn serenity(#[shuttle_runtime::Secrets] secret_store: SecretStore) -> ShuttleSerenity {
-    // Get the appropriate discord token from `Secrets.toml`
-    let discord_token = if *IS_DEV {
-        secret_store
-            .get("DEV_DISCORD_TOKEN")
-            .context("'DEV_DISCORD_TOKEN' was not found in Secrets.toml")?
-    } else {
-        secret_store
-            .get("DISCORD_TOKEN")
-            .context("'DISCORD_TOKEN' was not found in Secrets.toml")?
-    };
+async fn serenity(
+    #[shuttle_runtime::Secrets] secret_store: shuttle_runtime::SecretStore,
+) -> Result<Bot, shuttle_runtime::Error> {
+    let bot = Bot::new(Box::new(move |name| secret_store.get(name))).await?;
+
+    Ok(bot)
+}
+
+/// Runs the bot without shuttle, with its secrets in environment variables.
+#[cfg(not(feature = "shuttle"))]
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into()),
+        )
+        .init();
+
+    let bot = Bot::new(Box::new(|name| std::env::var(name).ok())).await?;
+    bot.run().await?;
 
-    let intents =
-        serenity::GatewayIntents::non_privileged() | serenity::GatewayIntents::MESSAGE_CONTENT;
+    Ok(())
+}
+
+/// The serenity client, with what has to be done once it stops.
+struct Bot {
+    client: serenity::Client,
+    shutdown: pocketbase::Shutdown,
+}
+
+impl Bot {
+    async fn new(secrets: Secrets) -> anyhow::Result<Self> {
+        // Get the appropriate discord token from the secrets
+        let discord_token = if *IS_DEV {
+            secrets("DEV_DISCORD_TOKEN")
+                .context("'DEV_DISCORD_TOKEN' was not found in the secrets")?
+        } else {
+            secrets("DISCORD_TOKEN").context("'DISCORD_TOKEN' was not found in the secrets")?
+        };
+
+        let (shutdown, stop_rx) = pocketbase::Shutdown::new();
+
+        let framework = poise::Framework::builder()
+            .options(framework_options())
+            .setup(move |ctx, _, _| framework_setup(ctx, secrets, stop_rx))
+            .build();
+
+        let intents =
+            serenity::GatewayIntents::non_privileged() | serenity::GatewayIntents::MESSAGE_CONTENT;
+        let client = serenity::ClientBuilder::new(&discord_token, intents)
+            .framework(framework)
+            .await?;
+
+        Ok(Bot { client, shutdown })
+    }
+
+    /// Runs the client until it fails or the process is asked to stop, then waits for the
+    /// buffered scores to be written.
+    async fn run(mut self) -> Result<(), serenity::Error> {
+        let shard_manager = self.client.shard_manager.clone();
+        let res = tokio::select! {
+            res = self.client.start_autosharded() => res,
+            _ = shutdown_signal() => {
+                info!("Stopping");
+                shard_manager.shutdown_all().await;
+                Ok(())
+            }
+        };
+
+        self.shutdown.flush_and_wait().await;
+
+        res
+    }
+}
 
-    let framework_options = poise::FrameworkOptions {
+#[cfg(feature = "shuttle")]
+#[shuttle_runtime::async_trait]
+impl shuttle_runtime::Service for Bot {
+    async fn bind(self, _addr: std::net::SocketAddr) -> Result<(), shuttle_runtime::Error> {
+        self.run()
+            .await
+            .map_err(shuttle_runtime::CustomError::new)?;
+
+        Ok(())
+    }
+}
+
+fn framework_options() -> poise::FrameworkOptions<Data, Error> {
+    poise::FrameworkOptions {
         commands: vec![
             commands::hello(),
+            commands::achievements(),
+            commands::channels(),
+            commands::forget(),
+            commands::profile(),
             commands::graveyard(),
             commands::register(),
             commands::incr_score(),
+            commands::migrate(),
+            commands::outbox(),
+            commands::pb_stats(),
             commands::set_afk_channel(),
             commands::rank(),
             commands::gtfo(),
             commands::voice_state(),
             commands::settings(),
+            commands::season(),
         ],
         prefix_options: poise::PrefixFrameworkOptions {
             prefix: Some("f:".into()),
@@ -114,65 +245,134 @@ async fn serenity(#[shuttle_runtime::Secrets] secret_store: SecretStore) -> Shut
         },
         owners: HashSet::from([UserId::new(429661753362874402)]),
         ..Default::default()
-    };
-
-    let framework = poise::Framework::builder()
-        .options(framework_options)
-        .setup(move |ctx, _, _| framework_setup(ctx, &secret_store))
-        .build();
+    }
+}
 
-    let client = serenity::ClientBuilder::new(&discord_token, intents)
-        .framework(framework)
-        .await
-        .expect("Failed to create serenity client");
+/// Resolves once the process is asked to stop.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+            }
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
 
-    Ok(client.into())
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
 }
 
 fn framework_setup(
     ctx: &serenity::Context,
-    secret_store: &SecretStore,
+    secrets: Secrets,
+    stop_rx: pocketbase::manager::StopReceiver,
 ) -> poise::BoxFuture<'static, Result<Data, Error>> {
-    // Get the redis URL set in `Secrets.toml`
-    let redis_url = secret_store
-        .get("REDIS_URL")
-        .context("'REDIS_URL' was not found in Secrets.toml.")
-        .unwrap();
-
-    // Get the pocketbase URL set in `Secrets.toml`
-    let pb_url = secret_store
-        .get("POCKETBASE_URL")
-        .context("'POCKETBASE_URL' was not found in Secrets.toml.")
-        .unwrap();
-
-    // Get the pocketbase username set in `Secrets.toml`
-    let pb_username = secret_store
-        .get("POCKETBASE_USERNAME")
-        .context("'POCKETBASE_USERNAME' was not found in Secrets.toml.")
-        .unwrap();
-
-    // Get the pocketbase password set in `Secrets.toml`
-    let pb_password = secret_store
-        .get("POCKETBASE_PASSWORD")
-        .context("'POCKETBASE_PASSWORD' was not found in Secrets.toml.")
-        .unwrap();
+    // Get the redis URL set in the secrets, without it there is no outbox
+    let redis_url = secrets("REDIS_URL");
+
+    // Get the pocketbase URL, username and password set in the secrets, all or nothing
+    let pocketbase = match (
+        secrets("POCKETBASE_URL"),
+        secrets("POCKETBASE_USERNAME"),
+        secrets("POCKETBASE_PASSWORD"),
+    ) {
+        (Some(url), Some(username), Some(password)) => Ok(Some((url, username, password))),
+        (None, None, None) => Ok(None),
+        _ => Err("'POCKETBASE_URL', 'POCKETBASE_USERNAME' and 'POCKETBASE_PASSWORD' have to be set together."),
+    };
+
+    // Get the policy for sessions left open by a restart, defaults to resuming them
+    let session_policy = secrets("SESSION_POLICY")
+        .map(|policy| policy.parse::<SessionPolicy>())
+        .transpose()
+        .context("'SESSION_POLICY' is not one of resume, credit or discard.")
+        .unwrap()
+        .unwrap_or_default();
+
+    // Get where everything is kept, defaults to pocketbase
+    let storage_kind = secrets("STORAGE")
+        .map(|kind| kind.parse::<StorageKind>())
+        .transpose()
+        .context("'STORAGE' is not one of pocketbase, redis, sqlite or memory.")
+        .unwrap()
+        .unwrap_or_default();
+
+    // Get the SQLite database URL set in the secrets, only used by the sqlite storage
+    let sqlite_url = secrets("SQLITE_URL").unwrap_or_else(|| "sqlite://hoofoobot.db".to_string());
 
     let http = ctx.http.clone();
     let cache = ctx.cache.clone();
-    let (tx, rx) = mpsc::channel::<pocketbase::Command>(10);
-
-    let data = Data {
-        db: Arc::new(Mutex::new(Redis::new(&redis_url))),
-        voice_state: Arc::new(Mutex::new(VoiceStates::default())),
-        cache: Arc::new(Mutex::new(DataCache::default())),
-        tx,
-    };
+    let pb_metrics = Arc::new(pocketbase::Metrics::default());
 
     Box::pin(async move {
+        let db = redis_url.map(|url| Arc::new(Mutex::new(Redis::new(&url))));
+
+        // The manager keeps what it failed to write in the redis outbox, so it needs both.
+        let pocketbase = match (&db, pocketbase?) {
+            (Some(db), Some((url, username, password))) => {
+                let client = pocketbase::Client::new(&url, &username, &password).await?;
+                let outbox = Outbox::new(db.clone()).await?;
+                let (tx, rx) = mpsc::channel::<pocketbase::Command>(100);
+                Some((client, outbox, tx, rx))
+            }
+            (None, Some(_)) => {
+                return Err("'POCKETBASE_URL' is set but 'REDIS_URL' isn't, pocketbase needs redis for its outbox.".into());
+            }
+            _ => None,
+        };
+        let tx = pocketbase.as_ref().map(|(_, _, tx, _)| tx.clone());
+        let outbox = pocketbase.as_ref().map(|(_, outbox, _, _)| outbox.clone());
+
+        let storage: Arc<dyn Storage> = match storage_kind {
+            StorageKind::Pocketbase => {
+                let tx = tx
+                    .clone()
+                    .context("'STORAGE' is pocketbase, but redis and pocketbase aren't set up.")?;
+                Arc::new(PocketbaseStorage::new(tx))
+            }
+            StorageKind::Redis => {
+                let db = db
+                    .clone()
+                    .context("'STORAGE' is redis, but 'REDIS_URL' isn't set.")?;
+                let storage = RedisStorage::new(db);
+                match &tx {
+                    Some(tx) => {
+                        Arc::new(storage.with_pocketbase(PocketbaseStorage::new(tx.clone())))
+                    }
+                    None => Arc::new(storage),
+                }
+            }
+            StorageKind::Sqlite => Arc::new(SqliteStorage::connect(&sqlite_url).await?),
+            StorageKind::Memory => Arc::new(MemoryStorage::default()),
+        };
+
+        let data = Data {
+            db,
+            storage,
+            voice_state: Arc::new(Mutex::new(VoiceStates::default())),
+            cache: Arc::new(Mutex::new(DataCache::default())),
+            tx,
+            pb_metrics: pb_metrics.clone(),
+            outbox,
+            http: http.clone(),
+            discord_cache: cache.clone(),
+            session_policy,
+        };
+
         // Background worker setup
         {
             let data = data.clone();
             tokio::spawn(async move {
+                let has_outbox = data.outbox.is_some();
                 let worker_data = WorkerData { data, http, cache };
 
                 let schedule = Schedule::from_str("@hourly")?;
@@ -180,22 +380,46 @@ fn framework_setup(
                 let worker = WorkerBuilder::new("hourly-score-update")
                     .layer(RetryLayer::new(DefaultRetryPolicy))
                     .layer(TraceLayer::new())
-                    .layer(Extension(worker_data))
+                    .layer(Extension(worker_data.clone()))
                     .stream(stream.to_stream())
                     .build_fn(score_updater_fn);
-
-                Monitor::new().register(worker).run().await?;
+                let mut monitor = Monitor::new().register(worker);
+
+                if has_outbox {
+                    // Every five minutes, a failed replay is retried in between with backoff.
+                    let schedule = Schedule::from_str("0 */5 * * * *")?;
+                    let stream = CronStream::new(schedule).timer(timer::TokioTimer {});
+                    let outbox_worker = WorkerBuilder::new("outbox-replay")
+                        .layer(RetryLayer::new(outbox::ReplayBackoff))
+                        .layer(TraceLayer::new())
+                        .layer(Extension(worker_data))
+                        .stream(stream.to_stream())
+                        .build_fn(outbox_replayer_fn);
+                    monitor = monitor.register(outbox_worker);
+                }
+
+                monitor.run().await?;
 
                 Ok::<(), Error>(())
             });
         }
 
         // Pocketbase background worker setup
-        {
-            let client = pocketbase::Client::new(&pb_url, &pb_username, &pb_password).await?;
-            let manager = pocketbase::Manager::new(client);
-
-            manager.spawn(rx);
+        if let Some((client, outbox, _, rx)) = pocketbase {
+            // Only worth following when pocketbase keeps something the bot caches.
+            if matches!(storage_kind, StorageKind::Pocketbase | StorageKind::Redis) {
+                let realtime = pocketbase::Realtime::new(
+                    client.clone(),
+                    data.cache.clone(),
+                    storage_kind == StorageKind::Pocketbase,
+                );
+                realtime.spawn();
+            }
+
+            let manager = pocketbase::Manager::new(client, pb_metrics, outbox);
+            manager.spawn(rx, stop_rx);
+        } else {
+            info!("Running without pocketbase");
         }
 
         Ok(data)
@@ -224,9 +448,35 @@ struct WorkerData {
 
 async fn score_updater_fn(_job: ScoreUpdater, ctx: JobContext) -> Result<(), Error> {
     let WorkerData { data, http, cache } = ctx.data::<WorkerData>()?.clone();
-    let now = Instant::now();
+    let now = Utc::now();
 
     score_update(data, http, cache, now).await?;
 
     Ok(())
 }
+
+#[derive(Default, Debug, Clone)]
+struct OutboxReplayer(DateTime<Utc>);
+
+impl From<DateTime<Utc>> for OutboxReplayer {
+    fn from(t: DateTime<Utc>) -> Self {
+        OutboxReplayer(t)
+    }
+}
+
+impl Job for OutboxReplayer {
+    const NAME: &'static str = "outbox::OutboxReplayer";
+}
+
+async fn outbox_replayer_fn(job: OutboxReplayer, ctx: JobContext) -> Result<(), Error> {
+    let WorkerData { data, .. } = ctx.data::<WorkerData>()?.clone();
+
+    let replayed = outbox::replay(&data).await?;
+    info!("Outbox replay of {}: {replayed}", job.0);
+    // Failing has the `ReplayBackoff` run it again.
+    if replayed.failed > 0 {
+        return Err(anyhow::anyhow!("Outbox replay: {replayed}").into());
+    }
+
+    Ok(())
+}
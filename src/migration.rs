@@ -0,0 +1,264 @@
+use std::{collections::HashMap, time::Duration};
+
+use anyhow::{Context, Result};
+use poise::serenity_prelude::GuildId;
+use tracing::info;
+
+use crate::{
+    activity::Extras,
+    config::Config,
+    outbox::{self, Increment, Outbox},
+    score::{GuildUser, ScoreType},
+    storage::{RedisStorage, Storage},
+    Data,
+};
+
+/// What moving everything left in redis over to the storage does, or would do on a dry run.
+#[derive(Debug, Default)]
+pub struct MigrationReport {
+    /// Members whose voice time in the storage is behind redis, with how much is missing.
+    pub scores: Vec<(GuildUser, Duration)>,
+    pub scores_up_to_date: usize,
+    /// Guilds missing channels in the storage that are set in redis, with only those channels.
+    pub configs: Vec<(GuildId, Config)>,
+    pub configs_up_to_date: usize,
+}
+
+impl MigrationReport {
+    pub fn missing_time(&self) -> Duration {
+        self.scores.iter().map(|(_, delta)| *delta).sum()
+    }
+}
+
+impl std::fmt::Display for MigrationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Scores: {} to top up by {} in total, {} already up to date",
+            self.scores.len(),
+            humantime::format_duration(self.missing_time()),
+            self.scores_up_to_date,
+        )?;
+        write!(
+            f,
+            "Configs: {} to fill in, {} already up to date",
+            self.configs.len(),
+            self.configs_up_to_date,
+        )
+    }
+}
+
+/// Moves every `score:{guild}:{user}` key and `config:{guild}` hash from redis into the
+/// storage the bot runs on.
+///
+/// Voice time is topped up to what redis has, counting what the outbox still has to write,
+/// and channels are only filled in where the storage has none, so running it again after it
+/// went through changes nothing.
+pub async fn migrate_redis(data: &Data, dry_run: bool) -> Result<MigrationReport> {
+    let db = data
+        .db
+        .clone()
+        .context("There is no redis to migrate from without 'REDIS_URL'")?;
+    let redis = RedisStorage::new(db);
+    let members = redis.members().await?;
+    let guilds = redis.guilds().await?;
+    let outboxed = match &data.outbox {
+        Some(outbox) => outboxed_voice_time(outbox).await?,
+        None => HashMap::new(),
+    };
+
+    let report = migrate(
+        &redis,
+        data.storage.as_ref(),
+        &members,
+        &guilds,
+        &outboxed,
+        dry_run,
+    )
+    .await?;
+
+    if !dry_run {
+        for (guild_id, _) in report.configs.iter() {
+            let config = data.storage.get_config(*guild_id).await?;
+            let mut cache = data.cache.lock().await;
+            cache.insert_config(*guild_id, config);
+        }
+        let mut cache = data.cache.lock().await;
+        for (member, _) in report.scores.iter() {
+            cache.rem_scores(member.0);
+        }
+    }
+
+    Ok(report)
+}
+
+/// Voice time increments the outbox still has to write, by member.
+async fn outboxed_voice_time(outbox: &Outbox) -> Result<HashMap<GuildUser, Duration>> {
+    let mut outboxed = HashMap::<GuildUser, Duration>::new();
+    for entry in outbox.list().await? {
+        let Increment::Score { deltas, .. } = &entry.increment else {
+            continue;
+        };
+        let voice_time = deltas
+            .iter()
+            .filter(|(field, _)| field == "voice_time")
+            .map(|(_, delta)| Duration::from_secs(*delta))
+            .sum::<Duration>();
+        *outboxed.entry(entry.increment.member()).or_default() += voice_time;
+    }
+
+    Ok(outboxed)
+}
+
+/// Tops up the scores of `members` and fills in the configs of `guilds` in `to` from `from`,
+/// see `migrate_redis`.
+async fn migrate(
+    from: &dyn Storage,
+    to: &dyn Storage,
+    members: &[GuildUser],
+    guilds: &[GuildId],
+    outboxed: &HashMap<GuildUser, Duration>,
+    dry_run: bool,
+) -> Result<MigrationReport> {
+    let mut report = MigrationReport::default();
+
+    for &member in members {
+        let Some(from_score) = from.get_score(member).await? else {
+            continue;
+        };
+        let current = to.get_score(member).await?.unwrap_or_default().score
+            + outboxed.get(&member).copied().unwrap_or_default();
+        let delta = from_score.score.saturating_sub(current);
+        if delta.is_zero() {
+            report.scores_up_to_date += 1;
+            continue;
+        }
+
+        info!(guild_id = %member.0, user_id = %member.1, ?delta, dry_run, "Migrating score");
+        if !dry_run {
+            // Kept by the outbox, the top-up is written later and counted from there on a rerun.
+            outbox::or_outboxed(
+                to.incr_score(
+                    member,
+                    ScoreType::Voice,
+                    delta.as_secs(),
+                    None,
+                    Extras::default(),
+                )
+                .await,
+            )?;
+        }
+        report.scores.push((member, delta));
+    }
+
+    for &guild_id in guilds {
+        let from_config = from.get_config(guild_id).await?;
+        let current = to.get_config(guild_id).await?;
+
+        let missing = Config {
+            graveyard: from_config
+                .graveyard
+                .filter(|_| current.graveyard.is_none()),
+            afk_channel: from_config
+                .afk_channel
+                .filter(|_| current.afk_channel.is_none()),
+            achievement_channel: from_config
+                .achievement_channel
+                .filter(|_| current.achievement_channel.is_none()),
+        };
+        if missing.graveyard.is_none()
+            && missing.afk_channel.is_none()
+            && missing.achievement_channel.is_none()
+        {
+            report.configs_up_to_date += 1;
+            continue;
+        }
+
+        info!(%guild_id, ?missing, dry_run, "Migrating config");
+        if !dry_run {
+            to.set_config(
+                guild_id,
+                missing.afk_channel,
+                missing.graveyard,
+                missing.achievement_channel,
+            )
+            .await?;
+        }
+        report.configs.push((guild_id, missing));
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use poise::serenity_prelude::{ChannelId, UserId};
+
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    #[tokio::test]
+    async fn running_twice_changes_nothing_more() {
+        let from = MemoryStorage::default();
+        let to = MemoryStorage::default();
+        let guild_id = GuildId::new(1);
+        let members = [
+            GuildUser(guild_id, UserId::new(2)),
+            GuildUser(guild_id, UserId::new(3)),
+        ];
+        for (member, voice_time) in members.iter().zip([600, 60]) {
+            from.incr_score(
+                *member,
+                ScoreType::Voice,
+                voice_time,
+                None,
+                Extras::default(),
+            )
+            .await
+            .unwrap();
+        }
+        to.incr_score(members[1], ScoreType::Voice, 20, None, Extras::default())
+            .await
+            .unwrap();
+        from.set_config(guild_id, Some(ChannelId::new(4)), None, None)
+            .await
+            .unwrap();
+        let outboxed = HashMap::new();
+
+        let first = migrate(&from, &to, &members, &[guild_id], &outboxed, false)
+            .await
+            .unwrap();
+        let second = migrate(&from, &to, &members, &[guild_id], &outboxed, false)
+            .await
+            .unwrap();
+
+        assert_eq!(first.missing_time(), Duration::from_secs(640));
+        assert_eq!(first.configs.len(), 1);
+        assert_eq!(second.missing_time(), Duration::ZERO);
+        assert_eq!(second.scores_up_to_date, 2);
+        assert_eq!(second.configs_up_to_date, 1);
+        for member in members {
+            let from_score = from.get_score(member).await.unwrap().unwrap();
+            let to_score = to.get_score(member).await.unwrap().unwrap();
+            assert_eq!(to_score.score, from_score.score);
+        }
+    }
+
+    #[tokio::test]
+    async fn outboxed_time_is_not_topped_up_again() {
+        let from = MemoryStorage::default();
+        let to = MemoryStorage::default();
+        let member = GuildUser(GuildId::new(1), UserId::new(2));
+        from.incr_score(member, ScoreType::Voice, 600, None, Extras::default())
+            .await
+            .unwrap();
+        let outboxed = HashMap::from([(member, Duration::from_secs(600))]);
+
+        let report = migrate(&from, &to, &[member], &[], &outboxed, false)
+            .await
+            .unwrap();
+
+        assert_eq!(report.scores_up_to_date, 1);
+        assert!(to.get_score(member).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,152 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use poise::serenity_prelude::{GuildId, RoleId};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{score::GuildUser, Data};
+
+/// Which milestone roles a member keeps once they reach several tiers.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum MilestoneMode {
+    /// Keep the role of every tier reached.
+    #[default]
+    #[name = "all"]
+    All,
+    /// Keep only the role of the highest tier reached.
+    #[name = "highest"]
+    Highest,
+}
+
+impl MilestoneMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MilestoneMode::All => "all",
+            MilestoneMode::Highest => "highest",
+        }
+    }
+}
+
+impl FromStr for MilestoneMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "" | "all" => Ok(MilestoneMode::All),
+            "highest" => Ok(MilestoneMode::Highest),
+            _ => Err(anyhow!("Unknown milestone mode {s}")),
+        }
+    }
+}
+
+/// A role granted once a member has spent `hours` in voice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Milestone {
+    pub hours: u64,
+    pub role_id: RoleId,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Milestones {
+    pub mode: MilestoneMode,
+    /// Sorted from the lowest tier.
+    pub tiers: Vec<Milestone>,
+}
+
+impl Milestones {
+    /// The milestone roles a member with `voice_time` seconds in voice should have.
+    pub fn earned(&self, voice_time: u64) -> Vec<RoleId> {
+        let mut reached = self
+            .tiers
+            .iter()
+            .filter(|tier| tier.hours.saturating_mul(60 * 60) <= voice_time)
+            .map(|tier| tier.role_id);
+
+        match self.mode {
+            MilestoneMode::All => reached.collect(),
+            MilestoneMode::Highest => reached.next_back().into_iter().collect(),
+        }
+    }
+}
+
+/// Gets a guild's milestone roles.
+pub async fn get_milestones(data: &Data, guild_id: GuildId) -> Result<Milestones> {
+    let cached = {
+        let cache = data.cache.lock().await;
+        cache.get_milestones(guild_id).cloned()
+    };
+    if let Some(milestones) = cached {
+        return Ok(milestones);
+    }
+
+    let milestones = data.storage.get_milestones(guild_id).await?;
+
+    {
+        let mut cache = data.cache.lock().await;
+        cache.insert_milestones(guild_id, milestones.clone());
+    }
+
+    Ok(milestones)
+}
+
+/// Saves a guild's milestone roles, leaving whatever is `None` as it is.
+pub async fn set_milestones(
+    data: &Data,
+    guild_id: GuildId,
+    tiers: Option<Vec<Milestone>>,
+    mode: Option<MilestoneMode>,
+) -> Result<()> {
+    data.storage.set_milestones(guild_id, tiers, mode).await?;
+
+    {
+        let mut cache = data.cache.lock().await;
+        cache.rem_milestones(guild_id);
+    }
+
+    Ok(())
+}
+
+/// Grants the milestone roles a member has reached with `voice_time` seconds in voice,
+/// and takes away the ones they shouldn't have.
+pub async fn sync_roles(data: &Data, guild_user: GuildUser, voice_time: u64) -> Result<()> {
+    let GuildUser(guild_id, user_id) = guild_user;
+
+    let milestones = get_milestones(data, guild_id).await?;
+    if milestones.tiers.is_empty() {
+        return Ok(());
+    }
+    let earned = milestones.earned(voice_time);
+
+    // Credits come in often, the member is only fetched when the cache doesn't have them.
+    let cached = data
+        .discord_cache
+        .guild(guild_id)
+        .and_then(|guild| guild.members.get(&user_id).cloned());
+    let member = match cached {
+        Some(member) => member,
+        // The member could have left the guild while still in voice.
+        None => match guild_id.member(&data.http, user_id).await {
+            Ok(member) => member,
+            Err(err) => {
+                warn!(?err, "Can't fetch member to update their milestone roles");
+                return Ok(());
+            }
+        },
+    };
+    for tier in milestones.tiers.iter() {
+        let has_role = member.roles.contains(&tier.role_id);
+        let res = match (earned.contains(&tier.role_id), has_role) {
+            (true, false) => member.add_role(&data.http, tier.role_id).await,
+            (false, true) => member.remove_role(&data.http, tier.role_id).await,
+            _ => continue,
+        };
+
+        // Roles above the bot's own can't be managed, that shouldn't stop the time from being credited.
+        if let Err(err) = res {
+            warn!(?err, role_id = %tier.role_id, "Can't update a milestone role");
+        }
+    }
+
+    Ok(())
+}
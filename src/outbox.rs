@@ -0,0 +1,454 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use anyhow::Result;
+use apalis::prelude::{JobError, JobRequest};
+use chrono::{DateTime, Utc};
+use poise::serenity_prelude::{ChannelId, GuildId, UserId};
+use redis::{aio::ConnectionManager, AsyncCommands};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{oneshot, Mutex};
+use tower::retry::Policy;
+use tracing::warn;
+
+use crate::{database::Redis, pocketbase as pb, score::GuildUser, Data};
+
+const OUTBOX_KEY: &str = "outbox";
+
+/// How long a claim on an entry lasts, well past the longest a write can take with the
+/// client's request timeout.
+pub const LEASE: Duration = Duration::from_secs(5 * 60);
+
+/// How long the first retry of a failed replay waits, doubling with every attempt after that.
+const FIRST_RETRY: Duration = Duration::from_secs(30);
+/// How many times a failed replay is run, retries included.
+const MAX_REPLAY_ATTEMPTS: i32 = 4;
+
+/// An increment pocketbase has to get, kept as plain values so it can be written again later.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Increment {
+    Score {
+        guild_id: GuildId,
+        user_id: UserId,
+        /// Score record fields with how much to add to them.
+        deltas: Vec<(String, u64)>,
+    },
+    /// Raises the longest session of a score, which can be written any number of times.
+    LongestSession {
+        guild_id: GuildId,
+        user_id: UserId,
+        session_length: u64,
+    },
+    /// One bucket of a period, each gets its own entry so a failed one doesn't write the
+    /// others again.
+    PeriodScore {
+        guild_id: GuildId,
+        user_id: UserId,
+        /// `voice_time` or `afk_time`.
+        field: String,
+        delta: u64,
+        /// As in `Period::as_str`.
+        period: String,
+        bucket: String,
+    },
+    ChannelScore {
+        guild_id: GuildId,
+        user_id: UserId,
+        channel_id: ChannelId,
+        delta: u64,
+    },
+    /// A closed session for the voice history log, so the log keeps up with the scores.
+    Session {
+        guild_id: GuildId,
+        user_id: UserId,
+        channel_id: ChannelId,
+        /// `voice` or `afk`.
+        score_type: String,
+        /// Unix timestamps in seconds.
+        started_at: i64,
+        ended_at: i64,
+    },
+}
+
+impl Increment {
+    pub fn member(&self) -> GuildUser {
+        match self {
+            Increment::Score {
+                guild_id, user_id, ..
+            }
+            | Increment::LongestSession {
+                guild_id, user_id, ..
+            }
+            | Increment::PeriodScore {
+                guild_id, user_id, ..
+            }
+            | Increment::ChannelScore {
+                guild_id, user_id, ..
+            }
+            | Increment::Session {
+                guild_id, user_id, ..
+            } => GuildUser(*guild_id, *user_id),
+        }
+    }
+}
+
+impl std::fmt::Display for Increment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Increment::Score { deltas, .. } => {
+                let deltas = deltas
+                    .iter()
+                    .map(|(field, delta)| format!("{field} +{delta}s"))
+                    .collect::<Vec<_>>();
+                write!(f, "score {}", deltas.join(", "))
+            }
+            Increment::LongestSession { session_length, .. } => {
+                write!(f, "longest session at least {session_length}s")
+            }
+            Increment::PeriodScore {
+                field,
+                delta,
+                period,
+                bucket,
+                ..
+            } => write!(f, "{field} +{delta}s in {period} {bucket}"),
+            Increment::ChannelScore {
+                channel_id, delta, ..
+            } => write!(f, "<#{channel_id}> +{delta}s"),
+            Increment::Session {
+                channel_id,
+                score_type,
+                started_at,
+                ended_at,
+                ..
+            } => write!(
+                f,
+                "{score_type} session in <#{channel_id}> of {}s logged",
+                (ended_at - started_at).max(0)
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub id: String,
+    pub increment: Increment,
+    pub created_at: DateTime<Utc>,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+}
+
+/// What a caller gets instead of their write when pocketbase failed it but the outbox kept it,
+/// it gets written again later so the time isn't lost.
+#[derive(Debug)]
+pub struct Outboxed;
+
+impl std::error::Error for Outboxed {}
+
+impl std::fmt::Display for Outboxed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Write failed, kept in the outbox to be retried")
+    }
+}
+
+/// Turns `Outboxed` into `None`, anything else stays an error.
+pub fn or_outboxed<T>(res: Result<T>) -> Result<Option<T>> {
+    match res {
+        Ok(value) => Ok(Some(value)),
+        Err(err) if err.is::<Outboxed>() => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Increments on their way to pocketbase, in the redis `outbox` hash by entry id.
+///
+/// An entry is added before its write is sent and removed once pocketbase has it, so whatever
+/// is left failed and gets replayed by the outbox worker. Whoever writes an entry claims it
+/// first, so it is never written twice at the same time.
+///
+/// Every write goes through it, so it keeps a single redis connection for all of them.
+#[derive(Clone)]
+pub struct Outbox {
+    conn: ConnectionManager,
+    next_id: Arc<AtomicU64>,
+}
+
+impl std::fmt::Debug for Outbox {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Outbox")
+            .field("next_id", &self.next_id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Outbox {
+    pub async fn new(db: Arc<Mutex<Redis>>) -> Result<Self> {
+        Ok(Outbox {
+            conn: Redis::connection_manager(db).await?,
+            next_id: Arc::new(AtomicU64::new(0)),
+        })
+    }
+
+    /// Keeps a new entry, claimed for the write that goes along with it.
+    pub async fn push(&self, increment: Increment) -> Result<Entry> {
+        let entry = self.entry(increment);
+
+        let mut conn = self.conn.clone();
+        redis::pipe()
+            .atomic()
+            .pset_ex(lease_key(&entry.id), 1, LEASE.as_millis() as usize)
+            .ignore()
+            .hset(OUTBOX_KEY, &entry.id, serde_json::to_string(&entry)?)
+            .ignore()
+            .query_async::<_, ()>(&mut conn)
+            .await?;
+
+        Ok(entry)
+    }
+
+    fn entry(&self, increment: Increment) -> Entry {
+        let now = Utc::now();
+        let id = format!(
+            "{}-{}",
+            now.timestamp_millis(),
+            self.next_id.fetch_add(1, Ordering::Relaxed)
+        );
+
+        Entry {
+            id,
+            increment,
+            created_at: now,
+            attempts: 0,
+            last_error: None,
+        }
+    }
+
+    /// Claims an entry for `lease`, unless someone else has it already.
+    pub async fn claim(&self, id: &str, lease: Duration) -> Result<bool> {
+        let mut conn = self.conn.clone();
+        let claimed: Option<String> = redis::cmd("SET")
+            .arg(lease_key(id))
+            .arg(1)
+            .arg("NX")
+            .arg("PX")
+            .arg(lease.as_millis() as u64)
+            .query_async(&mut conn)
+            .await?;
+
+        Ok(claimed.is_some())
+    }
+
+    pub async fn is_claimed(&self, id: &str) -> Result<bool> {
+        let mut conn = self.conn.clone();
+
+        Ok(conn.exists(lease_key(id)).await?)
+    }
+
+    /// Removes an entry along with its claim, returning whether it was there.
+    pub async fn remove(&self, id: &str) -> Result<bool> {
+        let mut conn = self.conn.clone();
+        let (removed,): (u64,) = redis::pipe()
+            .atomic()
+            .hdel(OUTBOX_KEY, id)
+            .del(lease_key(id))
+            .ignore()
+            .query_async(&mut conn)
+            .await?;
+
+        Ok(removed > 0)
+    }
+
+    /// Counts a failed attempt and lets go of the entry for the next replay.
+    pub async fn fail(&self, mut entry: Entry, err: &anyhow::Error) -> Result<()> {
+        entry.attempts += 1;
+        entry.last_error = Some(err.to_string());
+
+        let mut conn = self.conn.clone();
+        // Dropped by an owner while being written, it stays dropped.
+        let exists: bool = conn.hexists(OUTBOX_KEY, &entry.id).await?;
+        if exists {
+            let _: () = conn
+                .hset(OUTBOX_KEY, &entry.id, serde_json::to_string(&entry)?)
+                .await?;
+        }
+        let _: () = conn.del(lease_key(&entry.id)).await?;
+
+        Ok(())
+    }
+
+    pub async fn get(&self, id: &str) -> Result<Option<Entry>> {
+        let mut conn = self.conn.clone();
+        let entry: Option<String> = conn.hget(OUTBOX_KEY, id).await?;
+
+        Ok(entry
+            .map(|entry| serde_json::from_str(&entry))
+            .transpose()?)
+    }
+
+    /// Drops every entry of a member, claiming each first so no replay starts on it meanwhile.
+    /// One being written right now is dropped too, and isn't kept if that write fails.
+    /// Returns how many were dropped.
+    pub async fn purge(&self, member: GuildUser) -> Result<usize> {
+        let mut purged = 0;
+        for entry in self.list().await? {
+            if entry.increment.member() != member {
+                continue;
+            }
+            self.claim(&entry.id, LEASE).await?;
+            if self.remove(&entry.id).await? {
+                purged += 1;
+            }
+        }
+
+        Ok(purged)
+    }
+
+    /// Every entry, oldest first. The ones that can't be read anymore are left in redis.
+    pub async fn list(&self) -> Result<Vec<Entry>> {
+        let mut conn = self.conn.clone();
+        let entries: Vec<(String, String)> = conn.hgetall(OUTBOX_KEY).await?;
+
+        let mut entries = entries
+            .iter()
+            .filter_map(|(id, entry)| match serde_json::from_str::<Entry>(entry) {
+                Ok(entry) => Some(entry),
+                Err(err) => {
+                    warn!(?err, id, "Can't read the outbox entry");
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        entries.sort_by_key(|entry| entry.created_at);
+
+        Ok(entries)
+    }
+}
+
+fn lease_key(id: &str) -> String {
+    format!("{OUTBOX_KEY}:lease:{id}")
+}
+
+#[derive(Debug, Default)]
+pub struct Replayed {
+    pub written: usize,
+    pub failed: usize,
+    /// Gone, or claimed by another write.
+    pub skipped: usize,
+}
+
+impl std::fmt::Display for Replayed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} written, {} failed again, {} skipped as they are being written",
+            self.written, self.failed, self.skipped
+        )
+    }
+}
+
+/// Writes again every entry nobody is writing right now.
+pub async fn replay(data: &Data) -> Result<Replayed> {
+    let mut replayed = Replayed::default();
+
+    for entry in data.outbox()?.list().await? {
+        match replay_entry(data, entry).await {
+            Ok(true) => replayed.written += 1,
+            Ok(false) => replayed.skipped += 1,
+            Err(err) => {
+                warn!(?err, "Outbox entry failed again");
+                replayed.failed += 1;
+            }
+        }
+    }
+
+    Ok(replayed)
+}
+
+/// Retries a failed outbox replay like `DefaultRetryPolicy`, waiting `FIRST_RETRY` the first
+/// time and twice as long every time after that, up to `MAX_REPLAY_ATTEMPTS`.
+#[derive(Debug, Clone)]
+pub struct ReplayBackoff;
+
+impl<T: Clone, Res> Policy<JobRequest<T>, Res, JobError> for ReplayBackoff {
+    type Future = Pin<Box<dyn Future<Output = Self> + Send>>;
+
+    fn retry(&self, req: &JobRequest<T>, result: Result<&Res, &JobError>) -> Option<Self::Future> {
+        if result.is_ok() || req.attempts() >= MAX_REPLAY_ATTEMPTS {
+            return None;
+        }
+
+        let retries = req.attempts().saturating_sub(1).max(0) as u32;
+        let backoff = FIRST_RETRY.saturating_mul(2u32.saturating_pow(retries));
+        Some(Box::pin(async move {
+            tokio::time::sleep(backoff).await;
+            ReplayBackoff
+        }))
+    }
+
+    fn clone_request(&self, req: &JobRequest<T>) -> Option<JobRequest<T>> {
+        let mut req = req.clone();
+        req.record_attempt();
+        Some(req)
+    }
+}
+
+/// Writes an entry again, unless it is gone or claimed by another write by then. Returns
+/// whether it was written.
+pub async fn replay_entry(data: &Data, entry: Entry) -> Result<bool> {
+    let (tx, rx) = oneshot::channel();
+    let cmd = pb::Command::new_replay_outbox(entry, tx);
+    data.tx()?.send(cmd).await?;
+
+    rx.await?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::fake;
+
+    fn score(member: GuildUser) -> Increment {
+        Increment::Score {
+            guild_id: member.0,
+            user_id: member.1,
+            deltas: vec![("voice_time".to_string(), 60)],
+        }
+    }
+
+    #[tokio::test]
+    async fn purge_drops_every_entry_of_the_member_only() {
+        let db = Arc::new(Mutex::new(Redis::new(&fake::spawn().await)));
+        let outbox = Outbox::new(db).await.unwrap();
+        let member = GuildUser(GuildId::new(1), UserId::new(2));
+        let other = GuildUser(GuildId::new(1), UserId::new(3));
+        let err = anyhow::anyhow!("pocketbase is down");
+
+        // Failed earlier and waiting for a replay.
+        let failed = outbox.push(score(member)).await.unwrap();
+        outbox.fail(failed, &err).await.unwrap();
+        // Still being written.
+        let writing = outbox.push(score(member)).await.unwrap();
+        let kept = outbox.push(score(other)).await.unwrap();
+        outbox.fail(kept.clone(), &err).await.unwrap();
+
+        assert_eq!(outbox.purge(member).await.unwrap(), 2);
+
+        // The write in progress fails after all, it isn't kept for a replay.
+        outbox.fail(writing.clone(), &err).await.unwrap();
+        assert!(outbox.get(&writing.id).await.unwrap().is_none());
+
+        let left = outbox.list().await.unwrap();
+        assert_eq!(left.len(), 1);
+        assert_eq!(left[0].id, kept.id);
+        assert_eq!(left[0].attempts, 1);
+    }
+}
@@ -0,0 +1,183 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use poise::serenity_prelude::GuildId;
+
+use crate::{
+    outbox,
+    score::{GuildUser, Score, ScoreType},
+    Data,
+};
+
+/// Time window of a leaderboard.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum Period {
+    #[default]
+    #[name = "all"]
+    All,
+    #[name = "day"]
+    Day,
+    #[name = "week"]
+    Week,
+    #[name = "month"]
+    Month,
+    #[name = "season"]
+    Season,
+}
+
+impl Period {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Period::All => "all",
+            Period::Day => "day",
+            Period::Week => "week",
+            Period::Month => "month",
+            Period::Season => "season",
+        }
+    }
+
+    /// The bucket `at` falls into, for calendar periods only.
+    /// Buckets are in UTC, a new one starts being used as soon as the date changes.
+    pub fn calendar_bucket(&self, at: DateTime<Utc>) -> Option<String> {
+        match self {
+            Period::Day => Some(at.format("%Y-%m-%d").to_string()),
+            Period::Week => Some(at.format("%G-W%V").to_string()),
+            Period::Month => Some(at.format("%Y-%m").to_string()),
+            Period::All | Period::Season => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Season {
+    pub id: String,
+    pub name: String,
+}
+
+/// A season once it's over.
+#[derive(Debug, Clone)]
+pub struct EndedSeason {
+    pub name: String,
+    /// Sorted from the highest voice time.
+    pub standings: Vec<Score>,
+}
+
+/// Gets the season currently running in a guild, if any.
+pub async fn get_active_season(data: &Data, guild_id: GuildId) -> Result<Option<Season>> {
+    let cached = {
+        let cache = data.cache.lock().await;
+        cache.get_season(guild_id).cloned()
+    };
+    if let Some(season) = cached {
+        return Ok(season);
+    }
+
+    let season = data.storage.get_season(guild_id).await?;
+
+    {
+        let mut cache = data.cache.lock().await;
+        cache.insert_season(guild_id, season.clone());
+    }
+
+    Ok(season)
+}
+
+/// Adds the `delta` seconds from `from` on to every period bucket they fall into.
+///
+/// Buckets are worked out from the time itself when it's credited, nothing rolls them over.
+/// Time spent across midnight is split, so each day, week and month only gets its own part.
+pub async fn incr_period_scores(
+    data: &Data,
+    guild_user: GuildUser,
+    score_type: ScoreType,
+    from: DateTime<Utc>,
+    delta: u64,
+) -> Result<()> {
+    let season = get_active_season(data, guild_user.0).await?;
+
+    for (at, delta) in split_by_day(from, delta) {
+        let mut buckets = [Period::Day, Period::Week, Period::Month]
+            .into_iter()
+            .filter_map(|period| period.calendar_bucket(at).map(|bucket| (period, bucket)))
+            .collect::<Vec<_>>();
+        if let Some(season) = &season {
+            buckets.push((Period::Season, season.id.clone()));
+        }
+
+        let res = data
+            .storage
+            .incr_period_scores(guild_user, score_type, delta, buckets)
+            .await;
+        outbox::or_outboxed(res)?;
+    }
+
+    Ok(())
+}
+
+/// Splits the `delta` seconds from `from` on at every midnight UTC, into when each part starts
+/// and how long it is. Weeks and months start at midnight too, so every part falls in a single
+/// bucket of each calendar period.
+fn split_by_day(from: DateTime<Utc>, delta: u64) -> Vec<(DateTime<Utc>, u64)> {
+    const DAY: i64 = 24 * 60 * 60;
+
+    let mut start = from.timestamp();
+    let end = start.saturating_add(delta as i64);
+
+    let mut parts = Vec::new();
+    while start < end {
+        let midnight = (start.div_euclid(DAY) + 1) * DAY;
+        let part_end = midnight.min(end);
+        if let Some(at) = DateTime::from_timestamp(start, 0) {
+            parts.push((at, (part_end - start) as u64));
+        }
+        start = part_end;
+    }
+
+    parts
+}
+
+/// Gets a guild's leaderboard for one period bucket, sorted from the highest voice time.
+pub async fn get_period_scores(
+    data: &Data,
+    guild_id: GuildId,
+    period: Period,
+    bucket: String,
+) -> Result<Arc<[Score]>> {
+    let mut scores = data.storage.period_scores(guild_id, period, bucket).await?;
+    scores.sort_by(|a, b| b.cmp(a));
+
+    Ok(scores.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_by_day_keeps_each_day_apart() {
+        let from = "2024-05-08T23:00:00Z".parse::<DateTime<Utc>>().unwrap();
+
+        let parts = split_by_day(from, 2 * 60 * 60);
+
+        let days = parts
+            .iter()
+            .map(|(at, delta)| (Period::Day.calendar_bucket(*at).unwrap(), *delta))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            days,
+            [
+                ("2024-05-08".to_string(), 60 * 60),
+                ("2024-05-09".to_string(), 60 * 60)
+            ]
+        );
+    }
+
+    #[test]
+    fn split_by_day_leaves_a_single_day_whole() {
+        let from = "2024-05-08T10:00:00Z".parse::<DateTime<Utc>>().unwrap();
+
+        assert_eq!(split_by_day(from, 60), [(from, 60)]);
+        assert!(split_by_day(from, 0).is_empty());
+    }
+}
@@ -1,17 +1,71 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, marker::PhantomData, sync::Arc, time::Duration};
 
 use anyhow::bail;
+use chrono::Utc;
+use jwt::{Claims, Header, Token, Unverified};
 use reqwest::{
-    header::{HeaderMap, HeaderValue, AUTHORIZATION},
-    Url,
+    header::{HeaderValue, ACCEPT, AUTHORIZATION},
+    RequestBuilder, Response, StatusCode, Url,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
+use tokio::sync::RwLock;
+use tracing::warn;
 
 use super::records::{AdminRecord, Record};
 
 static APP_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"),);
 
+/// How far into its lifetime the admin token gets refreshed, in percent.
+const REFRESH_AT_PERCENT: i64 = 80;
+
+/// How long a request can take before it fails. Not set on the client itself, the realtime
+/// connection stays open for as long as the bot runs.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The most records pocketbase gives back in a single page.
+const MAX_PER_PAGE: u32 = 500;
+
+/// Query parameters of a list request, anything left `None` is up to pocketbase.
+/// See <https://pocketbase.io/docs/api-records/#listsearch-records>.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ListParams<'a> {
+    /// Starts from 1.
+    pub page: Option<u32>,
+    pub per_page: Option<u32>,
+    /// Comma separated fields, prefixed with `-` to sort descending, ex: `-voice_time,created`.
+    pub sort: Option<&'a str>,
+    pub filter: Option<&'a str>,
+    /// Comma separated relation fields to fetch along, they end up in the record's `expand`.
+    pub expand: Option<&'a str>,
+    /// Comma separated fields to return, everything by default.
+    pub fields: Option<&'a str>,
+}
+
+impl<'a> ListParams<'a> {
+    fn query(&self) -> Vec<(&'static str, String)> {
+        let mut query = Vec::new();
+        if let Some(page) = self.page {
+            query.push(("page", page.to_string()));
+        }
+        if let Some(per_page) = self.per_page {
+            query.push(("perPage", per_page.to_string()));
+        }
+        for (key, value) in [
+            ("sort", self.sort),
+            ("filter", self.filter),
+            ("expand", self.expand),
+            ("fields", self.fields),
+        ] {
+            if let Some(value) = value {
+                query.push((key, value.to_string()));
+            }
+        }
+
+        query
+    }
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(untagged)]
 pub enum ListResponse<R: Record> {
@@ -40,6 +94,40 @@ impl<R: Record> ListResponse<R> {
     }
 }
 
+/// Walks every page of a list request, one request per page.
+pub struct Pages<'a, R> {
+    client: &'a Client,
+    params: ListParams<'a>,
+    next_page: Option<u32>,
+    record: PhantomData<R>,
+}
+
+impl<'a, R: for<'de> Deserialize<'de> + Record> Pages<'a, R> {
+    /// The records of the next page, `None` once every page has been walked.
+    pub async fn next(&mut self) -> anyhow::Result<Option<Vec<R>>> {
+        let Some(page) = self.next_page else {
+            return Ok(None);
+        };
+
+        let params = ListParams {
+            page: Some(page),
+            ..self.params
+        };
+        match self.client.list_with::<R>(params).await? {
+            ListResponse::Ok {
+                page,
+                total_pages,
+                items,
+                ..
+            } => {
+                self.next_page = (page < total_pages).then_some(page + 1);
+                Ok(Some(items))
+            }
+            ListResponse::Error { error } => bail!(error),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(untagged)]
 pub enum CVUResponse<R: Record> {
@@ -53,6 +141,15 @@ pub enum CVUResponse<R: Record> {
     },
 }
 
+impl<R: Record> CVUResponse<R> {
+    pub fn into_result(self) -> Result<R, ErrorResponse> {
+        match self {
+            CVUResponse::Ok { record } => Ok(record),
+            CVUResponse::Error { error } => Err(error),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(untagged)]
 pub enum AdminAuthResponse {
@@ -85,70 +182,252 @@ impl core::fmt::Display for ErrorResponse {
     }
 }
 
+struct Credentials {
+    identity: String,
+    password: String,
+}
+
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Credentials")
+            .field("identity", &self.identity)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug)]
+struct Auth {
+    token: HeaderValue,
+    /// Unix timestamp in seconds, `None` if the token doesn't expire.
+    refresh_at: Option<i64>,
+}
+
+impl Auth {
+    fn new(token: &str) -> anyhow::Result<Self> {
+        let mut header = HeaderValue::from_str(token)?;
+        header.set_sensitive(true);
+
+        // Pocketbase is the one checking the signature, only the lifetime matters here.
+        let claims = Token::<Header, Claims, Unverified>::parse_unverified(token)
+            .ok()
+            .map(|token| token.claims().registered.clone());
+        let expires_at = claims
+            .as_ref()
+            .and_then(|claims| claims.expiration)
+            .map(|exp| exp as i64);
+        // A token that doesn't say when it was issued was issued just now.
+        let issued_at = claims
+            .and_then(|claims| claims.issued_at)
+            .map(|iat| iat as i64)
+            .unwrap_or_else(|| Utc::now().timestamp());
+
+        Ok(Auth {
+            token: header,
+            refresh_at: expires_at
+                .map(|exp| issued_at + (exp - issued_at) * REFRESH_AT_PERCENT / 100),
+        })
+    }
+
+    fn is_expiring(&self) -> bool {
+        self.refresh_at
+            .is_some_and(|refresh_at| Utc::now().timestamp() >= refresh_at)
+    }
+}
+
+async fn read_auth(res: Response) -> anyhow::Result<(Auth, AdminRecord)> {
+    match res.json::<AdminAuthResponse>().await? {
+        AdminAuthResponse::Ok { token, admin } => Ok((Auth::new(&token)?, admin)),
+        AdminAuthResponse::Error { error } => bail!(error),
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Client {
     pub reqwest_client: reqwest::Client,
     pub admin: Arc<AdminRecord>,
     pub pb_url: Arc<Url>,
+    credentials: Arc<Credentials>,
+    auth: Arc<RwLock<Auth>>,
 }
 
 impl Client {
     pub async fn new(pb_url: &str, username: &str, password: &str) -> anyhow::Result<Self> {
         let pb_url = reqwest::Url::parse(pb_url).expect("Failed to parse pocketbase url");
-        let login_url = pb_url.join("/api/admins/auth-with-password")?;
+        let client = reqwest::Client::builder()
+            .user_agent(APP_USER_AGENT)
+            .build()?;
+        let credentials = Credentials {
+            identity: username.to_string(),
+            password: password.to_string(),
+        };
 
-        let login_client = reqwest::Client::new();
-        let response = login_client
-            .post(login_url)
-            .json(&json!({"identity": username, "password": password}))
+        let res = client
+            .post(pb_url.join("/api/admins/auth-with-password")?)
+            .json(&json!({"identity": credentials.identity, "password": credentials.password}))
+            .timeout(REQUEST_TIMEOUT)
             .send()
             .await?;
+        let (auth, admin) = read_auth(res).await?;
+
+        Ok(Client {
+            reqwest_client: client,
+            admin: admin.into(),
+            pb_url: pb_url.into(),
+            credentials: credentials.into(),
+            auth: Arc::new(RwLock::new(auth)),
+        })
+    }
 
-        let resp = response.json::<AdminAuthResponse>().await?;
+    async fn login(&self) -> anyhow::Result<Auth> {
+        let url = self.pb_url.join("/api/admins/auth-with-password")?;
+        let res = self
+            .reqwest_client
+            .post(url)
+            .json(&json!({
+                "identity": self.credentials.identity,
+                "password": self.credentials.password,
+            }))
+            .timeout(REQUEST_TIMEOUT)
+            .send()
+            .await?;
+        let (auth, _) = read_auth(res).await?;
 
-        match resp {
-            AdminAuthResponse::Ok { token, admin } => {
-                let mut auth_token = HeaderValue::from_str(&token)?;
-                auth_token.set_sensitive(true);
+        Ok(auth)
+    }
 
-                let mut headers = HeaderMap::with_capacity(1);
-                headers.insert(AUTHORIZATION, auth_token);
+    /// Swaps a token that is still valid for a fresh one, logging in again if that fails.
+    async fn refresh(&self, token: &HeaderValue) -> anyhow::Result<Auth> {
+        let url = self.pb_url.join("/api/admins/auth-refresh")?;
+        let res = self
+            .reqwest_client
+            .post(url)
+            .header(AUTHORIZATION, token)
+            .timeout(REQUEST_TIMEOUT)
+            .send()
+            .await;
 
-                let client = reqwest::Client::builder()
-                    .default_headers(headers)
-                    .user_agent(APP_USER_AGENT)
-                    .build()?;
+        let refreshed = match res {
+            Ok(res) => read_auth(res).await.map(|(auth, _)| auth),
+            Err(err) => Err(err.into()),
+        };
+        match refreshed {
+            Ok(auth) => Ok(auth),
+            Err(err) => {
+                warn!(?err, "Can't refresh the pocketbase token, logging in again");
+                self.login().await
+            }
+        }
+    }
 
-                Ok(Client {
-                    reqwest_client: client,
-                    admin: admin.into(),
-                    pb_url: pb_url.into(),
-                })
+    /// The admin token to send, refreshed first if it's about to expire.
+    async fn token(&self) -> anyhow::Result<HeaderValue> {
+        {
+            let auth = self.auth.read().await;
+            if !auth.is_expiring() {
+                return Ok(auth.token.clone());
             }
-            AdminAuthResponse::Error { error } => {
-                bail!(error)
+        }
+
+        let mut auth = self.auth.write().await;
+        // Another request could have refreshed it while this one waited for the lock.
+        if auth.is_expiring() {
+            *auth = self.refresh(&auth.token).await?;
+        }
+
+        Ok(auth.token.clone())
+    }
+
+    /// Logs in again after pocketbase turned `rejected` down.
+    async fn reauthenticate(&self, rejected: &HeaderValue) -> anyhow::Result<HeaderValue> {
+        let mut auth = self.auth.write().await;
+        // Another request could have logged in again while this one waited for the lock.
+        if auth.token == *rejected {
+            *auth = self.login().await?;
+        }
+
+        Ok(auth.token.clone())
+    }
+
+    /// Sends a request with the admin token, retrying once with a new token on a 401.
+    async fn send(&self, req: RequestBuilder) -> anyhow::Result<Response> {
+        let token = self.token().await?;
+        let req = req.timeout(REQUEST_TIMEOUT);
+        let retry = req.try_clone();
+        let res = req.header(AUTHORIZATION, token.clone()).send().await?;
+
+        match retry {
+            Some(retry) if res.status() == StatusCode::UNAUTHORIZED => {
+                warn!("Pocketbase turned the admin token down, logging in again");
+                let token = self.reauthenticate(&token).await?;
+                Ok(retry.header(AUTHORIZATION, token).send().await?)
             }
+            _ => Ok(res),
         }
     }
 
+    /// Lists the first page of records, good enough when looking up a single one.
+    /// Use `list_all` when every record matters.
     pub async fn list<R: for<'de> Deserialize<'de> + Record>(
         &self,
         filter: Option<&str>,
+    ) -> anyhow::Result<ListResponse<R>> {
+        let params = ListParams {
+            filter,
+            ..Default::default()
+        };
+
+        self.list_with(params).await
+    }
+
+    /// Lists a single page of records.
+    pub async fn list_with<R: for<'de> Deserialize<'de> + Record>(
+        &self,
+        params: ListParams<'_>,
     ) -> anyhow::Result<ListResponse<R>> {
         let url_path = format!("/api/collections/{}/records", R::collection_name());
         let url = self.pb_url.join(&url_path)?;
 
-        let req = self.reqwest_client.get(url.as_str());
-        let req = match filter {
-            Some(filter) => req.query(&[("filter", filter)]),
-            None => req,
-        };
-        let res = req.send().await?;
+        let req = self.reqwest_client.get(url.as_str()).query(&params.query());
+        let res = self.send(req).await?;
         let list = res.json::<ListResponse<R>>().await?;
 
         Ok(list)
     }
 
+    /// Walks every page of records from `params.page`, or from the first one.
+    /// Pages are as big as pocketbase allows unless `params.per_page` says otherwise.
+    pub fn pages<'a, R: for<'de> Deserialize<'de> + Record>(
+        &'a self,
+        params: ListParams<'a>,
+    ) -> Pages<'a, R> {
+        let params = ListParams {
+            per_page: params.per_page.or(Some(MAX_PER_PAGE)),
+            ..params
+        };
+
+        Pages {
+            client: self,
+            params,
+            next_page: Some(params.page.unwrap_or(1)),
+            record: PhantomData,
+        }
+    }
+
+    /// Lists every record on every page.
+    pub async fn list_all<R: for<'de> Deserialize<'de> + Record>(
+        &self,
+        params: ListParams<'_>,
+    ) -> anyhow::Result<Vec<R>> {
+        let mut pages = self.pages::<R>(params);
+
+        let mut records = Vec::new();
+        while let Some(mut items) = pages.next().await? {
+            records.append(&mut items);
+        }
+
+        Ok(records)
+    }
+
     pub async fn view<R: for<'de> Deserialize<'de> + Serialize + Record>(
         &self,
         id: &str,
@@ -157,7 +436,7 @@ impl Client {
         let url = self.pb_url.join(&url_path)?;
 
         let req = self.reqwest_client.get(url);
-        let res = req.send().await?.json::<CVUResponse<R>>().await?;
+        let res = self.send(req).await?.json::<CVUResponse<R>>().await?;
 
         Ok(res)
     }
@@ -170,7 +449,7 @@ impl Client {
         let url = self.pb_url.join(&url_path)?;
 
         let req = self.reqwest_client.post(url).json::<R>(&record);
-        let res = req.send().await?.json::<CVUResponse<R>>().await?;
+        let res = self.send(req).await?.json::<CVUResponse<R>>().await?;
 
         Ok(res)
     }
@@ -187,8 +466,205 @@ impl Client {
         let url = self.pb_url.join(&url_path)?;
 
         let req = self.reqwest_client.patch(url.as_str()).json::<R>(&record);
-        let res = req.send().await?.json::<CVUResponse<R>>().await?;
+        let res = self.send(req).await?.json::<CVUResponse<R>>().await?;
+
+        Ok(res)
+    }
+
+    /// Updates only the fields in `fields`, a JSON object, leaving the rest of the record as is.
+    pub async fn update_fields<R: for<'de> Deserialize<'de> + Record>(
+        &self,
+        id: &str,
+        fields: &Value,
+    ) -> anyhow::Result<CVUResponse<R>> {
+        let url_path = format!("/api/collections/{}/records/{}", R::collection_name(), id);
+        let url = self.pb_url.join(&url_path)?;
+
+        let req = self.reqwest_client.patch(url).json(fields);
+        let res = self.send(req).await?.json::<CVUResponse<R>>().await?;
+
+        Ok(res)
+    }
+
+    /// Adds to number fields with pocketbase's `field+` modifier. The sum happens in pocketbase,
+    /// so increments racing each other all land instead of the last write winning.
+    pub async fn increment<R: for<'de> Deserialize<'de> + Record>(
+        &self,
+        id: &str,
+        deltas: &[(&str, u64)],
+    ) -> anyhow::Result<CVUResponse<R>> {
+        let fields = deltas
+            .iter()
+            .map(|(field, delta)| (format!("{field}+"), json!(delta)))
+            .collect::<serde_json::Map<_, _>>();
+
+        self.update_fields::<R>(id, &Value::Object(fields)).await
+    }
+
+    /// Increments the first record matching `filter`, or creates `new`, which should already
+    /// hold `deltas`.
+    ///
+    /// Like `upsert_by_filter`, relies on a unique index over the fields in `filter` for a
+    /// create that lost a race to increment the winner instead.
+    pub async fn increment_or_create<R: for<'de> Deserialize<'de> + Serialize + Record>(
+        &self,
+        filter: &str,
+        new: R,
+        deltas: &[(&str, u64)],
+    ) -> anyhow::Result<CVUResponse<R>> {
+        if let Some(record) = self.first::<R>(filter).await? {
+            return self.increment::<R>(record.id(), deltas).await;
+        }
+
+        let error = match self.create::<R>(new).await? {
+            CVUResponse::Ok { record } => return Ok(CVUResponse::Ok { record }),
+            CVUResponse::Error { error } => error,
+        };
+
+        match self.first::<R>(filter).await? {
+            Some(record) => self.increment::<R>(record.id(), deltas).await,
+            None => Ok(CVUResponse::Error { error }),
+        }
+    }
+
+    /// Opens a connection to the realtime API, a stream of server-sent events that starts with
+    /// a `PB_CONNECT` one. See <https://pocketbase.io/docs/api-realtime/>.
+    pub async fn realtime(&self) -> anyhow::Result<Response> {
+        let url = self.pb_url.join("/api/realtime")?;
+
+        let req = self
+            .reqwest_client
+            .get(url)
+            .header(ACCEPT, "text/event-stream");
+        let res = req.send().await?.error_for_status()?;
 
         Ok(res)
     }
+
+    /// Sets the topics a realtime connection gets events for, replacing the ones it had.
+    pub async fn subscribe(&self, client_id: &str, topics: &[&str]) -> anyhow::Result<()> {
+        let url = self.pb_url.join("/api/realtime")?;
+
+        let req = self
+            .reqwest_client
+            .post(url)
+            .json(&json!({"clientId": client_id, "subscriptions": topics}));
+        let res = self.send(req).await?;
+        if !res.status().is_success() {
+            bail!(res.json::<ErrorResponse>().await?);
+        }
+
+        Ok(())
+    }
+
+    pub async fn delete<R: Record>(&self, id: &str) -> anyhow::Result<()> {
+        let url_path = format!("/api/collections/{}/records/{}", R::collection_name(), id);
+        let url = self.pb_url.join(&url_path)?;
+
+        let req = self.reqwest_client.delete(url);
+        let res = self.send(req).await?;
+        if !res.status().is_success() {
+            bail!(res.json::<ErrorResponse>().await?);
+        }
+
+        Ok(())
+    }
+
+    /// Applies `apply` to the first record matching `filter`, or to `new` which then gets created.
+    ///
+    /// Relies on a unique index over the fields in `filter`, see the README for the ones the
+    /// bot needs: when another create for the same record wins the race, this one is turned
+    /// down and the winner gets updated instead.
+    pub async fn upsert_by_filter<R: for<'de> Deserialize<'de> + Serialize + Record>(
+        &self,
+        filter: &str,
+        new: R,
+        apply: impl Fn(&mut R),
+    ) -> anyhow::Result<CVUResponse<R>> {
+        if let Some(mut record) = self.first::<R>(filter).await? {
+            apply(&mut record);
+            return self.update::<R>(record).await;
+        }
+
+        let mut record = new;
+        apply(&mut record);
+        let error = match self.create::<R>(record).await? {
+            CVUResponse::Ok { record } => return Ok(CVUResponse::Ok { record }),
+            CVUResponse::Error { error } => error,
+        };
+
+        match self.first::<R>(filter).await? {
+            Some(mut record) => {
+                apply(&mut record);
+                self.update::<R>(record).await
+            }
+            None => Ok(CVUResponse::Error { error }),
+        }
+    }
+
+    /// The first record matching `filter`, or `new` once created.
+    ///
+    /// Like `upsert_by_filter`, relies on a unique index over the fields in `filter` for a
+    /// create that lost a race to get the winner instead.
+    pub async fn first_or_create<R: for<'de> Deserialize<'de> + Serialize + Record>(
+        &self,
+        filter: &str,
+        new: R,
+    ) -> anyhow::Result<CVUResponse<R>> {
+        if let Some(record) = self.first::<R>(filter).await? {
+            return Ok(CVUResponse::Ok { record });
+        }
+
+        let error = match self.create::<R>(new).await? {
+            CVUResponse::Ok { record } => return Ok(CVUResponse::Ok { record }),
+            CVUResponse::Error { error } => error,
+        };
+
+        match self.first::<R>(filter).await? {
+            Some(record) => Ok(CVUResponse::Ok { record }),
+            None => Ok(CVUResponse::Error { error }),
+        }
+    }
+
+    /// The first record matching `filter`, if any.
+    pub async fn first<R: for<'de> Deserialize<'de> + Record>(
+        &self,
+        filter: &str,
+    ) -> anyhow::Result<Option<R>> {
+        let params = ListParams {
+            per_page: Some(1),
+            filter: Some(filter),
+            ..Default::default()
+        };
+        match self.list_with::<R>(params).await? {
+            ListResponse::Ok { items, .. } => Ok(items.into_iter().next()),
+            ListResponse::Error { error } => bail!(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn auth_refreshes_most_of_the_way_into_the_token_lifetime() {
+        // Issued at 1000, expires at 2000.
+        let token = "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9.\
+            eyJpZCI6ImFkbWluIiwidHlwZSI6ImFkbWluIiwiaWF0IjoxMDAwLCJleHAiOjIwMDB9.\
+            c2lnbmF0dXJl";
+
+        let auth = Auth::new(token).unwrap();
+
+        assert_eq!(auth.refresh_at, Some(1800));
+        assert!(auth.is_expiring());
+    }
+
+    #[test]
+    fn auth_without_an_expiry_is_never_refreshed() {
+        let auth = Auth::new("not-a-jwt").unwrap();
+
+        assert_eq!(auth.refresh_at, None);
+        assert!(!auth.is_expiring());
+    }
 }
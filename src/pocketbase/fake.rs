@@ -0,0 +1,124 @@
+use std::{collections::HashMap, net::TcpListener, sync::Arc, time::Duration};
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{get, patch, post},
+    Json, Router,
+};
+use serde_json::{json, Value};
+use tokio::sync::Mutex;
+
+/// Records by collection name.
+type Collections = Arc<Mutex<HashMap<String, Vec<Value>>>>;
+
+/// How long every request takes, so that requests sent together overlap.
+const LATENCY: Duration = Duration::from_millis(5);
+
+/// Serves a fresh, empty pocketbase with the parts of the records API the `Client` uses, and
+/// gives back its URL.
+///
+/// Filters are ignored, every record of a collection matches, so tests keep to a single record
+/// per collection. A collection also takes a single record, standing in for the unique index
+/// over the fields the bot looks records up by.
+pub fn spawn() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    let app = Router::new()
+        .route("/api/admins/auth-with-password", post(auth))
+        .route(
+            "/api/collections/:collection/records",
+            get(list).post(create),
+        )
+        .route("/api/collections/:collection/records/:id", patch(update))
+        .with_state(Collections::default());
+    let server = axum::Server::from_tcp(listener)
+        .unwrap()
+        .serve(app.into_make_service());
+    tokio::spawn(server);
+
+    url
+}
+
+async fn auth() -> Json<Value> {
+    Json(json!({
+        "token": "token",
+        "admin": { "id": "admin", "created": "", "updated": "", "email": "admin@localhost" },
+    }))
+}
+
+async fn list(
+    State(collections): State<Collections>,
+    Path(collection): Path<String>,
+) -> Json<Value> {
+    tokio::time::sleep(LATENCY).await;
+
+    let collections = collections.lock().await;
+    let items = collections.get(&collection).cloned().unwrap_or_default();
+
+    Json(json!({
+        "page": 1,
+        "perPage": items.len().max(1),
+        "totalItems": items.len(),
+        "totalPages": 1,
+        "items": items,
+    }))
+}
+
+async fn create(
+    State(collections): State<Collections>,
+    Path(collection): Path<String>,
+    Json(mut record): Json<Value>,
+) -> (StatusCode, Json<Value>) {
+    tokio::time::sleep(LATENCY).await;
+
+    let mut collections = collections.lock().await;
+    let records = collections.entry(collection).or_default();
+    if !records.is_empty() {
+        let error = json!({
+            "code": 400,
+            "message": "Failed to create record.",
+            "data": { "id": { "code": "validation_not_unique", "message": "Value must be unique." } },
+        });
+        return (StatusCode::BAD_REQUEST, Json(error));
+    }
+
+    record["id"] = json!(format!("record{}", records.len()));
+    record["created"] = json!("2024-05-08 10:00:00.000Z");
+    record["updated"] = json!("2024-05-08 10:00:00.000Z");
+    records.push(record.clone());
+
+    (StatusCode::OK, Json(record))
+}
+
+/// Sets fields, or adds to them for the ones with a `+` at the end, all in one go like
+/// pocketbase does.
+async fn update(
+    State(collections): State<Collections>,
+    Path((collection, id)): Path<(String, String)>,
+    Json(fields): Json<Value>,
+) -> (StatusCode, Json<Value>) {
+    tokio::time::sleep(LATENCY).await;
+
+    let mut collections = collections.lock().await;
+    let record = collections
+        .get_mut(&collection)
+        .and_then(|records| records.iter_mut().find(|record| record["id"] == id));
+    let Some(record) = record else {
+        let error = json!({ "code": 404, "message": "Not found.", "data": {} });
+        return (StatusCode::NOT_FOUND, Json(error));
+    };
+
+    for (field, value) in fields.as_object().into_iter().flatten() {
+        match field.strip_suffix('+') {
+            Some(field) => {
+                let sum = record[field].as_u64().unwrap_or_default() + value.as_u64().unwrap();
+                record[field] = json!(sum);
+            }
+            None => record[field.as_str()] = value.clone(),
+        }
+    }
+
+    (StatusCode::OK, Json(record.clone()))
+}
@@ -1,15 +1,56 @@
-use poise::serenity_prelude::{ChannelId, GuildId};
-use tokio::sync::{mpsc, oneshot};
-use tracing::error;
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use poise::serenity_prelude::{ChannelId, GuildId, UserId};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::{
+    sync::{mpsc, oneshot, oneshot::error::TryRecvError, Semaphore},
+    task::JoinSet,
+};
+use tracing::{error, info};
 
 use crate::{
-    pocketbase::client::{CVUResponse, Client, ListResponse},
-    pocketbase::records::{GuildRecord, PlayerRecord, ScoreRecord},
+    activity::Extras,
+    milestone::MilestoneMode,
+    outbox::{self, Entry, Increment, Outbox, Outboxed},
+    period::Period,
+    pocketbase::client::{CVUResponse, Client, ListParams, ListResponse},
+    pocketbase::records::Record,
+    pocketbase::records::{
+        AchievementRecord, ChannelRecord, ChannelScoreRecord, GuildRecord, PeriodScoreRecord,
+        PlayerRecord, ScoreRecord, SeasonRecord, SessionRecord, VoiceSessionRecord,
+    },
     score::{GuildUser, ScoreType},
+    VoiceSession,
 };
 
 pub type Responder<T> = oneshot::Sender<anyhow::Result<T>>;
 
+/// How many commands are handled at the same time.
+const MAX_IN_FLIGHT: usize = 8;
+
+/// How often buffered score increments are written.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How many scores can wait for the next flush before it happens early.
+const MAX_PENDING: usize = 100;
+
+/// How long a score learned from pocketbase is added to before being read again, so changes
+/// made straight in pocketbase show up.
+const SCORE_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// How often the `Metrics` are logged.
+const METRICS_INTERVAL: Duration = Duration::from_secs(60);
+
 macro_rules! bails {
     ($tx:expr, $err:expr) => {
         error!("Bails at {:?}", $err);
@@ -43,6 +84,23 @@ macro_rules! match_list_or_bails {
     };
 }
 
+/// Like `unwrap_result_or_bails!`, but hands `Outboxed` over as is, so the caller knows the
+/// write will still happen.
+macro_rules! unwrap_written_or_bails {
+    ($tx:expr, $res:expr) => {
+        match $res {
+            Ok(value) => value,
+            Err(err) if err.is::<Outboxed>() => {
+                let _ = $tx.send(Err(err));
+                return;
+            }
+            Err(err) => {
+                bails!($tx, err);
+            }
+        }
+    };
+}
+
 macro_rules! unwrap_record_or_bails {
     ($tx:expr, $res:expr) => {
         match $res {
@@ -57,35 +115,75 @@ macro_rules! unwrap_record_or_bails {
 #[non_exhaustive]
 pub enum Command {
     IncrScore(IncrScoreParams),
+    GetScore(GetScoreParams),
+    DeleteScore(DeleteScoreParams),
     SetConfig(SetConfigParams),
     GetConfig(GetConfigParams),
+    SetSession(SetSessionParams),
+    ListSessions(ListSessionsParams),
+    LogSession(LogSessionParams),
+    ListScores(ListScoresParams),
+    GetPlayer(GetPlayerParams),
+    IncrPeriodScores(IncrPeriodScoresParams),
+    ListPeriodScores(ListPeriodScoresParams),
+    GetSeason(GetSeasonParams),
+    StartSeason(StartSeasonParams),
+    EndSeason(EndSeasonParams),
+    ListAchievements(ListAchievementsParams),
+    UnlockAchievement(UnlockAchievementParams),
+    SetMilestones(SetMilestonesParams),
+    SetActivityRules(SetActivityRulesParams),
+    IncrChannelScore(IncrChannelScoreParams),
+    ListChannelScores(ListChannelScoresParams),
+    SetChannelPeak(SetChannelPeakParams),
+    ListChannels(ListChannelsParams),
+    ForgetMember(ForgetMemberParams),
+    /// Increments buffered by the `Manager`, never sent from outside.
+    FlushScore(FlushScoreParams),
+    ReplayOutbox(ReplayOutboxParams),
+    /// Sent by the `Manager` along with buffered increments ending a session.
+    RaiseLongestSession(RaiseLongestSessionParams),
 }
 
 impl Command {
     pub fn new_incr_score(
         member: GuildUser,
         delta: u64,
+        session_length: Option<u64>,
+        extras: Extras,
         resp_tx: Responder<ScoreRecord>,
         score_type: ScoreType,
     ) -> Self {
         Self::IncrScore(IncrScoreParams {
             member,
             delta,
+            session_length,
+            extras,
             resp_tx,
             score_type,
         })
     }
 
+    pub fn new_get_score(member: GuildUser, resp_tx: Responder<Option<ScoreRecord>>) -> Self {
+        Self::GetScore(GetScoreParams { member, resp_tx })
+    }
+
+    pub fn new_delete_score(member: GuildUser, resp_tx: Responder<()>) -> Self {
+        Self::DeleteScore(DeleteScoreParams { member, resp_tx })
+    }
+
     pub fn new_set_config(
         guild_id: GuildId,
         afk_channel: Option<ChannelId>,
         graveyard: Option<ChannelId>,
+        achievement_channel: Option<ChannelId>,
         resp_tx: Responder<GuildRecord>,
     ) -> Self {
         Self::SetConfig(SetConfigParams {
             guild_id,
             afk_channel,
             graveyard,
+            achievement_channel,
             resp_tx,
         })
     }
@@ -93,19 +191,298 @@ impl Command {
     pub fn new_get_config(guild_id: GuildId, resp_tx: Responder<GuildRecord>) -> Self {
         Self::GetConfig(GetConfigParams { guild_id, resp_tx })
     }
+
+    pub fn new_open_session(
+        member: GuildUser,
+        score_type: ScoreType,
+        session: VoiceSession,
+        resp_tx: Responder<SessionRecord>,
+    ) -> Self {
+        Self::SetSession(SetSessionParams {
+            member,
+            session: Some((score_type, session)),
+            resp_tx,
+        })
+    }
+
+    pub fn new_close_session(member: GuildUser, resp_tx: Responder<SessionRecord>) -> Self {
+        Self::SetSession(SetSessionParams {
+            member,
+            session: None,
+            resp_tx,
+        })
+    }
+
+    pub fn new_list_sessions(guild_id: GuildId, resp_tx: Responder<Vec<SessionRecord>>) -> Self {
+        Self::ListSessions(ListSessionsParams { guild_id, resp_tx })
+    }
+
+    pub fn new_log_session(
+        member: GuildUser,
+        score_type: ScoreType,
+        session: VoiceSession,
+        ended_at: DateTime<Utc>,
+        resp_tx: Responder<VoiceSessionRecord>,
+    ) -> Self {
+        Self::LogSession(LogSessionParams {
+            member,
+            score_type,
+            session,
+            ended_at,
+            resp_tx,
+        })
+    }
+
+    pub fn new_list_scores(
+        guild_id: GuildId,
+        resp_tx: Responder<Vec<(PlayerRecord, ScoreRecord)>>,
+    ) -> Self {
+        Self::ListScores(ListScoresParams { guild_id, resp_tx })
+    }
+
+    pub fn new_get_player(user_id: UserId, resp_tx: Responder<Option<PlayerRecord>>) -> Self {
+        Self::GetPlayer(GetPlayerParams { user_id, resp_tx })
+    }
+
+    pub fn new_incr_period_scores(
+        member: GuildUser,
+        delta: u64,
+        score_type: ScoreType,
+        buckets: Vec<(Period, String)>,
+        resp_tx: Responder<()>,
+    ) -> Self {
+        Self::IncrPeriodScores(IncrPeriodScoresParams {
+            member,
+            delta,
+            score_type,
+            buckets,
+            resp_tx,
+        })
+    }
+
+    pub fn new_list_period_scores(
+        guild_id: GuildId,
+        period: Period,
+        bucket: String,
+        resp_tx: Responder<Vec<PeriodScoreRecord>>,
+    ) -> Self {
+        Self::ListPeriodScores(ListPeriodScoresParams {
+            guild_id,
+            period,
+            bucket,
+            resp_tx,
+        })
+    }
+
+    pub fn new_get_season(guild_id: GuildId, resp_tx: Responder<Option<SeasonRecord>>) -> Self {
+        Self::GetSeason(GetSeasonParams { guild_id, resp_tx })
+    }
+
+    pub fn new_start_season(
+        guild_id: GuildId,
+        name: String,
+        started_at: DateTime<Utc>,
+        resp_tx: Responder<SeasonRecord>,
+    ) -> Self {
+        Self::StartSeason(StartSeasonParams {
+            guild_id,
+            name,
+            started_at,
+            resp_tx,
+        })
+    }
+
+    pub fn new_end_season(
+        guild_id: GuildId,
+        ended_at: DateTime<Utc>,
+        resp_tx: Responder<SeasonRecord>,
+    ) -> Self {
+        Self::EndSeason(EndSeasonParams {
+            guild_id,
+            ended_at,
+            resp_tx,
+        })
+    }
+
+    pub fn new_list_achievements(
+        member: GuildUser,
+        resp_tx: Responder<Vec<AchievementRecord>>,
+    ) -> Self {
+        Self::ListAchievements(ListAchievementsParams { member, resp_tx })
+    }
+
+    pub fn new_unlock_achievement(
+        member: GuildUser,
+        achievement: &'static str,
+        unlocked_at: DateTime<Utc>,
+        resp_tx: Responder<AchievementRecord>,
+    ) -> Self {
+        Self::UnlockAchievement(UnlockAchievementParams {
+            member,
+            achievement,
+            unlocked_at,
+            resp_tx,
+        })
+    }
+
+    pub fn new_set_milestones(
+        guild_id: GuildId,
+        milestone_roles: Option<Value>,
+        mode: Option<MilestoneMode>,
+        resp_tx: Responder<GuildRecord>,
+    ) -> Self {
+        Self::SetMilestones(SetMilestonesParams {
+            guild_id,
+            milestone_roles,
+            mode,
+            resp_tx,
+        })
+    }
+
+    pub fn new_set_activity_rules(
+        guild_id: GuildId,
+        activity_rules: Value,
+        resp_tx: Responder<GuildRecord>,
+    ) -> Self {
+        Self::SetActivityRules(SetActivityRulesParams {
+            guild_id,
+            activity_rules,
+            resp_tx,
+        })
+    }
+
+    pub fn new_incr_channel_score(
+        member: GuildUser,
+        channel_id: ChannelId,
+        delta: u64,
+        resp_tx: Responder<ChannelScoreRecord>,
+    ) -> Self {
+        Self::IncrChannelScore(IncrChannelScoreParams {
+            member,
+            channel_id,
+            delta,
+            resp_tx,
+        })
+    }
+
+    pub fn new_list_channel_scores(
+        guild_id: GuildId,
+        user_id: Option<UserId>,
+        resp_tx: Responder<Vec<ChannelScoreRecord>>,
+    ) -> Self {
+        Self::ListChannelScores(ListChannelScoresParams {
+            guild_id,
+            user_id,
+            resp_tx,
+        })
+    }
+
+    pub fn new_set_channel_peak(
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        users: u64,
+        at: DateTime<Utc>,
+        resp_tx: Responder<ChannelRecord>,
+    ) -> Self {
+        Self::SetChannelPeak(SetChannelPeakParams {
+            guild_id,
+            channel_id,
+            users,
+            at,
+            resp_tx,
+        })
+    }
+
+    pub fn new_list_channels(guild_id: GuildId, resp_tx: Responder<Vec<ChannelRecord>>) -> Self {
+        Self::ListChannels(ListChannelsParams { guild_id, resp_tx })
+    }
+
+    pub fn new_forget_member(member: GuildUser, resp_tx: Responder<()>) -> Self {
+        Self::ForgetMember(ForgetMemberParams { member, resp_tx })
+    }
+
+    pub fn new_replay_outbox(entry: Entry, resp_tx: Responder<bool>) -> Self {
+        Self::ReplayOutbox(ReplayOutboxParams { entry, resp_tx })
+    }
+
+    /// What a command writes to, if anything. Writes to the same member or to the same guild's
+    /// records are handled one after the other, in the order they were sent.
+    fn write_key(&self) -> Option<WriteKey> {
+        let key = match self {
+            Command::IncrScore(param) => WriteKey::Member(param.member),
+            Command::DeleteScore(param) => WriteKey::Member(param.member),
+            Command::SetSession(param) => WriteKey::Member(param.member),
+            Command::LogSession(param) => WriteKey::Member(param.member),
+            Command::IncrPeriodScores(param) => WriteKey::Member(param.member),
+            Command::UnlockAchievement(param) => WriteKey::Member(param.member),
+            Command::IncrChannelScore(param) => WriteKey::Member(param.member),
+            Command::ForgetMember(param) => WriteKey::Member(param.member),
+            Command::FlushScore(param) => WriteKey::Member(param.member),
+            Command::ReplayOutbox(param) => WriteKey::Member(param.entry.increment.member()),
+            Command::RaiseLongestSession(param) => WriteKey::Member(param.member),
+            Command::SetConfig(param) => WriteKey::Guild(param.guild_id),
+            Command::SetMilestones(param) => WriteKey::Guild(param.guild_id),
+            Command::SetActivityRules(param) => WriteKey::Guild(param.guild_id),
+            Command::SetChannelPeak(param) => WriteKey::Guild(param.guild_id),
+            Command::StartSeason(param) => WriteKey::Guild(param.guild_id),
+            Command::EndSeason(param) => WriteKey::Guild(param.guild_id),
+            _ => return None,
+        };
+
+        Some(key)
+    }
+}
+
+/// See `Command::write_key`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum WriteKey {
+    Member(GuildUser),
+    /// The guild's own records, like its config, channels and seasons.
+    Guild(GuildId),
 }
 
 pub struct IncrScoreParams {
     member: GuildUser,
     delta: u64,
+    session_length: Option<u64>,
+    extras: Extras,
     resp_tx: Responder<ScoreRecord>,
     score_type: ScoreType,
 }
 
+pub struct FlushScoreParams {
+    member: GuildUser,
+    deltas: Vec<(&'static str, u64)>,
+    resp_tx: Responder<ScoreRecord>,
+}
+
+pub struct ReplayOutboxParams {
+    /// As listed, it is read again once claimed.
+    entry: Entry,
+    resp_tx: Responder<bool>,
+}
+
+pub struct RaiseLongestSessionParams {
+    member: GuildUser,
+    session_length: u64,
+    resp_tx: Responder<()>,
+}
+
+pub struct GetScoreParams {
+    member: GuildUser,
+    resp_tx: Responder<Option<ScoreRecord>>,
+}
+
+pub struct DeleteScoreParams {
+    member: GuildUser,
+    resp_tx: Responder<()>,
+}
+
 pub struct SetConfigParams {
     guild_id: GuildId,
     afk_channel: Option<ChannelId>,
     graveyard: Option<ChannelId>,
+    achievement_channel: Option<ChannelId>,
     resp_tx: Responder<GuildRecord>,
 }
 
@@ -114,185 +491,1698 @@ pub struct GetConfigParams {
     resp_tx: Responder<GuildRecord>,
 }
 
-pub struct Manager {
-    pub client: Client,
+pub struct SetSessionParams {
+    member: GuildUser,
+    session: Option<(ScoreType, VoiceSession)>,
+    resp_tx: Responder<SessionRecord>,
 }
 
-impl Manager {
-    pub fn new(client: Client) -> Self {
-        Manager { client }
-    }
-    pub fn spawn(&self, mut rx: mpsc::Receiver<Command>) {
-        let client = self.client.clone();
-        tokio::spawn(async move {
-            while let Some(cmd) = rx.recv().await {
-                command_handler(client.clone(), cmd).await;
-            }
-        });
-    }
+pub struct ListSessionsParams {
+    guild_id: GuildId,
+    resp_tx: Responder<Vec<SessionRecord>>,
 }
 
-async fn command_handler(client: Client, cmd: Command) {
-    match cmd {
-        Command::IncrScore(param) => incr_score_handler(client, param).await,
-        Command::SetConfig(param) => set_config_handler(client, param).await,
-        Command::GetConfig(param) => get_config_handler(client, param).await,
-    };
+pub struct LogSessionParams {
+    member: GuildUser,
+    score_type: ScoreType,
+    session: VoiceSession,
+    ended_at: DateTime<Utc>,
+    resp_tx: Responder<VoiceSessionRecord>,
 }
 
-async fn incr_score_handler(client: Client, param: IncrScoreParams) {
-    let IncrScoreParams {
-        member,
-        delta,
-        resp_tx,
-        score_type,
-    } = param;
-
-    let filter = format!(
-        "guild.server_id = \"{}\" && player.user_id = \"{}\"",
-        member.0, member.1
-    );
+pub struct ListScoresParams {
+    guild_id: GuildId,
+    resp_tx: Responder<Vec<(PlayerRecord, ScoreRecord)>>,
+}
 
-    let res = client.list::<ScoreRecord>(Some(filter.as_str())).await;
-    let list_score_res = unwrap_result_or_bails!(resp_tx, res);
+pub struct GetPlayerParams {
+    user_id: UserId,
+    resp_tx: Responder<Option<PlayerRecord>>,
+}
 
-    match_list_or_bails!(resp_tx, list_score_res, {
-        let mut items = list_score_res.unwrap();
+pub struct IncrPeriodScoresParams {
+    member: GuildUser,
+    delta: u64,
+    score_type: ScoreType,
+    buckets: Vec<(Period, String)>,
+    resp_tx: Responder<()>,
+}
 
-        if !items.is_empty() {
-            let mut score = items.pop().unwrap();
-            match score_type {
-                ScoreType::Voice => score.voice_time += delta,
-                ScoreType::Afk => score.afk_time += delta,
-            }
+pub struct ListPeriodScoresParams {
+    guild_id: GuildId,
+    period: Period,
+    bucket: String,
+    resp_tx: Responder<Vec<PeriodScoreRecord>>,
+}
 
-            let res = client.update::<ScoreRecord>(score).await;
-            let record_res = unwrap_result_or_bails!(resp_tx, res);
-            let record = unwrap_record_or_bails!(resp_tx, record_res);
+pub struct GetSeasonParams {
+    guild_id: GuildId,
+    resp_tx: Responder<Option<SeasonRecord>>,
+}
 
-            let _ = resp_tx.send(Ok(record));
-        } else {
-            let guild_record = {
-                let filter = format!("server_id = \"{}\"", member.0);
-                let res = client.list::<GuildRecord>(Some(filter.as_str())).await;
-
-                let list_guild_res = unwrap_result_or_bails!(resp_tx, res);
-
-                match_list_or_bails!(resp_tx, list_guild_res, {
-                    let mut items = list_guild_res.unwrap();
-
-                    if !items.is_empty() {
-                        items.pop().unwrap()
-                    } else {
-                        let guild_record = GuildRecord::new(member.0.to_string(), None, None);
-                        let res = client.create::<GuildRecord>(guild_record).await;
-                        let record_res = unwrap_result_or_bails!(resp_tx, res);
-                        unwrap_record_or_bails!(resp_tx, record_res)
-                    }
-                })
-            };
+pub struct StartSeasonParams {
+    guild_id: GuildId,
+    name: String,
+    started_at: DateTime<Utc>,
+    resp_tx: Responder<SeasonRecord>,
+}
 
-            let player_record = {
-                let filter = format!("user_id = \"{}\"", member.1);
-                let res = client.list::<PlayerRecord>(Some(filter.as_str())).await;
+pub struct EndSeasonParams {
+    guild_id: GuildId,
+    ended_at: DateTime<Utc>,
+    resp_tx: Responder<SeasonRecord>,
+}
 
-                let list_guild_res = unwrap_result_or_bails!(resp_tx, res);
+pub struct SetMilestonesParams {
+    guild_id: GuildId,
+    milestone_roles: Option<Value>,
+    mode: Option<MilestoneMode>,
+    resp_tx: Responder<GuildRecord>,
+}
 
-                match_list_or_bails!(resp_tx, list_guild_res, {
-                    let mut items = list_guild_res.unwrap();
+pub struct SetActivityRulesParams {
+    guild_id: GuildId,
+    activity_rules: Value,
+    resp_tx: Responder<GuildRecord>,
+}
 
-                    if !items.is_empty() {
-                        items.pop().unwrap()
-                    } else {
-                        let guild_record = PlayerRecord::new(member.0.to_string());
-                        let res = client.create::<PlayerRecord>(guild_record).await;
-                        let record_res = unwrap_result_or_bails!(resp_tx, res);
-                        unwrap_record_or_bails!(resp_tx, record_res)
-                    }
-                })
-            };
+pub struct IncrChannelScoreParams {
+    member: GuildUser,
+    channel_id: ChannelId,
+    delta: u64,
+    resp_tx: Responder<ChannelScoreRecord>,
+}
 
-            let mut score_record = ScoreRecord {
-                guild: guild_record.default.id.clone(),
-                player: player_record.default.id.clone(),
-                ..Default::default()
-            };
-            match score_type {
-                ScoreType::Voice => score_record.voice_time += delta,
-                ScoreType::Afk => score_record.afk_time += delta,
-            }
+pub struct ListChannelScoresParams {
+    guild_id: GuildId,
+    user_id: Option<UserId>,
+    resp_tx: Responder<Vec<ChannelScoreRecord>>,
+}
 
-            let res = client.create::<ScoreRecord>(score_record).await;
-            let record_res = unwrap_result_or_bails!(resp_tx, res);
-            let record = unwrap_record_or_bails!(resp_tx, record_res);
+pub struct SetChannelPeakParams {
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    users: u64,
+    at: DateTime<Utc>,
+    resp_tx: Responder<ChannelRecord>,
+}
 
-            let _ = resp_tx.send(Ok(record));
-        }
-    });
+pub struct ListChannelsParams {
+    guild_id: GuildId,
+    resp_tx: Responder<Vec<ChannelRecord>>,
 }
 
-async fn set_config_handler(client: Client, param: SetConfigParams) {
-    let SetConfigParams {
-        guild_id,
-        afk_channel,
-        graveyard,
-        resp_tx,
-    } = param;
+pub struct ForgetMemberParams {
+    member: GuildUser,
+    resp_tx: Responder<()>,
+}
 
-    let filter = format!("server_id = \"{}\"", guild_id);
+pub struct ListAchievementsParams {
+    member: GuildUser,
+    resp_tx: Responder<Vec<AchievementRecord>>,
+}
+
+pub struct UnlockAchievementParams {
+    member: GuildUser,
+    achievement: &'static str,
+    unlocked_at: DateTime<Utc>,
+    resp_tx: Responder<AchievementRecord>,
+}
+
+/// How the `Manager` keeps up, shared with whoever reports on it.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// Taken off the channel, waiting for a free slot or for an earlier write of the member.
+    waiting: AtomicUsize,
+    in_flight: AtomicUsize,
+    handled: AtomicU64,
+    /// Score increments answered from the buffer instead of going to pocketbase right away.
+    buffered: AtomicU64,
+    /// Scores with increments waiting for the next flush.
+    pending: AtomicUsize,
+    /// In microseconds, from being taken off the channel to done.
+    total_latency: AtomicU64,
+    max_latency: AtomicU64,
+}
+
+impl Metrics {
+    fn record(&self, latency: Duration) {
+        let latency = latency.as_micros() as u64;
+
+        self.handled.fetch_add(1, Ordering::Relaxed);
+        self.total_latency.fetch_add(latency, Ordering::Relaxed);
+        self.max_latency.fetch_max(latency, Ordering::Relaxed);
+    }
+
+    pub fn waiting(&self) -> usize {
+        self.waiting.load(Ordering::Relaxed)
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+
+    pub fn handled(&self) -> u64 {
+        self.handled.load(Ordering::Relaxed)
+    }
+
+    pub fn buffered(&self) -> u64 {
+        self.buffered.load(Ordering::Relaxed)
+    }
+
+    pub fn pending(&self) -> usize {
+        self.pending.load(Ordering::Relaxed)
+    }
+
+    pub fn mean_latency(&self) -> Duration {
+        let total = self.total_latency.load(Ordering::Relaxed);
+        Duration::from_micros(total.checked_div(self.handled()).unwrap_or_default())
+    }
+
+    pub fn max_latency(&self) -> Duration {
+        Duration::from_micros(self.max_latency.load(Ordering::Relaxed))
+    }
+
+    /// Logs every figure as a field of the `pocketbase::metrics` target, for whatever collects
+    /// the logs to pick up.
+    fn report(&self) {
+        info!(
+            target: "pocketbase::metrics",
+            waiting = self.waiting(),
+            in_flight = self.in_flight(),
+            handled = self.handled(),
+            buffered = self.buffered(),
+            pending = self.pending(),
+            mean_latency_ms = self.mean_latency().as_millis() as u64,
+            max_latency_ms = self.max_latency().as_millis() as u64,
+            "Pocketbase manager metrics"
+        );
+    }
+}
+
+pub struct Manager {
+    pub client: Client,
+    metrics: Arc<Metrics>,
+    outbox: Outbox,
+}
+
+impl Manager {
+    pub fn new(client: Client, metrics: Arc<Metrics>, outbox: Outbox) -> Self {
+        Manager {
+            client,
+            metrics,
+            outbox,
+        }
+    }
+
+    /// Handles up to `MAX_IN_FLIGHT` commands at once, with the writes of a member, or to a
+    /// guild's own records, still going through one at a time.
+    ///
+    /// Score increments of members whose score is known are answered right away and written
+    /// every `FLUSH_INTERVAL`, when `MAX_PENDING` scores are waiting, or on `Shutdown`, see
+    /// `ScoreBuffer`.
+    pub fn spawn(&self, mut rx: mpsc::Receiver<Command>, mut stop_rx: StopReceiver) {
+        let mut dispatcher = Dispatcher::new(
+            self.client.clone(),
+            self.metrics.clone(),
+            self.outbox.clone(),
+        );
+        let (learned_tx, mut learned_rx) = mpsc::unbounded_channel();
+        let mut buffer = ScoreBuffer::new(self.metrics.clone(), learned_tx);
+        let metrics = self.metrics.clone();
+        tokio::spawn(async move {
+            let mut flush_interval = tokio::time::interval(FLUSH_INTERVAL);
+            let mut metrics_interval = tokio::time::interval(METRICS_INTERVAL);
+
+            loop {
+                tokio::select! {
+                    cmd = rx.recv() => {
+                        let Some(cmd) = cmd else {
+                            break;
+                        };
+                        if let Some(cmd) = buffer.intercept(cmd) {
+                            dispatcher.dispatch(cmd);
+                        }
+                        if buffer.pending.len() >= MAX_PENDING {
+                            dispatcher.flush(&mut buffer);
+                        }
+                    }
+                    Some((member, record)) = learned_rx.recv() => buffer.learn(member, record),
+                    _ = flush_interval.tick() => dispatcher.flush(&mut buffer),
+                    _ = metrics_interval.tick() => metrics.report(),
+                    Some(done) = stop_rx.0.recv() => {
+                        info!("Writing buffered scores before stopping");
+                        dispatcher.flush(&mut buffer);
+                        dispatcher.wait_idle().await;
+                        let _ = done.send(());
+                        return;
+                    }
+                }
+            }
+
+            // Every sender is gone, nothing can be buffered anymore.
+            dispatcher.flush(&mut buffer);
+            dispatcher.wait_idle().await;
+        });
+    }
+}
+
+/// Stops the `Manager` once the bot is done with it, see `Shutdown::flush_and_wait`.
+#[derive(Debug, Clone)]
+pub struct Shutdown(mpsc::Sender<oneshot::Sender<()>>);
+
+/// What the `Manager` listens on for its `Shutdown`.
+#[derive(Debug)]
+pub struct StopReceiver(mpsc::Receiver<oneshot::Sender<()>>);
+
+impl Shutdown {
+    pub fn new() -> (Self, StopReceiver) {
+        let (tx, rx) = mpsc::channel(1);
+        (Shutdown(tx), StopReceiver(rx))
+    }
+
+    /// Writes the buffered scores and waits for every command handed over so far to be done,
+    /// the `Manager` takes no more after that. Returns right away if it isn't running.
+    pub async fn flush_and_wait(&self) {
+        let (done_tx, done_rx) = oneshot::channel();
+        if self.0.send(done_tx).await.is_ok() {
+            let _ = done_rx.await;
+        }
+    }
+}
+
+/// Hands commands over to tasks, see `Manager::spawn`.
+struct Dispatcher {
+    client: Client,
+    metrics: Arc<Metrics>,
+    outbox: Outbox,
+    permits: Arc<Semaphore>,
+    tasks: JoinSet<()>,
+    /// Closes once the last write sent for the member or guild is done.
+    last_writes: HashMap<WriteKey, oneshot::Receiver<()>>,
+}
+
+impl Dispatcher {
+    fn new(client: Client, metrics: Arc<Metrics>, outbox: Outbox) -> Self {
+        Dispatcher {
+            client,
+            metrics,
+            outbox,
+            permits: Arc::new(Semaphore::new(MAX_IN_FLIGHT)),
+            tasks: JoinSet::new(),
+            last_writes: HashMap::new(),
+        }
+    }
+
+    /// Handles `cmd` in its own task, once the earlier writes to the same thing are done and a
+    /// slot is free.
+    fn dispatch(&mut self, cmd: Command) {
+        let received = Instant::now();
+        self.metrics.waiting.fetch_add(1, Ordering::Relaxed);
+
+        while self.tasks.try_join_next().is_some() {}
+        self.last_writes
+            .retain(|_, done| matches!(done.try_recv(), Err(TryRecvError::Empty)));
+        let (previous, done) = match cmd.write_key() {
+            Some(key) => {
+                let (done_tx, done_rx) = oneshot::channel::<()>();
+                (self.last_writes.insert(key, done_rx), Some(done_tx))
+            }
+            None => (None, None),
+        };
+
+        let client = self.client.clone();
+        let outbox = self.outbox.clone();
+        let metrics = self.metrics.clone();
+        let permits = self.permits.clone();
+        self.tasks.spawn(async move {
+            // Waiting on the previous write doesn't hold a slot another command could use.
+            if let Some(previous) = previous {
+                let _ = previous.await;
+            }
+            let Ok(permit) = permits.acquire_owned().await else {
+                return;
+            };
+            metrics.waiting.fetch_sub(1, Ordering::Relaxed);
+            metrics.in_flight.fetch_add(1, Ordering::Relaxed);
+
+            command_handler(client, outbox, cmd).await;
+
+            metrics.in_flight.fetch_sub(1, Ordering::Relaxed);
+            metrics.record(received.elapsed());
+            drop(done);
+            drop(permit);
+        });
+    }
+
+    fn flush(&mut self, buffer: &mut ScoreBuffer) {
+        for cmd in buffer.take() {
+            self.dispatch(cmd);
+        }
+    }
+
+    /// Waits for every command handed over so far to be done.
+    async fn wait_idle(&mut self) {
+        while self.tasks.join_next().await.is_some() {}
+    }
+}
+
+/// What is left to add to a score, see `ScoreBuffer`.
+type PendingScore = Vec<(&'static str, u64)>;
+
+/// A score as pocketbase gave it back, with the buffered increments added.
+#[derive(Debug)]
+struct KnownScore {
+    record: ScoreRecord,
+    learned_at: Instant,
+}
+
+/// Score increments waiting to be written, coalesced by member and score type.
+///
+/// Only members whose score was read from pocketbase in the last `SCORE_TTL` get buffered,
+/// their first increment goes to pocketbase as usual and the score it gives back is what later
+/// ones add to. Pending increments are only kept in memory, a flush puts them in the outbox
+/// along with its write like any other, so a flush that fails leaves them there to be
+/// replayed. A crash loses up to `FLUSH_INTERVAL` of them.
+struct ScoreBuffer {
+    metrics: Arc<Metrics>,
+    /// Scores pocketbase gave back, or `None` when a flush of the member failed.
+    learned_tx: mpsc::UnboundedSender<(GuildUser, Option<ScoreRecord>)>,
+    pending: HashMap<(GuildUser, ScoreType), PendingScore>,
+    /// Members' scores as they are once everything pending is written.
+    scores: HashMap<GuildUser, KnownScore>,
+}
+
+impl ScoreBuffer {
+    fn new(
+        metrics: Arc<Metrics>,
+        learned_tx: mpsc::UnboundedSender<(GuildUser, Option<ScoreRecord>)>,
+    ) -> Self {
+        ScoreBuffer {
+            metrics,
+            learned_tx,
+            pending: HashMap::new(),
+            scores: HashMap::new(),
+        }
+    }
+
+    /// Answers what the buffer can, giving back the commands that still have to be handled.
+    fn intercept(&mut self, cmd: Command) -> Option<Command> {
+        match cmd {
+            Command::IncrScore(param) => {
+                if self.score(param.member).is_none() {
+                    return Some(Command::IncrScore(learn_from(param, &self.learned_tx)));
+                }
+
+                let deltas = score_deltas(param.score_type, param.delta, param.extras);
+                let pending = self
+                    .pending
+                    .entry((param.member, param.score_type))
+                    .or_default();
+                for (field, delta) in &deltas {
+                    match pending.iter_mut().find(|(f, _)| f == field) {
+                        Some((_, total)) => *total += delta,
+                        None => pending.push((field, *delta)),
+                    }
+                }
+
+                self.metrics.buffered.fetch_add(1, Ordering::Relaxed);
+                self.update_pending();
+
+                let score = self.score(param.member).unwrap();
+                add_deltas(score, &deltas);
+
+                // Written right away, a session only ends once in a while.
+                let Some(session_length) = param.session_length else {
+                    let _ = param.resp_tx.send(Ok(score.clone()));
+                    return None;
+                };
+                score.longest_session = score.longest_session.max(session_length);
+                let _ = param.resp_tx.send(Ok(score.clone()));
+
+                let (resp_tx, _) = oneshot::channel();
+                let cmd = Command::RaiseLongestSession(RaiseLongestSessionParams {
+                    member: param.member,
+                    session_length,
+                    resp_tx,
+                });
+                Some(cmd)
+            }
+            Command::GetScore(param) => match self.score(param.member) {
+                Some(score) => {
+                    let _ = param.resp_tx.send(Ok(Some(score.clone())));
+                    None
+                }
+                None => Some(Command::GetScore(param)),
+            },
+            Command::DeleteScore(param) => {
+                self.drop_member(param.member);
+                Some(Command::DeleteScore(param))
+            }
+            Command::ForgetMember(param) => {
+                self.drop_member(param.member);
+                Some(Command::ForgetMember(param))
+            }
+            cmd => Some(cmd),
+        }
+    }
+
+    /// The member's known score, unless it was learned too long ago.
+    fn score(&mut self, member: GuildUser) -> Option<&mut ScoreRecord> {
+        let fresh = self
+            .scores
+            .get(&member)
+            .is_some_and(|known| known.learned_at.elapsed() < SCORE_TTL);
+        if !fresh {
+            self.scores.remove(&member);
+            return None;
+        }
+
+        self.scores.get_mut(&member).map(|known| &mut known.record)
+    }
+
+    /// Forgets the member's score along with what is pending for it, which is about to be
+    /// deleted anyway.
+    fn drop_member(&mut self, member: GuildUser) {
+        self.scores.remove(&member);
+        self.pending.retain(|(m, _), _| *m != member);
+        self.update_pending();
+    }
+
+    /// Keeps the score pocketbase gave back, unless increments are already pending on top of
+    /// it. A failed flush forgets the score instead, until pocketbase gives one back again.
+    fn learn(&mut self, member: GuildUser, record: Option<ScoreRecord>) {
+        let Some(record) = record else {
+            self.scores.remove(&member);
+            return;
+        };
+        if !self.pending.keys().any(|(m, _)| *m == member) {
+            let known = KnownScore {
+                record,
+                learned_at: Instant::now(),
+            };
+            self.scores.insert(member, known);
+        }
+    }
+
+    /// Takes every pending increment out, as the commands writing them.
+    fn take(&mut self) -> Vec<Command> {
+        self.scores
+            .retain(|_, known| known.learned_at.elapsed() < SCORE_TTL);
+
+        let cmds = self
+            .pending
+            .drain()
+            .map(|((member, _), pending)| {
+                let (resp_tx, rx) = oneshot::channel::<anyhow::Result<ScoreRecord>>();
+                let learned_tx = self.learned_tx.clone();
+                tokio::spawn(async move {
+                    if let Ok(res) = rx.await {
+                        let _ = learned_tx.send((member, res.ok()));
+                    }
+                });
+
+                Command::FlushScore(FlushScoreParams {
+                    member,
+                    deltas: pending,
+                    resp_tx,
+                })
+            })
+            .collect();
+        self.update_pending();
+
+        cmds
+    }
+
+    fn update_pending(&self) {
+        self.metrics
+            .pending
+            .store(self.pending.len(), Ordering::Relaxed);
+    }
+}
+
+/// Sends the score `param` ends up with to `learned_tx` before answering.
+fn learn_from(
+    mut param: IncrScoreParams,
+    learned_tx: &mpsc::UnboundedSender<(GuildUser, Option<ScoreRecord>)>,
+) -> IncrScoreParams {
+    let (tx, rx) = oneshot::channel();
+    let resp_tx = std::mem::replace(&mut param.resp_tx, tx);
+    let learned_tx = learned_tx.clone();
+    let member = param.member;
+
+    tokio::spawn(async move {
+        let Ok(res) = rx.await else {
+            return;
+        };
+        if let Ok(record) = &res {
+            let _ = learned_tx.send((member, Some(record.clone())));
+        }
+        let _ = resp_tx.send(res);
+    });
+
+    param
+}
+
+async fn command_handler(client: Client, outbox: Outbox, cmd: Command) {
+    match cmd {
+        Command::IncrScore(param) => incr_score_handler(client, outbox, param).await,
+        Command::GetScore(param) => get_score_handler(client, param).await,
+        Command::DeleteScore(param) => delete_score_handler(client, param).await,
+        Command::SetConfig(param) => set_config_handler(client, param).await,
+        Command::GetConfig(param) => get_config_handler(client, param).await,
+        Command::SetSession(param) => set_session_handler(client, param).await,
+        Command::ListSessions(param) => list_sessions_handler(client, param).await,
+        Command::LogSession(param) => log_session_handler(client, outbox, param).await,
+        Command::ListScores(param) => list_scores_handler(client, param).await,
+        Command::GetPlayer(param) => get_player_handler(client, param).await,
+        Command::IncrPeriodScores(param) => incr_period_scores_handler(client, outbox, param).await,
+        Command::ListPeriodScores(param) => list_period_scores_handler(client, param).await,
+        Command::GetSeason(param) => get_season_handler(client, param).await,
+        Command::StartSeason(param) => start_season_handler(client, param).await,
+        Command::EndSeason(param) => end_season_handler(client, param).await,
+        Command::ListAchievements(param) => list_achievements_handler(client, param).await,
+        Command::UnlockAchievement(param) => unlock_achievement_handler(client, param).await,
+        Command::SetMilestones(param) => set_milestones_handler(client, param).await,
+        Command::SetActivityRules(param) => set_activity_rules_handler(client, param).await,
+        Command::IncrChannelScore(param) => incr_channel_score_handler(client, outbox, param).await,
+        Command::ListChannelScores(param) => list_channel_scores_handler(client, param).await,
+        Command::SetChannelPeak(param) => set_channel_peak_handler(client, param).await,
+        Command::ListChannels(param) => list_channels_handler(client, param).await,
+        Command::ForgetMember(param) => forget_member_handler(client, param).await,
+        Command::FlushScore(param) => flush_score_handler(client, outbox, param).await,
+        Command::ReplayOutbox(param) => replay_outbox_handler(client, outbox, param).await,
+        Command::RaiseLongestSession(param) => {
+            raise_longest_session_handler(client, outbox, param).await
+        }
+    };
+}
+
+async fn incr_score_handler(client: Client, outbox: Outbox, param: IncrScoreParams) {
+    let IncrScoreParams {
+        member,
+        delta,
+        session_length,
+        extras,
+        resp_tx,
+        score_type,
+    } = param;
+
+    let deltas = score_deltas(score_type, delta, extras);
+    let increment = score_increment(member, &deltas);
+    let write = write_score(&client, member, &deltas);
+    let res = write_through(&outbox, increment, write).await;
+    let mut record = unwrap_written_or_bails!(resp_tx, res);
+
+    if let Some(session_length) = session_length {
+        let res = raise_longest_session(&client, &outbox, member, session_length).await;
+        // The score is written either way, what's left is in the outbox.
+        if let Err(err) = outbox::or_outboxed(res) {
+            bails!(resp_tx, err);
+        }
+        record.longest_session = record.longest_session.max(session_length);
+    }
+
+    let _ = resp_tx.send(Ok(record));
+}
+
+async fn raise_longest_session_handler(
+    client: Client,
+    outbox: Outbox,
+    param: RaiseLongestSessionParams,
+) {
+    let RaiseLongestSessionParams {
+        member,
+        session_length,
+        resp_tx,
+    } = param;
+
+    let res = raise_longest_session(&client, &outbox, member, session_length).await;
+    unwrap_written_or_bails!(resp_tx, res);
+
+    let _ = resp_tx.send(Ok(()));
+}
+
+async fn flush_score_handler(client: Client, outbox: Outbox, param: FlushScoreParams) {
+    let FlushScoreParams {
+        member,
+        deltas,
+        resp_tx,
+    } = param;
+
+    let increment = score_increment(member, &deltas);
+    let write = write_score(&client, member, &deltas);
+    let res = write_through(&outbox, increment, write).await;
+
+    let _ = resp_tx.send(res);
+}
+
+async fn replay_outbox_handler(client: Client, outbox: Outbox, param: ReplayOutboxParams) {
+    let ReplayOutboxParams { entry, resp_tx } = param;
+
+    let res = outbox.claim(&entry.id, outbox::LEASE).await;
+    if !unwrap_result_or_bails!(resp_tx, res) {
+        let _ = resp_tx.send(Ok(false));
+        return;
+    }
+    // Written and removed since it was listed, or dropped by an owner.
+    let res = outbox.get(&entry.id).await;
+    let Some(entry) = unwrap_result_or_bails!(resp_tx, res) else {
+        let res = outbox.remove(&entry.id).await;
+        unwrap_result_or_bails!(resp_tx, res);
+        let _ = resp_tx.send(Ok(false));
+        return;
+    };
+
+    let res = write_increment(&client, &entry.increment).await;
+    let res = settle(&outbox, entry, res).await.map(|_| true);
+
+    let _ = resp_tx.send(res);
+}
+
+/// Takes an entry out of the outbox once its write went through, or counts the failed attempt
+/// so it gets replayed.
+async fn settle<T>(outbox: &Outbox, entry: Entry, res: anyhow::Result<T>) -> anyhow::Result<T> {
+    match &res {
+        Ok(_) => {
+            if let Err(err) = outbox.remove(&entry.id).await {
+                error!(
+                    ?err,
+                    id = entry.id,
+                    "Can't take the written increment out of the outbox"
+                );
+            }
+        }
+        Err(err) => {
+            error!(?err, id = entry.id, "Write failed, kept in the outbox");
+            if let Err(err) = outbox.fail(entry, err).await {
+                error!(?err, "Can't count the failed attempt in the outbox");
+            }
+        }
+    }
+
+    res
+}
+
+/// The outbox increment of score `deltas`.
+fn score_increment(member: GuildUser, deltas: &[(&str, u64)]) -> Increment {
+    Increment::Score {
+        guild_id: member.0,
+        user_id: member.1,
+        deltas: deltas.iter().map(|(f, d)| (f.to_string(), *d)).collect(),
+    }
+}
+
+/// Raises a member's longest session through the outbox, in its own entry as it can be
+/// written again without harm, unlike the score increment going along with it.
+async fn raise_longest_session(
+    client: &Client,
+    outbox: &Outbox,
+    member: GuildUser,
+    session_length: u64,
+) -> anyhow::Result<()> {
+    let increment = Increment::LongestSession {
+        guild_id: member.0,
+        user_id: member.1,
+        session_length,
+    };
+    let write = write_longest_session(client, member, session_length);
+
+    write_through(outbox, increment, write).await
+}
+
+/// Writes an increment, keeping it in the outbox until pocketbase has it. Fails with
+/// `Outboxed` when pocketbase didn't take it but the outbox did.
+async fn write_through<T>(
+    outbox: &Outbox,
+    increment: Increment,
+    write: impl Future<Output = anyhow::Result<T>>,
+) -> anyhow::Result<T> {
+    let entry = match outbox.push(increment).await {
+        Ok(entry) => Some(entry),
+        Err(err) => {
+            error!(?err, "Can't keep the increment in the outbox");
+            None
+        }
+    };
+
+    match entry {
+        Some(entry) => settle(outbox, entry, write.await)
+            .await
+            .map_err(|_| Outboxed.into()),
+        None => write.await,
+    }
+}
+
+/// Writes an increment coming out of the outbox.
+async fn write_increment(client: &Client, increment: &Increment) -> anyhow::Result<()> {
+    let member = increment.member();
+
+    match increment {
+        Increment::Score { deltas, .. } => {
+            let deltas = deltas
+                .iter()
+                .map(|(field, delta)| (field.as_str(), *delta))
+                .collect::<Vec<_>>();
+            write_score(client, member, &deltas).await?;
+        }
+        Increment::LongestSession { session_length, .. } => {
+            write_longest_session(client, member, *session_length).await?
+        }
+        Increment::PeriodScore {
+            field,
+            delta,
+            period,
+            bucket,
+            ..
+        } => write_period_score(client, member, field, *delta, period, bucket).await?,
+        Increment::ChannelScore {
+            channel_id, delta, ..
+        } => {
+            write_channel_score(client, member, *channel_id, *delta).await?;
+        }
+        Increment::Session { .. } => {
+            write_session(client, increment).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Adds `deltas` to a member's score, creating it if they have none yet.
+async fn write_score(
+    client: &Client,
+    member: GuildUser,
+    deltas: &[(&str, u64)],
+) -> anyhow::Result<ScoreRecord> {
+    let filter = format!(
+        "guild.server_id = \"{}\" && player.user_id = \"{}\"",
+        member.0, member.1
+    );
+
+    let res = match client.first::<ScoreRecord>(&filter).await? {
+        Some(score) => client.increment::<ScoreRecord>(score.id(), deltas).await?,
+        None => {
+            let (guild, player) = upsert_member(client, member).await?;
+
+            let filter = format!(
+                "guild = \"{}\" && player = \"{}\"",
+                guild.default.id, player.default.id
+            );
+            let mut score = ScoreRecord {
+                guild: guild.default.id,
+                player: player.default.id,
+                ..Default::default()
+            };
+            add_deltas(&mut score, deltas);
+
+            client
+                .increment_or_create::<ScoreRecord>(&filter, score, deltas)
+                .await?
+        }
+    };
+
+    Ok(res.into_result()?)
+}
+
+/// Raises a member's longest session to `session_length`, if it's longer and they have a
+/// score.
+///
+/// Pocketbase can't keep the larger of two values by itself, so this reads then writes. No
+/// other write of the member's score can land in between, the `Dispatcher` hands them over one
+/// at a time.
+async fn write_longest_session(
+    client: &Client,
+    member: GuildUser,
+    session_length: u64,
+) -> anyhow::Result<()> {
+    let filter = format!(
+        "guild.server_id = \"{}\" && player.user_id = \"{}\"",
+        member.0, member.1
+    );
+
+    let Some(score) = client.first::<ScoreRecord>(&filter).await? else {
+        return Ok(());
+    };
+    if session_length > score.longest_session {
+        let fields = json!({ "longest_session": session_length });
+        client
+            .update_fields::<ScoreRecord>(score.id(), &fields)
+            .await?
+            .into_result()?;
+    }
+
+    Ok(())
+}
+
+/// Gets the guild and player records a member's score points to, creating whichever is missing.
+/// Existing ones are left alone, the guild's are written by guild commands.
+async fn upsert_member(
+    client: &Client,
+    member: GuildUser,
+) -> anyhow::Result<(GuildRecord, PlayerRecord)> {
+    let filter = format!("server_id = \"{}\"", member.0);
+    let guild = GuildRecord::new(member.0.to_string(), None, None, None);
+    let guild = client
+        .first_or_create::<GuildRecord>(&filter, guild)
+        .await?
+        .into_result()?;
+
+    let filter = format!("user_id = \"{}\"", member.1);
+    let player = PlayerRecord::new(member.1.to_string());
+    let player = client
+        .first_or_create::<PlayerRecord>(&filter, player)
+        .await?
+        .into_result()?;
+
+    Ok((guild, player))
+}
+
+/// The fields of a score record a credit adds to, with how much.
+fn score_deltas(score_type: ScoreType, delta: u64, extras: Extras) -> Vec<(&'static str, u64)> {
+    let field = match score_type {
+        ScoreType::Voice => "voice_time",
+        ScoreType::Afk => "afk_time",
+    };
+
+    let mut deltas = vec![(field, delta)];
+    for (field, enabled) in [
+        ("stream_time", extras.stream),
+        ("video_time", extras.video),
+        ("stage_time", extras.stage),
+    ] {
+        if enabled {
+            deltas.push((field, delta));
+        }
+    }
+
+    deltas
+}
+
+/// Adds `deltas`, as given by `score_deltas`, to a score record in memory.
+fn add_deltas(score: &mut ScoreRecord, deltas: &[(&str, u64)]) {
+    for (field, delta) in deltas {
+        let value = match *field {
+            "voice_time" => &mut score.voice_time,
+            "afk_time" => &mut score.afk_time,
+            "stream_time" => &mut score.stream_time,
+            "video_time" => &mut score.video_time,
+            "stage_time" => &mut score.stage_time,
+            _ => continue,
+        };
+        *value += delta;
+    }
+}
+
+async fn get_score_handler(client: Client, param: GetScoreParams) {
+    let GetScoreParams { member, resp_tx } = param;
+
+    let filter = format!(
+        "guild.server_id = \"{}\" && player.user_id = \"{}\"",
+        member.0, member.1
+    );
+
+    let res = client.list::<ScoreRecord>(Some(filter.as_str())).await;
+    let list_score_res = unwrap_result_or_bails!(resp_tx, res);
+
+    match_list_or_bails!(resp_tx, list_score_res, {
+        let mut items = list_score_res.unwrap();
+
+        let _ = resp_tx.send(Ok(items.pop()));
+    })
+}
+
+async fn delete_score_handler(client: Client, param: DeleteScoreParams) {
+    let DeleteScoreParams { member, resp_tx } = param;
+
+    let filter = format!(
+        "guild.server_id = \"{}\" && player.user_id = \"{}\"",
+        member.0, member.1
+    );
+    let res = delete_all::<ScoreRecord>(&client, &filter).await;
+    unwrap_result_or_bails!(resp_tx, res);
+
+    // The player is shared by every guild, it only goes once none of them has a score left.
+    let filter = format!("user_id = \"{}\"", member.1);
+    let res = client.first::<PlayerRecord>(&filter).await;
+    if let Some(player) = unwrap_result_or_bails!(resp_tx, res) {
+        let filter = format!("player = \"{}\"", player.id());
+        let res = client.first::<ScoreRecord>(&filter).await;
+        if unwrap_result_or_bails!(resp_tx, res).is_none() {
+            let res = client.delete::<PlayerRecord>(player.id()).await;
+            unwrap_result_or_bails!(resp_tx, res);
+        }
+    }
+
+    let _ = resp_tx.send(Ok(()));
+}
+
+/// Deletes every record matching `filter`.
+async fn delete_all<R: for<'de> Deserialize<'de> + Record>(
+    client: &Client,
+    filter: &str,
+) -> anyhow::Result<()> {
+    let records = client
+        .list_all::<R>(ListParams {
+            filter: Some(filter),
+            ..Default::default()
+        })
+        .await?;
+    for record in records {
+        client.delete::<R>(record.id()).await?;
+    }
+
+    Ok(())
+}
+
+async fn set_config_handler(client: Client, param: SetConfigParams) {
+    let SetConfigParams {
+        guild_id,
+        afk_channel,
+        graveyard,
+        achievement_channel,
+        resp_tx,
+    } = param;
+
+    let filter = format!("server_id = \"{}\"", guild_id);
+    let guild = GuildRecord::new(guild_id.to_string(), None, None, None);
+
+    let res = client
+        .upsert_by_filter::<GuildRecord>(&filter, guild, |guild| {
+            if let Some(ch) = afk_channel {
+                guild.afk_channel = ch.to_string();
+            }
+            if let Some(ch) = graveyard {
+                guild.graveyard = ch.to_string();
+            }
+            if let Some(ch) = achievement_channel {
+                guild.achievement_channel = ch.to_string();
+            }
+        })
+        .await;
+    let record_res = unwrap_result_or_bails!(resp_tx, res);
+    let record = unwrap_record_or_bails!(resp_tx, record_res);
+
+    let _ = resp_tx.send(Ok(record));
+}
+
+async fn set_milestones_handler(client: Client, param: SetMilestonesParams) {
+    let SetMilestonesParams {
+        guild_id,
+        milestone_roles,
+        mode,
+        resp_tx,
+    } = param;
+
+    let filter = format!("server_id = \"{}\"", guild_id);
+    let guild = GuildRecord::new(guild_id.to_string(), None, None, None);
+
+    let res = client
+        .upsert_by_filter::<GuildRecord>(&filter, guild, |guild| {
+            if let Some(milestone_roles) = &milestone_roles {
+                guild.milestone_roles = milestone_roles.clone();
+            }
+            if let Some(mode) = mode {
+                guild.milestone_mode = mode.as_str().to_string();
+            }
+        })
+        .await;
+    let record_res = unwrap_result_or_bails!(resp_tx, res);
+    let record = unwrap_record_or_bails!(resp_tx, record_res);
+
+    let _ = resp_tx.send(Ok(record));
+}
+
+async fn set_activity_rules_handler(client: Client, param: SetActivityRulesParams) {
+    let SetActivityRulesParams {
+        guild_id,
+        activity_rules,
+        resp_tx,
+    } = param;
+
+    let filter = format!("server_id = \"{}\"", guild_id);
+    let guild = GuildRecord::new(guild_id.to_string(), None, None, None);
+
+    let res = client
+        .upsert_by_filter::<GuildRecord>(&filter, guild, |guild| {
+            guild.activity_rules = activity_rules.clone();
+        })
+        .await;
+    let record_res = unwrap_result_or_bails!(resp_tx, res);
+    let record = unwrap_record_or_bails!(resp_tx, record_res);
+
+    let _ = resp_tx.send(Ok(record));
+}
+
+async fn get_config_handler(client: Client, param: GetConfigParams) {
+    let GetConfigParams { guild_id, resp_tx } = param;
+
+    let filter = format!("server_id = \"{}\"", guild_id);
 
     let res = client.list::<GuildRecord>(Some(&filter)).await;
     let list_guild_res = unwrap_result_or_bails!(resp_tx, res);
 
     match_list_or_bails!(resp_tx, list_guild_res, {
         let mut guilds = list_guild_res.unwrap();
-        let afk_channel = afk_channel.as_ref().map(ChannelId::to_string);
-        let graveyard = graveyard.as_ref().map(ChannelId::to_string);
 
-        if !guilds.is_empty() {
-            let mut guild = guilds.pop().unwrap();
+        let record = if !guilds.is_empty() {
+            guilds.pop().unwrap()
+        } else {
+            GuildRecord::new(guild_id.to_string(), None, None, None)
+        };
 
-            if let Some(ch) = afk_channel {
-                guild.afk_channel = ch;
-            }
-            if let Some(ch) = graveyard {
-                guild.graveyard = ch;
-            }
+        let _ = resp_tx.send(Ok(record));
+    })
+}
+
+async fn set_session_handler(client: Client, param: SetSessionParams) {
+    let SetSessionParams {
+        member,
+        session,
+        resp_tx,
+    } = param;
+
+    let filter = format!("server_id = \"{}\" && user_id = \"{}\"", member.0, member.1);
 
-            let res = client.update::<GuildRecord>(guild).await;
+    let res = client.list::<SessionRecord>(Some(&filter)).await;
+    let list_session_res = unwrap_result_or_bails!(resp_tx, res);
+
+    match_list_or_bails!(resp_tx, list_session_res, {
+        let mut sessions = list_session_res.unwrap();
+        let (kind, channel_id, joined_at, started_at) = match session {
+            Some((score_type, session)) => (
+                score_type.as_str().to_string(),
+                session.channel_id.to_string(),
+                session.joined_at.timestamp(),
+                session.started_at.timestamp(),
+            ),
+            None => (String::new(), String::new(), 0, 0),
+        };
+
+        if !sessions.is_empty() {
+            let mut record = sessions.pop().unwrap();
+            record.kind = kind;
+            record.channel_id = channel_id;
+            record.joined_at = joined_at;
+            record.started_at = started_at;
+
+            let res = client.update::<SessionRecord>(record).await;
             let record_res = unwrap_result_or_bails!(resp_tx, res);
             let record = unwrap_record_or_bails!(resp_tx, record_res);
 
             let _ = resp_tx.send(Ok(record));
         } else {
-            let guild = GuildRecord::new(guild_id.to_string(), afk_channel, graveyard);
+            let mut record = SessionRecord::new(member.0.to_string(), member.1.to_string());
+            record.kind = kind;
+            record.channel_id = channel_id;
+            record.joined_at = joined_at;
+            record.started_at = started_at;
 
-            let res = client.create::<GuildRecord>(guild).await;
+            let res = client.create::<SessionRecord>(record).await;
             let record_res = unwrap_result_or_bails!(resp_tx, res);
             let record = unwrap_record_or_bails!(resp_tx, record_res);
 
             let _ = resp_tx.send(Ok(record));
+        }
+    })
+}
+
+async fn list_sessions_handler(client: Client, param: ListSessionsParams) {
+    let ListSessionsParams { guild_id, resp_tx } = param;
+
+    let filter = format!("server_id = \"{}\" && started_at > 0", guild_id);
+
+    let res = client
+        .list_all::<SessionRecord>(ListParams {
+            filter: Some(&filter),
+            ..Default::default()
+        })
+        .await;
+    let sessions = unwrap_result_or_bails!(resp_tx, res);
+
+    let _ = resp_tx.send(Ok(sessions));
+}
+
+async fn log_session_handler(client: Client, outbox: Outbox, param: LogSessionParams) {
+    let LogSessionParams {
+        member,
+        score_type,
+        session,
+        ended_at,
+        resp_tx,
+    } = param;
+
+    let increment = Increment::Session {
+        guild_id: member.0,
+        user_id: member.1,
+        channel_id: session.channel_id,
+        score_type: score_type.as_str().to_string(),
+        started_at: session.joined_at.timestamp(),
+        ended_at: ended_at.timestamp(),
+    };
+    let write = write_session(&client, &increment);
+    let res = write_through(&outbox, increment.clone(), write).await;
+    let record = unwrap_written_or_bails!(resp_tx, res);
+
+    let _ = resp_tx.send(Ok(record));
+}
+
+/// Appends an `Increment::Session` to the voice history log. A member only starts one session
+/// at a time in a guild, so a replay of one that was logged already finds it and writes the same
+/// values over it.
+async fn write_session(
+    client: &Client,
+    increment: &Increment,
+) -> anyhow::Result<VoiceSessionRecord> {
+    let Increment::Session {
+        guild_id,
+        user_id,
+        channel_id,
+        score_type,
+        started_at,
+        ended_at,
+    } = increment
+    else {
+        anyhow::bail!("`{increment}` is not a session");
+    };
+
+    let filter = format!(
+        "server_id = \"{}\" && user_id = \"{}\" && started_at = {}",
+        guild_id, user_id, started_at
+    );
+    let record = VoiceSessionRecord {
+        server_id: guild_id.to_string(),
+        user_id: user_id.to_string(),
+        started_at: *started_at,
+        ..Default::default()
+    };
+
+    let record = client
+        .upsert_by_filter::<VoiceSessionRecord>(&filter, record, |record| {
+            record.channel_id = channel_id.to_string();
+            record.kind = score_type.clone();
+            record.ended_at = *ended_at;
+        })
+        .await?
+        .into_result()?;
+
+    Ok(record)
+}
+
+async fn list_scores_handler(client: Client, param: ListScoresParams) {
+    let ListScoresParams { guild_id, resp_tx } = param;
+
+    let filter = format!("guild.server_id = \"{}\"", guild_id);
+    let params = ListParams {
+        filter: Some(&filter),
+        sort: Some("-voice_time"),
+        expand: Some("player"),
+        ..Default::default()
+    };
+
+    let res = client.list_all::<ScoreRecord>(params).await;
+    let scores = unwrap_result_or_bails!(resp_tx, res);
+
+    let scores = scores
+        .into_iter()
+        .filter_map(|mut score| Some((score.expand.player.take()?, score)))
+        .collect();
+
+    let _ = resp_tx.send(Ok(scores));
+}
+
+async fn get_player_handler(client: Client, param: GetPlayerParams) {
+    let GetPlayerParams { user_id, resp_tx } = param;
+
+    let filter = format!("user_id = \"{}\"", user_id);
+    let res = client.first::<PlayerRecord>(&filter).await;
+
+    let _ = resp_tx.send(res);
+}
+
+async fn incr_period_scores_handler(client: Client, outbox: Outbox, param: IncrPeriodScoresParams) {
+    let IncrPeriodScoresParams {
+        member,
+        delta,
+        score_type,
+        buckets,
+        resp_tx,
+    } = param;
+
+    let field = match score_type {
+        ScoreType::Voice => "voice_time",
+        ScoreType::Afk => "afk_time",
+    };
+
+    // Each bucket goes through the outbox by itself, so replaying one doesn't count the others
+    // twice.
+    let mut res = Ok(());
+    for (period, bucket) in buckets {
+        let write = write_period_score(&client, member, field, delta, period.as_str(), &bucket);
+        let increment = Increment::PeriodScore {
+            guild_id: member.0,
+            user_id: member.1,
+            field: field.to_string(),
+            delta,
+            period: period.as_str().to_string(),
+            bucket: bucket.clone(),
         };
+        if let Err(err) = write_through(&outbox, increment, write).await {
+            res = Err(err);
+        }
+    }
+    unwrap_written_or_bails!(resp_tx, res);
+
+    let _ = resp_tx.send(Ok(()));
+}
+
+/// Adds `delta` to `field` of a member's score in a period bucket.
+async fn write_period_score(
+    client: &Client,
+    member: GuildUser,
+    field: &str,
+    delta: u64,
+    period: &str,
+    bucket: &str,
+) -> anyhow::Result<()> {
+    let filter = format!(
+        "server_id = \"{}\" && user_id = \"{}\" && period = \"{}\" && bucket = \"{}\"",
+        member.0, member.1, period, bucket
+    );
+
+    let mut score = PeriodScoreRecord {
+        server_id: member.0.to_string(),
+        user_id: member.1.to_string(),
+        period: period.to_string(),
+        bucket: bucket.to_string(),
+        ..Default::default()
+    };
+    match field {
+        "voice_time" => score.voice_time = delta,
+        "afk_time" => score.afk_time = delta,
+        _ => anyhow::bail!("Period scores have no `{field}`"),
+    }
+
+    client
+        .increment_or_create::<PeriodScoreRecord>(&filter, score, &[(field, delta)])
+        .await?
+        .into_result()?;
+
+    Ok(())
+}
+
+async fn list_period_scores_handler(client: Client, param: ListPeriodScoresParams) {
+    let ListPeriodScoresParams {
+        guild_id,
+        period,
+        bucket,
+        resp_tx,
+    } = param;
+
+    let filter = format!(
+        "server_id = \"{}\" && period = \"{}\" && bucket = \"{}\"",
+        guild_id,
+        period.as_str(),
+        bucket
+    );
+
+    let res = client
+        .list_all::<PeriodScoreRecord>(ListParams {
+            filter: Some(&filter),
+            ..Default::default()
+        })
+        .await;
+    let scores = unwrap_result_or_bails!(resp_tx, res);
+
+    let _ = resp_tx.send(Ok(scores));
+}
+
+async fn get_season_handler(client: Client, param: GetSeasonParams) {
+    let GetSeasonParams { guild_id, resp_tx } = param;
+
+    let filter = format!("server_id = \"{}\" && ended_at = 0", guild_id);
+
+    let res = client.list::<SeasonRecord>(Some(&filter)).await;
+    let list_season_res = unwrap_result_or_bails!(resp_tx, res);
+
+    match_list_or_bails!(resp_tx, list_season_res, {
+        let mut seasons = list_season_res.unwrap();
+
+        let _ = resp_tx.send(Ok(seasons.pop()));
     })
 }
 
-async fn get_config_handler(client: Client, param: GetConfigParams) {
-    let GetConfigParams { guild_id, resp_tx } = param;
+async fn start_season_handler(client: Client, param: StartSeasonParams) {
+    let StartSeasonParams {
+        guild_id,
+        name,
+        started_at,
+        resp_tx,
+    } = param;
 
-    let filter = format!("server_id = \"{}\"", guild_id);
+    let filter = format!("server_id = \"{}\" && ended_at = 0", guild_id);
 
-    let res = client.list::<GuildRecord>(Some(&filter)).await;
-    let list_guild_res = unwrap_result_or_bails!(resp_tx, res);
+    let res = client.list::<SeasonRecord>(Some(&filter)).await;
+    let list_season_res = unwrap_result_or_bails!(resp_tx, res);
 
-    match_list_or_bails!(resp_tx, list_guild_res, {
-        let mut guilds = list_guild_res.unwrap();
+    match_list_or_bails!(resp_tx, list_season_res, {
+        let seasons = list_season_res.unwrap();
 
-        let record = if !guilds.is_empty() {
-            guilds.pop().unwrap()
+        if let Some(season) = seasons.first() {
+            let err = anyhow::anyhow!("Season `{}` is still running!", season.name);
+            let _ = resp_tx.send(Err(err));
+            return;
+        }
+
+        let season = SeasonRecord {
+            server_id: guild_id.to_string(),
+            name,
+            started_at: started_at.timestamp(),
+            ..Default::default()
+        };
+
+        let res = client.create::<SeasonRecord>(season).await;
+        let record_res = unwrap_result_or_bails!(resp_tx, res);
+        let record = unwrap_record_or_bails!(resp_tx, record_res);
+
+        let _ = resp_tx.send(Ok(record));
+    })
+}
+
+async fn end_season_handler(client: Client, param: EndSeasonParams) {
+    let EndSeasonParams {
+        guild_id,
+        ended_at,
+        resp_tx,
+    } = param;
+
+    let filter = format!("server_id = \"{}\" && ended_at = 0", guild_id);
+
+    let res = client.list::<SeasonRecord>(Some(&filter)).await;
+    let list_season_res = unwrap_result_or_bails!(resp_tx, res);
+
+    let mut season = match_list_or_bails!(resp_tx, list_season_res, {
+        let mut seasons = list_season_res.unwrap();
+
+        match seasons.pop() {
+            Some(season) => season,
+            None => {
+                let err = anyhow::anyhow!("There is no season running right now!");
+                let _ = resp_tx.send(Err(err));
+                return;
+            }
+        }
+    });
+
+    let filter = format!(
+        "server_id = \"{}\" && period = \"{}\" && bucket = \"{}\"",
+        guild_id,
+        Period::Season.as_str(),
+        season.id()
+    );
+
+    let res = client
+        .list_all::<PeriodScoreRecord>(ListParams {
+            filter: Some(&filter),
+            ..Default::default()
+        })
+        .await;
+    let mut scores = unwrap_result_or_bails!(resp_tx, res);
+    scores.sort_by_key(|s| std::cmp::Reverse(s.voice_time));
+
+    season.ended_at = ended_at.timestamp();
+    season.standings = scores
+        .iter()
+        .map(|s| {
+            json!({
+                "user_id": s.user_id,
+                "voice_time": s.voice_time,
+                "afk_time": s.afk_time,
+            })
+        })
+        .collect();
+
+    let res = client.update::<SeasonRecord>(season).await;
+    let record_res = unwrap_result_or_bails!(resp_tx, res);
+    let record = unwrap_record_or_bails!(resp_tx, record_res);
+
+    let _ = resp_tx.send(Ok(record));
+}
+
+async fn list_achievements_handler(client: Client, param: ListAchievementsParams) {
+    let ListAchievementsParams { member, resp_tx } = param;
+
+    let filter = format!("server_id = \"{}\" && user_id = \"{}\"", member.0, member.1);
+
+    let res = client
+        .list_all::<AchievementRecord>(ListParams {
+            filter: Some(&filter),
+            ..Default::default()
+        })
+        .await;
+    let achievements = unwrap_result_or_bails!(resp_tx, res);
+
+    let _ = resp_tx.send(Ok(achievements));
+}
+
+async fn unlock_achievement_handler(client: Client, param: UnlockAchievementParams) {
+    let UnlockAchievementParams {
+        member,
+        achievement,
+        unlocked_at,
+        resp_tx,
+    } = param;
+
+    let record = AchievementRecord {
+        server_id: member.0.to_string(),
+        user_id: member.1.to_string(),
+        achievement: achievement.to_string(),
+        unlocked_at: unlocked_at.timestamp(),
+        ..Default::default()
+    };
+
+    let res = client.create::<AchievementRecord>(record).await;
+    let record_res = unwrap_result_or_bails!(resp_tx, res);
+    let record = unwrap_record_or_bails!(resp_tx, record_res);
+
+    let _ = resp_tx.send(Ok(record));
+}
+
+async fn incr_channel_score_handler(client: Client, outbox: Outbox, param: IncrChannelScoreParams) {
+    let IncrChannelScoreParams {
+        member,
+        channel_id,
+        delta,
+        resp_tx,
+    } = param;
+
+    let increment = Increment::ChannelScore {
+        guild_id: member.0,
+        user_id: member.1,
+        channel_id,
+        delta,
+    };
+    let write = write_channel_score(&client, member, channel_id, delta);
+    let res = write_through(&outbox, increment, write).await;
+    let record = unwrap_written_or_bails!(resp_tx, res);
+
+    let _ = resp_tx.send(Ok(record));
+}
+
+async fn write_channel_score(
+    client: &Client,
+    member: GuildUser,
+    channel_id: ChannelId,
+    delta: u64,
+) -> anyhow::Result<ChannelScoreRecord> {
+    let filter = format!(
+        "server_id = \"{}\" && channel_id = \"{}\" && user_id = \"{}\"",
+        member.0, channel_id, member.1
+    );
+
+    let score = ChannelScoreRecord {
+        server_id: member.0.to_string(),
+        channel_id: channel_id.to_string(),
+        user_id: member.1.to_string(),
+        time: delta,
+        ..Default::default()
+    };
+
+    let record = client
+        .increment_or_create::<ChannelScoreRecord>(&filter, score, &[("time", delta)])
+        .await?
+        .into_result()?;
+
+    Ok(record)
+}
+
+async fn list_channel_scores_handler(client: Client, param: ListChannelScoresParams) {
+    let ListChannelScoresParams {
+        guild_id,
+        user_id,
+        resp_tx,
+    } = param;
+
+    let filter = match user_id {
+        Some(user_id) => format!("server_id = \"{}\" && user_id = \"{}\"", guild_id, user_id),
+        None => format!("server_id = \"{}\"", guild_id),
+    };
+
+    let res = client
+        .list_all::<ChannelScoreRecord>(ListParams {
+            filter: Some(&filter),
+            ..Default::default()
+        })
+        .await;
+    let scores = unwrap_result_or_bails!(resp_tx, res);
+
+    let _ = resp_tx.send(Ok(scores));
+}
+
+async fn set_channel_peak_handler(client: Client, param: SetChannelPeakParams) {
+    let SetChannelPeakParams {
+        guild_id,
+        channel_id,
+        users,
+        at,
+        resp_tx,
+    } = param;
+
+    let filter = format!(
+        "server_id = \"{}\" && channel_id = \"{}\"",
+        guild_id, channel_id
+    );
+
+    let res = client.list::<ChannelRecord>(Some(&filter)).await;
+    let list_channel_res = unwrap_result_or_bails!(resp_tx, res);
+
+    match_list_or_bails!(resp_tx, list_channel_res, {
+        let mut items = list_channel_res.unwrap();
+
+        let mut channel = match items.pop() {
+            Some(channel) if channel.peak_users >= users => {
+                let _ = resp_tx.send(Ok(channel));
+                return;
+            }
+            Some(channel) => channel,
+            None => ChannelRecord {
+                server_id: guild_id.to_string(),
+                channel_id: channel_id.to_string(),
+                ..Default::default()
+            },
+        };
+        channel.peak_users = users;
+        channel.peak_at = at.timestamp();
+
+        let res = if channel.default.id.is_empty() {
+            client.create::<ChannelRecord>(channel).await
         } else {
-            GuildRecord::new(guild_id.to_string(), None, None)
+            client.update::<ChannelRecord>(channel).await
         };
+        let record_res = unwrap_result_or_bails!(resp_tx, res);
+        let record = unwrap_record_or_bails!(resp_tx, record_res);
 
         let _ = resp_tx.send(Ok(record));
     })
 }
+
+async fn list_channels_handler(client: Client, param: ListChannelsParams) {
+    let ListChannelsParams { guild_id, resp_tx } = param;
+
+    let filter = format!("server_id = \"{}\"", guild_id);
+
+    let res = client
+        .list_all::<ChannelRecord>(ListParams {
+            filter: Some(&filter),
+            ..Default::default()
+        })
+        .await;
+    let channels = unwrap_result_or_bails!(resp_tx, res);
+
+    let _ = resp_tx.send(Ok(channels));
+}
+
+/// Deletes everything kept about a member in a guild besides their score, which is up to the
+/// storage.
+async fn forget_member_handler(client: Client, param: ForgetMemberParams) {
+    let ForgetMemberParams { member, resp_tx } = param;
+
+    let filter = format!("server_id = \"{}\" && user_id = \"{}\"", member.0, member.1);
+    let res = async {
+        delete_all::<SessionRecord>(&client, &filter).await?;
+        delete_all::<VoiceSessionRecord>(&client, &filter).await?;
+        delete_all::<PeriodScoreRecord>(&client, &filter).await?;
+        delete_all::<AchievementRecord>(&client, &filter).await?;
+        delete_all::<ChannelScoreRecord>(&client, &filter).await
+    }
+    .await;
+    unwrap_result_or_bails!(resp_tx, res);
+
+    let _ = resp_tx.send(Ok(()));
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::task::JoinSet;
+
+    use super::*;
+    use crate::{
+        database::{fake as fake_redis, Redis},
+        pocketbase::fake,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn concurrent_score_increments_all_land() {
+        let url = fake::spawn();
+        let client = Client::new(&url, "admin", "password").await.unwrap();
+        let member = GuildUser(GuildId::new(1), UserId::new(2));
+
+        // Racing for the first score record too, as none exists yet.
+        let mut writes = JoinSet::new();
+        for _ in 0..20 {
+            let client = client.clone();
+            writes.spawn(async move { write_score(&client, member, &[("voice_time", 60)]).await });
+        }
+        while let Some(res) = writes.join_next().await {
+            res.unwrap().unwrap();
+        }
+
+        let score = client.first::<ScoreRecord>("").await.unwrap().unwrap();
+        assert_eq!(score.voice_time, 20 * 60);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn shutdown_writes_buffered_scores() {
+        let client = Client::new(&fake::spawn(), "admin", "password")
+            .await
+            .unwrap();
+        let db = Arc::new(tokio::sync::Mutex::new(Redis::new(
+            &fake_redis::spawn().await,
+        )));
+        let outbox = Outbox::new(db).await.unwrap();
+        let manager = Manager::new(client.clone(), Arc::default(), outbox.clone());
+        let (tx, rx) = mpsc::channel(10);
+        let (shutdown, stop_rx) = Shutdown::new();
+        manager.spawn(rx, stop_rx);
+        let member = GuildUser(GuildId::new(1), UserId::new(2));
+
+        // The first one learns the score, the ones after it are buffered.
+        for _ in 0..3 {
+            let (resp_tx, resp_rx) = oneshot::channel();
+            let extras = Extras::default();
+            let cmd = Command::new_incr_score(member, 60, None, extras, resp_tx, ScoreType::Voice);
+            tx.send(cmd).await.unwrap();
+            resp_rx.await.unwrap().unwrap();
+            // Gives the learned score time to reach the buffer.
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        let score = client.first::<ScoreRecord>("").await.unwrap().unwrap();
+        assert_eq!(score.voice_time, 60);
+
+        shutdown.flush_and_wait().await;
+
+        let score = client.first::<ScoreRecord>("").await.unwrap().unwrap();
+        assert_eq!(score.voice_time, 3 * 60);
+        assert!(outbox.list().await.unwrap().is_empty());
+    }
+}
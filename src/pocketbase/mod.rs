@@ -1,7 +1,14 @@
 pub mod client;
+/// A stand-in pocketbase for tests.
+#[cfg(test)]
+pub mod fake;
 pub mod manager;
+pub mod realtime;
 pub mod records;
 
 pub use client::Client;
 pub use manager::Command;
 pub use manager::Manager;
+pub use manager::Metrics;
+pub use manager::Shutdown;
+pub use realtime::Realtime;
@@ -0,0 +1,224 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use anyhow::Context as _;
+use poise::serenity_prelude::GuildId;
+use reqwest::Response;
+use serde::Deserialize;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::{
+    cache::DataCache,
+    pocketbase::records::{GuildRecord, Record, ScoreRecord},
+};
+
+use super::Client;
+
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// A server-sent event, only the fields pocketbase uses.
+#[derive(Debug, Default)]
+struct Event {
+    name: String,
+    data: String,
+}
+
+/// Reads server-sent events off a response as they come in.
+/// See <https://html.spec.whatwg.org/multipage/server-sent-events.html#event-stream-interpretation>.
+struct EventStream {
+    res: Response,
+    buf: Vec<u8>,
+}
+
+impl EventStream {
+    fn new(res: Response) -> Self {
+        EventStream {
+            res,
+            buf: Vec::new(),
+        }
+    }
+
+    /// The next event, `None` once pocketbase closes the connection.
+    async fn next(&mut self) -> anyhow::Result<Option<Event>> {
+        loop {
+            if let Some(end) = self.buf.windows(2).position(|w| w == b"\n\n") {
+                let raw = self.buf.drain(..end + 2).collect::<Vec<_>>();
+                let event = parse_event(&String::from_utf8_lossy(&raw));
+
+                // Nothing but comments, used to keep the connection alive.
+                if event.name.is_empty() && event.data.is_empty() {
+                    continue;
+                }
+                return Ok(Some(event));
+            }
+
+            match self.res.chunk().await? {
+                Some(chunk) => self.buf.extend(chunk.iter().filter(|b| **b != b'\r')),
+                None => return Ok(None),
+            }
+        }
+    }
+}
+
+fn parse_event(raw: &str) -> Event {
+    let mut event = Event::default();
+
+    for line in raw.lines() {
+        let (field, value) = line.split_once(':').unwrap_or((line, ""));
+        let value = value.strip_prefix(' ').unwrap_or(value);
+
+        match field {
+            "event" => event.name = value.to_string(),
+            "data" => {
+                if !event.data.is_empty() {
+                    event.data.push('\n');
+                }
+                event.data.push_str(value);
+            }
+            _ => {}
+        }
+    }
+
+    event
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Connect {
+    client_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Action {
+    Create,
+    Update,
+    Delete,
+}
+
+#[derive(Debug, Deserialize)]
+struct RecordEvent<R> {
+    action: Action,
+    record: R,
+}
+
+/// Follows changes to pocketbase made by anything else than this bot, like the admin UI or a
+/// second instance, and drops whatever they make stale from `DataCache`.
+pub struct Realtime {
+    client: Client,
+    cache: Arc<Mutex<DataCache>>,
+    /// Whether scores are kept in pocketbase too, guilds always are.
+    scores: bool,
+    /// Guild ids by guild record id, for the scores that only point to their guild record.
+    guild_ids: HashMap<String, GuildId>,
+}
+
+impl Realtime {
+    pub fn new(client: Client, cache: Arc<Mutex<DataCache>>, scores: bool) -> Self {
+        Realtime {
+            client,
+            cache,
+            scores,
+            guild_ids: HashMap::new(),
+        }
+    }
+
+    /// Listens in the background, connecting again whenever the connection drops.
+    pub fn spawn(mut self) {
+        tokio::spawn(async move {
+            let mut backoff = MIN_BACKOFF;
+            loop {
+                match self.listen(&mut backoff).await {
+                    Ok(()) => info!("Pocketbase closed the realtime connection"),
+                    Err(err) => warn!(?err, "Pocketbase realtime connection failed"),
+                }
+
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        });
+    }
+
+    async fn listen(&mut self, backoff: &mut Duration) -> anyhow::Result<()> {
+        let mut events = EventStream::new(self.client.realtime().await?);
+
+        let connect = events
+            .next()
+            .await?
+            .context("Realtime connection closed before `PB_CONNECT`")?;
+        let Connect { client_id } = serde_json::from_str(&connect.data)?;
+
+        let topics: &[&str] = if self.scores {
+            &["guilds", "scores"]
+        } else {
+            &["guilds"]
+        };
+        self.client.subscribe(&client_id, topics).await?;
+        info!(?topics, "Subscribed to pocketbase realtime");
+        *backoff = MIN_BACKOFF;
+
+        // Anything could have changed while disconnected.
+        self.cache.lock().await.clear_guilds();
+
+        while let Some(event) = events.next().await? {
+            if let Err(err) = self.on_event(&event).await {
+                warn!(
+                    ?err,
+                    event = event.name,
+                    "Can't handle pocketbase realtime event"
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn on_event(&mut self, event: &Event) -> anyhow::Result<()> {
+        match event.name.as_str() {
+            "guilds" => self.on_guild(serde_json::from_str(&event.data)?).await,
+            "scores" => self.on_score(serde_json::from_str(&event.data)?).await,
+            _ => Ok(()),
+        }
+    }
+
+    async fn on_guild(&mut self, event: RecordEvent<GuildRecord>) -> anyhow::Result<()> {
+        let RecordEvent { action, record } = event;
+        let guild_id = GuildId::new(record.server_id.parse::<u64>()?);
+
+        match action {
+            Action::Create | Action::Update => {
+                self.guild_ids.insert(record.id().to_string(), guild_id);
+            }
+            Action::Delete => {
+                self.guild_ids.remove(record.id());
+            }
+        }
+
+        let mut cache = self.cache.lock().await;
+        cache.rem_guild(guild_id);
+
+        Ok(())
+    }
+
+    async fn on_score(&mut self, event: RecordEvent<ScoreRecord>) -> anyhow::Result<()> {
+        let guild_id = match self.guild_ids.get(&event.record.guild) {
+            Some(guild_id) => *guild_id,
+            None => {
+                let guild = self
+                    .client
+                    .view::<GuildRecord>(&event.record.guild)
+                    .await?
+                    .into_result()?;
+                let guild_id = GuildId::new(guild.server_id.parse::<u64>()?);
+                self.guild_ids.insert(event.record.guild.clone(), guild_id);
+                guild_id
+            }
+        };
+
+        let mut cache = self.cache.lock().await;
+        cache.rem_scores(guild_id);
+
+        Ok(())
+    }
+}
@@ -1,4 +1,5 @@
 use serde::{self, Deserialize, Serialize};
+use serde_json::Value;
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct AdminRecord {
@@ -7,7 +8,7 @@ pub struct AdminRecord {
     pub email: String,
 }
 
-#[derive(Debug, Default, Deserialize, Serialize)]
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
 pub struct ScoreRecord {
     #[serde(flatten, skip_serializing)]
     pub default: DefaultFields,
@@ -16,9 +17,28 @@ pub struct ScoreRecord {
     pub player: String,
     pub voice_time: u64,
     pub afk_time: u64,
+    /// Missing from records made before it was tracked.
+    #[serde(default)]
+    pub longest_session: u64,
+    /// Missing from records made before streams, cameras and stages were tracked.
+    #[serde(default)]
+    pub stream_time: u64,
+    #[serde(default)]
+    pub video_time: u64,
+    #[serde(default)]
+    pub stage_time: u64,
+
+    /// Only filled in when listed with `expand = "player"`.
+    #[serde(default, skip_serializing)]
+    pub expand: ScoreExpand,
 }
 
-#[derive(Debug, Default, Deserialize, Serialize)]
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct ScoreExpand {
+    pub player: Option<PlayerRecord>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
 pub struct PlayerRecord {
     #[serde(flatten, skip_serializing)]
     pub default: DefaultFields,
@@ -44,19 +64,136 @@ pub struct GuildRecord {
     pub server_id: String,
     pub afk_channel: String,
     pub graveyard: String,
+    pub achievement_channel: String,
+    /// List of `{ hours, role_id }`, see `Milestone`.
+    pub milestone_roles: Value,
+    pub milestone_mode: String,
+    /// See `ActivityRules`.
+    pub activity_rules: Value,
 }
 
 impl GuildRecord {
-    pub fn new(server_id: String, afk_channel: Option<String>, graveyard: Option<String>) -> Self {
+    pub fn new(
+        server_id: String,
+        afk_channel: Option<String>,
+        graveyard: Option<String>,
+        achievement_channel: Option<String>,
+    ) -> Self {
         GuildRecord {
             server_id,
             afk_channel: afk_channel.unwrap_or_default(),
             graveyard: graveyard.unwrap_or_default(),
+            achievement_channel: achievement_channel.unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+}
+
+/// An in-flight voice session. `joined_at` and `started_at` are unix timestamps in seconds,
+/// a `started_at` of `0` means the member has no open session.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct SessionRecord {
+    #[serde(flatten, skip_serializing)]
+    pub default: DefaultFields,
+
+    pub server_id: String,
+    pub user_id: String,
+    pub channel_id: String,
+    pub kind: String,
+    pub joined_at: i64,
+    pub started_at: i64,
+}
+
+impl SessionRecord {
+    pub fn new(server_id: String, user_id: String) -> Self {
+        SessionRecord {
+            server_id,
+            user_id,
             ..Default::default()
         }
     }
 }
 
+/// A closed voice session. `started_at` and `ended_at` are unix timestamps in seconds.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct VoiceSessionRecord {
+    #[serde(flatten, skip_serializing)]
+    pub default: DefaultFields,
+
+    pub server_id: String,
+    pub user_id: String,
+    pub channel_id: String,
+    pub kind: String,
+    pub started_at: i64,
+    pub ended_at: i64,
+}
+
+/// Score accumulated within a single day, week, month or season. `bucket` identifies which one,
+/// e.g. `2024-05-08`, `2024-W19`, `2024-05`, or the season's record id.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct PeriodScoreRecord {
+    #[serde(flatten, skip_serializing)]
+    pub default: DefaultFields,
+
+    pub server_id: String,
+    pub user_id: String,
+    pub period: String,
+    pub bucket: String,
+    pub voice_time: u64,
+    pub afk_time: u64,
+}
+
+/// A named season. `ended_at` is `0` while the season is still running, `standings` holds the
+/// final leaderboard once it has ended.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct SeasonRecord {
+    #[serde(flatten, skip_serializing)]
+    pub default: DefaultFields,
+
+    pub server_id: String,
+    pub name: String,
+    pub started_at: i64,
+    pub ended_at: i64,
+    pub standings: Value,
+}
+
+/// Time a member has spent in one channel, voice and AFK alike.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct ChannelScoreRecord {
+    #[serde(flatten, skip_serializing)]
+    pub default: DefaultFields,
+
+    pub server_id: String,
+    pub channel_id: String,
+    pub user_id: String,
+    pub time: u64,
+}
+
+/// The most members ever credited at once in a channel. `peak_at` is a unix timestamp in seconds.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct ChannelRecord {
+    #[serde(flatten, skip_serializing)]
+    pub default: DefaultFields,
+
+    pub server_id: String,
+    pub channel_id: String,
+    pub peak_users: u64,
+    pub peak_at: i64,
+}
+
+/// An achievement a member has unlocked. `achievement` is the id from the catalogue,
+/// `unlocked_at` is a unix timestamp in seconds.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct AchievementRecord {
+    #[serde(flatten, skip_serializing)]
+    pub default: DefaultFields,
+
+    pub server_id: String,
+    pub user_id: String,
+    pub achievement: String,
+    pub unlocked_at: i64,
+}
+
 #[derive(Clone, Debug, Default, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct DefaultFields {
@@ -90,3 +227,10 @@ macro_rules! impl_record {
 impl_record!(GuildRecord, "guilds");
 impl_record!(PlayerRecord, "players");
 impl_record!(ScoreRecord, "scores");
+impl_record!(SessionRecord, "sessions");
+impl_record!(VoiceSessionRecord, "voice_sessions");
+impl_record!(PeriodScoreRecord, "period_scores");
+impl_record!(SeasonRecord, "seasons");
+impl_record!(AchievementRecord, "achievements");
+impl_record!(ChannelScoreRecord, "channel_scores");
+impl_record!(ChannelRecord, "channels");
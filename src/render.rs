@@ -0,0 +1,173 @@
+use std::io::Cursor;
+
+use ab_glyph::{FontRef, PxScale};
+use anyhow::Result;
+use image::{ImageFormat, Rgb, RgbImage};
+use imageproc::{
+    drawing::{draw_filled_circle_mut, draw_filled_rect_mut, draw_text_mut, text_size},
+    rect::Rect,
+};
+use once_cell::sync::Lazy;
+use poise::serenity_prelude::UserId;
+
+static FONT: Lazy<FontRef<'static>> = Lazy::new(|| {
+    FontRef::try_from_slice(include_bytes!("../assets/fonts/DejaVuSans-Bold.ttf"))
+        .expect("Bundled font is not a valid font")
+});
+
+const WIDTH: u32 = 640;
+const PADDING: i32 = 24;
+const TITLE_HEIGHT: u32 = 72;
+const ROW_HEIGHT: u32 = 56;
+const AVATAR_RADIUS: i32 = 18;
+
+const BACKGROUND: Rgb<u8> = Rgb([43, 45, 49]);
+const ROW_STRIPE: Rgb<u8> = Rgb([49, 51, 56]);
+const TEXT: Rgb<u8> = Rgb([242, 243, 245]);
+const MUTED_TEXT: Rgb<u8> = Rgb([181, 186, 193]);
+
+/// Palette for avatar placeholders, picked by user id so a member keeps the same color.
+const AVATAR_COLORS: [Rgb<u8>; 6] = [
+    Rgb([88, 101, 242]),
+    Rgb([87, 242, 135]),
+    Rgb([254, 231, 92]),
+    Rgb([235, 69, 158]),
+    Rgb([237, 66, 69]),
+    Rgb([52, 152, 219]),
+];
+
+pub struct LeaderboardRow {
+    pub rank: usize,
+    pub user_id: UserId,
+    pub name: String,
+    pub value: String,
+}
+
+/// Draws a leaderboard into a PNG image.
+pub fn render_leaderboard(title: &str, header: &str, rows: &[LeaderboardRow]) -> Result<Vec<u8>> {
+    let height = TITLE_HEIGHT + ROW_HEIGHT * rows.len().max(1) as u32;
+    let mut img = RgbImage::from_pixel(WIDTH, height, BACKGROUND);
+
+    let title_scale = PxScale::from(28.0);
+    let header_scale = PxScale::from(16.0);
+    let row_scale = PxScale::from(20.0);
+    let initials_scale = PxScale::from(16.0);
+
+    let (header_width, _) = text_size(header_scale, &*FONT, header);
+    let title_width = WIDTH as i32 - PADDING * 3 - header_width as i32;
+    let title = fit_text(title, title_scale, title_width);
+    draw_text_mut(&mut img, TEXT, PADDING, 20, title_scale, &*FONT, &title);
+    draw_text_mut(
+        &mut img,
+        MUTED_TEXT,
+        WIDTH as i32 - PADDING - header_width as i32,
+        28,
+        header_scale,
+        &*FONT,
+        header,
+    );
+
+    if rows.is_empty() {
+        let y = TITLE_HEIGHT as i32 + 16;
+        draw_text_mut(
+            &mut img,
+            MUTED_TEXT,
+            PADDING,
+            y,
+            row_scale,
+            &*FONT,
+            "Nobody is here yet.",
+        );
+    }
+
+    for (i, row) in rows.iter().enumerate() {
+        let top = TITLE_HEIGHT as i32 + (ROW_HEIGHT * i as u32) as i32;
+        let center_y = top + ROW_HEIGHT as i32 / 2;
+        let text_y = center_y - 12;
+
+        if i % 2 == 0 {
+            let stripe = Rect::at(0, top).of_size(WIDTH, ROW_HEIGHT);
+            draw_filled_rect_mut(&mut img, stripe, ROW_STRIPE);
+        }
+
+        draw_text_mut(
+            &mut img,
+            MUTED_TEXT,
+            PADDING,
+            text_y,
+            row_scale,
+            &*FONT,
+            &format!("#{}", row.rank),
+        );
+
+        let avatar_x = PADDING + 72;
+        let color_idx = row.user_id.get() % AVATAR_COLORS.len() as u64;
+        let avatar_color = AVATAR_COLORS[color_idx as usize];
+        draw_filled_circle_mut(&mut img, (avatar_x, center_y), AVATAR_RADIUS, avatar_color);
+
+        let initials = initials(&row.name);
+        let (initials_width, initials_height) = text_size(initials_scale, &*FONT, &initials);
+        draw_text_mut(
+            &mut img,
+            BACKGROUND,
+            avatar_x - initials_width as i32 / 2,
+            center_y - initials_height as i32 / 2 - 2,
+            initials_scale,
+            &*FONT,
+            &initials,
+        );
+
+        let (value_width, _) = text_size(row_scale, &*FONT, &row.value);
+        let value_x = WIDTH as i32 - PADDING - value_width as i32;
+        draw_text_mut(
+            &mut img, TEXT, value_x, text_y, row_scale, &*FONT, &row.value,
+        );
+
+        let name_x = avatar_x + AVATAR_RADIUS + 16;
+        let name = fit_text(&row.name, row_scale, value_x - name_x - PADDING);
+        draw_text_mut(&mut img, TEXT, name_x, text_y, row_scale, &*FONT, &name);
+    }
+
+    let mut png = Vec::new();
+    img.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+
+    Ok(png)
+}
+
+/// Up to two letters to show in an avatar placeholder.
+fn initials(name: &str) -> String {
+    let mut words = name
+        .split(|c: char| c.is_whitespace() || c == '_' || c == '.')
+        .filter(|w| !w.is_empty());
+
+    let initials: String = match (words.next(), words.next()) {
+        (Some(first), Some(second)) => first
+            .chars()
+            .take(1)
+            .chain(second.chars().take(1))
+            .collect(),
+        (Some(first), None) => first.chars().take(2).collect(),
+        _ => "?".into(),
+    };
+
+    initials.to_uppercase()
+}
+
+/// Cuts `text` short with an ellipsis so it fits in `max_width` pixels.
+fn fit_text(text: &str, scale: PxScale, max_width: i32) -> String {
+    let fits = |s: &str| text_size(scale, &*FONT, s).0 as i32 <= max_width;
+    if fits(text) {
+        return text.to_string();
+    }
+
+    let mut chars: Vec<char> = text.chars().collect();
+    while !chars.is_empty() {
+        chars.pop();
+        let shortened = format!("{}…", chars.iter().collect::<String>());
+        if fits(&shortened) {
+            return shortened;
+        }
+    }
+
+    "…".into()
+}
@@ -2,83 +2,36 @@ use std::{sync::Arc, time::Duration};
 
 use anyhow::Result;
 use poise::serenity_prelude::{GuildId, UserId};
-use redis::AsyncCommands;
-use tokio::sync::Mutex;
 
-use crate::{database::Redis, Context};
+use crate::Data;
 
 pub struct Scores {}
 
 impl Scores {
-    pub async fn get_all_score(ctx: Context<'_>, guild_id: GuildId) -> Result<Arc<[Score]>> {
-        let db = ctx.data().db.clone();
-        let key = format!("score:{}", guild_id.get());
-        let mut conn = Redis::get_connection(db.clone()).await?;
-
-        let id_set: Vec<String> = conn.smembers(key.as_str()).await?;
-        let keys: Vec<String> = id_set
-            .iter()
-            .map(|s| format!("score:{}:{}", guild_id.get(), s))
-            .collect();
-
-        let mut conn = Redis::get_connection(db.clone()).await?;
-        let scores: Vec<u64> = conn.mget(keys).await?;
-        let mut scores = id_set
-            .iter()
-            .zip(scores)
-            .map(|(id, s)| {
-                let id = id.parse::<u64>()?;
-                Ok(Score {
-                    guild_id,
-                    user_id: id.into(),
-                    score: Duration::from_secs(s),
-                })
-            })
-            .collect::<Result<Vec<Score>>>()?;
-        scores.sort_by(|a, b| b.partial_cmp(a).unwrap_or_else(|| b.cmp(a)));
+    /// Gets every member's score, sorted from the highest voice time.
+    pub async fn get_all_score(data: &Data, guild_id: GuildId) -> Result<Arc<[Score]>> {
+        let scores = data.storage.leaderboard(guild_id).await?;
         let scores: Arc<[Score]> = scores.into();
 
         {
-            let mut cache = ctx.data().cache.lock().await;
+            let mut cache = data.cache.lock().await;
             cache.set_scores(guild_id, scores.clone());
         };
 
         Ok(scores)
     }
-
-    pub async fn _get_score(db: Arc<Mutex<Redis>>, member: GuildUser) -> Result<Score> {
-        let mut conn = Redis::get_connection(db).await?;
-        let guild_id = member.0;
-        let user_id = member.1;
-
-        let score: u64 = conn
-            .get(format!("score:{}:{}", guild_id.get(), user_id.get()))
-            .await?;
-        let score = Duration::from_secs(score);
-
-        Ok(Score::from((guild_id, user_id, score)))
-    }
-
-    pub async fn incr_score(db: Arc<Mutex<Redis>>, member: GuildUser, delta: u64) -> Result<u64> {
-        let mut conn = Redis::get_connection(db).await?;
-        let guild_id = member.0;
-        let user_id = member.1;
-
-        let (after,) = redis::pipe()
-            .sadd(format!("score:{guild_id}"), user_id.get())
-            .ignore()
-            .incr(format!("score:{guild_id}:{user_id}"), delta)
-            .query_async(&mut conn)
-            .await?;
-        Ok(after)
-    }
 }
 
-#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+#[derive(Debug, Default, Eq, PartialEq, Clone, Copy)]
 pub struct Score {
     pub guild_id: GuildId,
     pub user_id: UserId,
     pub score: Duration,
+    pub afk_score: Duration,
+    pub stream_score: Duration,
+    pub video_score: Duration,
+    pub stage_score: Duration,
+    pub longest_session: Duration,
 }
 
 impl PartialOrd for Score {
@@ -93,12 +46,29 @@ impl Ord for Score {
     }
 }
 
+impl Score {
+    pub fn total(&self) -> Duration {
+        self.score + self.afk_score
+    }
+
+    /// How much of the total time was spent outside of AFK, from 0.0 to 1.0.
+    pub fn active_ratio(&self) -> f64 {
+        let total = self.total().as_secs_f64();
+        if total == 0.0 {
+            0.0
+        } else {
+            self.score.as_secs_f64() / total
+        }
+    }
+}
+
 impl From<(GuildId, UserId, Duration)> for Score {
     fn from((guild_id, user_id, score): (GuildId, UserId, Duration)) -> Self {
         Score {
             guild_id,
             user_id,
             score,
+            ..Default::default()
         }
     }
 }
@@ -118,7 +88,107 @@ impl std::cmp::PartialEq<poise::serenity_prelude::Member> for GuildUser {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum ScoreType {
     Voice,
     Afk,
 }
+
+/// What a leaderboard ranks members by.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum RankKind {
+    #[default]
+    #[name = "voice"]
+    Voice,
+    #[name = "afk"]
+    Afk,
+    #[name = "total"]
+    Total,
+    #[name = "active-ratio"]
+    ActiveRatio,
+    #[name = "stream"]
+    Stream,
+    #[name = "video"]
+    Video,
+    #[name = "stage"]
+    Stage,
+}
+
+impl RankKind {
+    pub fn title(&self) -> &'static str {
+        match self {
+            RankKind::Voice => "Voice Chat Total Time",
+            RankKind::Afk => "AFK Total Time",
+            RankKind::Total => "Voice Chat + AFK Total Time",
+            RankKind::ActiveRatio => "Voice Chat Active Ratio",
+            RankKind::Stream => "Streaming Total Time",
+            RankKind::Video => "Camera Total Time",
+            RankKind::Stage => "Stage Speaking Total Time",
+        }
+    }
+
+    pub fn header(&self) -> &'static str {
+        match self {
+            RankKind::Voice => "Voice Time",
+            RankKind::Afk => "AFK Time",
+            RankKind::Total => "Total Time",
+            RankKind::ActiveRatio => "Active Ratio",
+            RankKind::Stream => "Stream Time",
+            RankKind::Video => "Camera Time",
+            RankKind::Stage => "Stage Time",
+        }
+    }
+
+    pub fn format(&self, score: &Score) -> String {
+        match self {
+            RankKind::Voice => humantime::format_duration(score.score).to_string(),
+            RankKind::Afk => humantime::format_duration(score.afk_score).to_string(),
+            RankKind::Total => humantime::format_duration(score.total()).to_string(),
+            RankKind::ActiveRatio => format!("{:.1}%", score.active_ratio() * 100.0),
+            RankKind::Stream => humantime::format_duration(score.stream_score).to_string(),
+            RankKind::Video => humantime::format_duration(score.video_score).to_string(),
+            RankKind::Stage => humantime::format_duration(score.stage_score).to_string(),
+        }
+    }
+
+    /// Sorts scores from the highest to the lowest by this kind.
+    pub fn sort(&self, scores: &mut [Score]) {
+        match self {
+            RankKind::Voice => scores.sort_by_key(|s| std::cmp::Reverse(s.score)),
+            RankKind::Afk => scores.sort_by_key(|s| std::cmp::Reverse(s.afk_score)),
+            RankKind::Total => scores.sort_by_key(|s| std::cmp::Reverse(s.total())),
+            RankKind::ActiveRatio => {
+                scores.sort_by(|a, b| b.active_ratio().total_cmp(&a.active_ratio()))
+            }
+            RankKind::Stream => scores.sort_by_key(|s| std::cmp::Reverse(s.stream_score)),
+            RankKind::Video => scores.sort_by_key(|s| std::cmp::Reverse(s.video_score)),
+            RankKind::Stage => scores.sort_by_key(|s| std::cmp::Reverse(s.stage_score)),
+        }
+    }
+
+    /// Whether only the all time leaderboard keeps track of this kind.
+    pub fn is_all_time_only(&self) -> bool {
+        matches!(self, RankKind::Stream | RankKind::Video | RankKind::Stage)
+    }
+}
+
+impl ScoreType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScoreType::Voice => "voice",
+            ScoreType::Afk => "afk",
+        }
+    }
+}
+
+impl std::str::FromStr for ScoreType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "voice" => Ok(ScoreType::Voice),
+            "afk" => Ok(ScoreType::Afk),
+            _ => anyhow::bail!("Unknown score type `{s}`"),
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use poise::serenity_prelude::{ChannelId, Guild, UserId};
+use tracing::{info, warn};
+
+use crate::{
+    activity::{self, Extras},
+    event::{apply, get_afk_channel, go_out_afk, go_out_voice},
+    outbox,
+    score::{GuildUser, ScoreType},
+    Data, VoiceSession,
+};
+
+/// A session kept open by `open`, as read back. Whatever can't be read is `None`.
+#[derive(Debug, Clone, Copy)]
+pub struct StoredSession {
+    pub user_id: UserId,
+    pub kind: Option<ScoreType>,
+    /// `None` for sessions stored before channels were recorded.
+    pub channel_id: Option<ChannelId>,
+    /// `None` for sessions stored before join times were recorded.
+    pub joined_at: Option<DateTime<Utc>>,
+    pub started_at: Option<DateTime<Utc>>,
+}
+
+/// What to do with a voice session that was still open when the bot went down.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SessionPolicy {
+    /// Keep the original start time if the member is still in the same kind of channel,
+    /// otherwise throw the session away.
+    #[default]
+    Resume,
+    /// Credit everything from the original start time up to now, then start a fresh session
+    /// if the member is still around.
+    Credit,
+    /// Throw every stored session away.
+    Discard,
+}
+
+impl std::str::FromStr for SessionPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "resume" => Ok(SessionPolicy::Resume),
+            "credit" => Ok(SessionPolicy::Credit),
+            "discard" => Ok(SessionPolicy::Discard),
+            _ => anyhow::bail!("Unknown session policy `{s}`"),
+        }
+    }
+}
+
+/// Stores an open session so it survives a restart.
+pub async fn open(
+    data: &Data,
+    guild_user: GuildUser,
+    score_type: ScoreType,
+    session: VoiceSession,
+) -> Result<()> {
+    data.storage
+        .open_session(guild_user, score_type, session)
+        .await
+}
+
+/// Marks the member's stored session as closed.
+pub async fn close(data: &Data, guild_user: GuildUser) -> Result<()> {
+    data.storage.close_session(guild_user).await
+}
+
+/// Appends a closed session to the voice history log.
+///
+/// The log goes through the outbox like the time it credits, so a failed write ends up in
+/// both once pocketbase is back instead of only in the scores.
+pub async fn log(
+    data: &Data,
+    guild_user: GuildUser,
+    score_type: ScoreType,
+    session: VoiceSession,
+    ended_at: DateTime<Utc>,
+) -> Result<()> {
+    let res = data
+        .storage
+        .log_session(guild_user, score_type, session, ended_at)
+        .await;
+    outbox::or_outboxed(res)?;
+
+    Ok(())
+}
+
+async fn is_tracked(data: &Data, guild_user: GuildUser) -> bool {
+    let voice_state = data.voice_state.lock().await;
+    matches!(voice_state.sessions.get(&guild_user), Some(Some(_)))
+}
+
+/// Reconciles sessions stored before a restart against who is actually in voice right now.
+#[tracing::instrument(skip_all, fields(guild_id = %guild.id))]
+pub async fn reconcile(data: &Data, guild: &Guild) -> Result<()> {
+    let now = Utc::now();
+    let guild_id = guild.id;
+
+    let sessions = data.storage.list_sessions(guild_id).await?;
+
+    if sessions.is_empty() {
+        return Ok(());
+    }
+
+    let afk_channel = get_afk_channel(data, guild_id).await?;
+    let rules = activity::get_rules(data, guild_id).await?;
+    let presences = activity::presences(guild, afk_channel, rules);
+
+    for stored in sessions {
+        let user_id = stored.user_id;
+        let guild_user = GuildUser(guild_id, user_id);
+
+        if is_tracked(data, guild_user).await {
+            continue;
+        }
+
+        let current_channel = guild
+            .voice_states
+            .get(&user_id)
+            .and_then(|vs| vs.channel_id);
+        let voice = presences.get(&user_id).copied().unwrap_or_default();
+        let current = voice.presence.map(|(score_type, _)| score_type);
+
+        // Sessions stored before channels were recorded fall back to the current channel.
+        let (Some(kind), Some(started_at), Some(channel_id)) = (
+            stored.kind,
+            stored.started_at,
+            stored.channel_id.or(current_channel),
+        ) else {
+            warn!(?stored, "Stored session is malformed, discarding");
+            close(data, guild_user).await?;
+            continue;
+        };
+        // Sessions stored before join times were recorded joined when they were last credited.
+        let joined_at = stored.joined_at.unwrap_or(started_at);
+        // Whatever was going on besides being in voice is picked up again on the next update.
+        let stored_session = VoiceSession {
+            channel_id,
+            joined_at,
+            started_at,
+            extras: Extras::default(),
+        };
+
+        match data.session_policy {
+            SessionPolicy::Resume if current == Some(kind) => {
+                let mut voice_state = data.voice_state.lock().await;
+                voice_state
+                    .sessions
+                    .insert(guild_user, Some(stored_session));
+                info!(%user_id, "Resumed stored session");
+                continue;
+            }
+            SessionPolicy::Credit => {
+                {
+                    let mut voice_state = data.voice_state.lock().await;
+                    voice_state
+                        .sessions
+                        .insert(guild_user, Some(stored_session));
+                }
+                match kind {
+                    ScoreType::Voice => go_out_voice(data, guild_id, user_id, now).await?,
+                    ScoreType::Afk => go_out_afk(data, guild_id, user_id, now).await?,
+                }
+                info!(%user_id, "Credited stored session");
+            }
+            SessionPolicy::Resume | SessionPolicy::Discard => {
+                info!(%user_id, "Discarded stored session");
+            }
+        }
+
+        match voice.presence {
+            Some(_) => apply(data, guild_user, voice, now).await?,
+            None => close(data, guild_user).await?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Starts tracking every member that is already sitting in a voice channel, so that members
+/// who were in voice before the bot came online get credited from the first minute.
+#[tracing::instrument(skip_all, fields(guild_id = %guild.id))]
+pub async fn seed(data: &Data, guild: &Guild) -> Result<()> {
+    let now = Utc::now();
+    let guild_id = guild.id;
+    let afk_channel = get_afk_channel(data, guild_id).await?;
+    let rules = activity::get_rules(data, guild_id).await?;
+
+    for (user_id, voice) in activity::presences(guild, afk_channel, rules) {
+        let guild_user = GuildUser(guild_id, user_id);
+
+        if voice.presence.is_none() || is_tracked(data, guild_user).await {
+            continue;
+        }
+
+        apply(data, guild_user, voice, now).await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,594 @@
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use poise::serenity_prelude::{ChannelId, GuildId, UserId};
+use tokio::sync::Mutex;
+
+use crate::{
+    achievement::Unlocked,
+    activity::{ActivityRules, Extras},
+    channel::ChannelStats,
+    config::Config,
+    milestone::{Milestone, MilestoneMode, Milestones},
+    period::{EndedSeason, Period, Season},
+    score::{GuildUser, Score, ScoreType},
+    session::StoredSession,
+    VoiceSession,
+};
+
+use super::Storage;
+
+/// A logged session's kind, channel and when it ended, by member and when they joined.
+type VoiceLog = HashMap<(GuildUser, DateTime<Utc>), (ScoreType, ChannelId, DateTime<Utc>)>;
+/// Period scores by guild, `Period::as_str` and bucket.
+type PeriodScores = HashMap<(GuildId, &'static str, String), HashMap<UserId, Score>>;
+/// The most members ever in a channel, and when.
+type ChannelPeaks = HashMap<(GuildId, ChannelId), (u64, DateTime<Utc>)>;
+
+/// Keeps everything in memory, nothing survives a restart.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    scores: Mutex<HashMap<GuildUser, Score>>,
+    configs: Mutex<HashMap<GuildId, Config>>,
+    first_seen: Mutex<HashMap<UserId, DateTime<Utc>>>,
+    sessions: Mutex<HashMap<GuildUser, (ScoreType, VoiceSession)>>,
+    voice_log: Mutex<VoiceLog>,
+    period_scores: Mutex<PeriodScores>,
+    /// Running seasons only, ended ones aren't read again.
+    seasons: Mutex<HashMap<GuildId, Season>>,
+    next_season_id: AtomicU64,
+    channel_times: Mutex<HashMap<GuildUser, HashMap<ChannelId, Duration>>>,
+    channel_peaks: Mutex<ChannelPeaks>,
+    achievements: Mutex<HashMap<GuildUser, Vec<Unlocked>>>,
+    milestones: Mutex<HashMap<GuildId, Milestones>>,
+    activity_rules: Mutex<HashMap<GuildId, ActivityRules>>,
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn incr_score(
+        &self,
+        member: GuildUser,
+        score_type: ScoreType,
+        delta: u64,
+        session_length: Option<u64>,
+        extras: Extras,
+    ) -> Result<Score> {
+        self.first_seen
+            .lock()
+            .await
+            .entry(member.1)
+            .or_insert_with(Utc::now);
+
+        let mut scores = self.scores.lock().await;
+        let score = scores
+            .entry(member)
+            .or_insert_with(|| Score::from((member.0, member.1, Duration::ZERO)));
+
+        let delta = Duration::from_secs(delta);
+        match score_type {
+            ScoreType::Voice => score.score += delta,
+            ScoreType::Afk => score.afk_score += delta,
+        }
+        if let Some(session_length) = session_length {
+            score.longest_session = score
+                .longest_session
+                .max(Duration::from_secs(session_length));
+        }
+        if extras.stream {
+            score.stream_score += delta;
+        }
+        if extras.video {
+            score.video_score += delta;
+        }
+        if extras.stage {
+            score.stage_score += delta;
+        }
+
+        Ok(*score)
+    }
+
+    async fn get_score(&self, member: GuildUser) -> Result<Option<Score>> {
+        let scores = self.scores.lock().await;
+
+        Ok(scores.get(&member).copied())
+    }
+
+    async fn delete_score(&self, member: GuildUser) -> Result<()> {
+        let mut scores = self.scores.lock().await;
+        scores.remove(&member);
+        if !scores.keys().any(|other| other.1 == member.1) {
+            self.first_seen.lock().await.remove(&member.1);
+        }
+
+        Ok(())
+    }
+
+    async fn leaderboard(&self, guild_id: GuildId) -> Result<Vec<Score>> {
+        let scores = self.scores.lock().await;
+        let mut scores = scores
+            .values()
+            .filter(|score| score.guild_id == guild_id)
+            .copied()
+            .collect::<Vec<_>>();
+        scores.sort_by(|a, b| b.cmp(a));
+
+        Ok(scores)
+    }
+
+    async fn first_seen(&self, user_id: UserId) -> Result<Option<DateTime<Utc>>> {
+        let first_seen = self.first_seen.lock().await;
+
+        Ok(first_seen.get(&user_id).copied())
+    }
+
+    async fn get_config(&self, guild_id: GuildId) -> Result<Config> {
+        let configs = self.configs.lock().await;
+
+        Ok(configs.get(&guild_id).copied().unwrap_or_default())
+    }
+
+    async fn set_config(
+        &self,
+        guild_id: GuildId,
+        afk_channel: Option<ChannelId>,
+        graveyard: Option<ChannelId>,
+        achievement_channel: Option<ChannelId>,
+    ) -> Result<Config> {
+        let mut configs = self.configs.lock().await;
+        let config = configs.entry(guild_id).or_default();
+
+        if afk_channel.is_some() {
+            config.afk_channel = afk_channel;
+        }
+        if graveyard.is_some() {
+            config.graveyard = graveyard;
+        }
+        if achievement_channel.is_some() {
+            config.achievement_channel = achievement_channel;
+        }
+
+        Ok(*config)
+    }
+
+    async fn open_session(
+        &self,
+        member: GuildUser,
+        score_type: ScoreType,
+        session: VoiceSession,
+    ) -> Result<()> {
+        let mut sessions = self.sessions.lock().await;
+        sessions.insert(member, (score_type, session));
+
+        Ok(())
+    }
+
+    async fn close_session(&self, member: GuildUser) -> Result<()> {
+        let mut sessions = self.sessions.lock().await;
+        sessions.remove(&member);
+
+        Ok(())
+    }
+
+    async fn list_sessions(&self, guild_id: GuildId) -> Result<Vec<StoredSession>> {
+        let sessions = self.sessions.lock().await;
+
+        Ok(sessions
+            .iter()
+            .filter(|(member, _)| member.0 == guild_id)
+            .map(|(member, (score_type, session))| StoredSession {
+                user_id: member.1,
+                kind: Some(*score_type),
+                channel_id: Some(session.channel_id),
+                joined_at: Some(session.joined_at),
+                started_at: Some(session.started_at),
+            })
+            .collect())
+    }
+
+    async fn log_session(
+        &self,
+        member: GuildUser,
+        score_type: ScoreType,
+        session: VoiceSession,
+        ended_at: DateTime<Utc>,
+    ) -> Result<()> {
+        let mut voice_log = self.voice_log.lock().await;
+        voice_log.insert(
+            (member, session.joined_at),
+            (score_type, session.channel_id, ended_at),
+        );
+
+        Ok(())
+    }
+
+    async fn incr_period_scores(
+        &self,
+        member: GuildUser,
+        score_type: ScoreType,
+        delta: u64,
+        buckets: Vec<(Period, String)>,
+    ) -> Result<()> {
+        let mut period_scores = self.period_scores.lock().await;
+        let delta = Duration::from_secs(delta);
+
+        for (period, bucket) in buckets {
+            let score = period_scores
+                .entry((member.0, period.as_str(), bucket))
+                .or_default()
+                .entry(member.1)
+                .or_insert_with(|| Score::from((member.0, member.1, Duration::ZERO)));
+            match score_type {
+                ScoreType::Voice => score.score += delta,
+                ScoreType::Afk => score.afk_score += delta,
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn period_scores(
+        &self,
+        guild_id: GuildId,
+        period: Period,
+        bucket: String,
+    ) -> Result<Vec<Score>> {
+        let period_scores = self.period_scores.lock().await;
+
+        Ok(period_scores
+            .get(&(guild_id, period.as_str(), bucket))
+            .map(|scores| scores.values().copied().collect())
+            .unwrap_or_default())
+    }
+
+    async fn get_season(&self, guild_id: GuildId) -> Result<Option<Season>> {
+        let seasons = self.seasons.lock().await;
+
+        Ok(seasons.get(&guild_id).cloned())
+    }
+
+    async fn start_season(
+        &self,
+        guild_id: GuildId,
+        name: String,
+        _started_at: DateTime<Utc>,
+    ) -> Result<Season> {
+        let mut seasons = self.seasons.lock().await;
+        if let Some(season) = seasons.get(&guild_id) {
+            bail!("Season `{}` is still running!", season.name);
+        }
+
+        let season = Season {
+            id: self
+                .next_season_id
+                .fetch_add(1, Ordering::Relaxed)
+                .to_string(),
+            name,
+        };
+        seasons.insert(guild_id, season.clone());
+
+        Ok(season)
+    }
+
+    async fn end_season(&self, guild_id: GuildId, _ended_at: DateTime<Utc>) -> Result<EndedSeason> {
+        let season = self
+            .seasons
+            .lock()
+            .await
+            .remove(&guild_id)
+            .context("There is no season running right now!")?;
+
+        let mut standings = self
+            .period_scores(guild_id, Period::Season, season.id)
+            .await?;
+        standings.sort_by(|a, b| b.cmp(a));
+
+        Ok(EndedSeason {
+            name: season.name,
+            standings,
+        })
+    }
+
+    async fn incr_channel_score(
+        &self,
+        member: GuildUser,
+        channel_id: ChannelId,
+        delta: u64,
+    ) -> Result<()> {
+        let mut channel_times = self.channel_times.lock().await;
+        *channel_times
+            .entry(member)
+            .or_default()
+            .entry(channel_id)
+            .or_default() += Duration::from_secs(delta);
+
+        Ok(())
+    }
+
+    async fn set_channel_peak(
+        &self,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        users: u64,
+        at: DateTime<Utc>,
+    ) -> Result<()> {
+        let mut channel_peaks = self.channel_peaks.lock().await;
+        let peak = channel_peaks
+            .entry((guild_id, channel_id))
+            .or_insert((0, at));
+        if peak.0 < users {
+            *peak = (users, at);
+        }
+
+        Ok(())
+    }
+
+    async fn channel_stats(&self, guild_id: GuildId) -> Result<Vec<ChannelStats>> {
+        let mut stats: HashMap<ChannelId, ChannelStats> = HashMap::new();
+
+        let channel_times = self.channel_times.lock().await;
+        let times = channel_times
+            .iter()
+            .filter(|(member, _)| member.0 == guild_id)
+            .flat_map(|(_, times)| times);
+        for (channel_id, time) in times {
+            stats
+                .entry(*channel_id)
+                .or_insert_with(|| ChannelStats::new(*channel_id))
+                .time += *time;
+        }
+
+        let channel_peaks = self.channel_peaks.lock().await;
+        let peaks = channel_peaks
+            .iter()
+            .filter(|((peak_guild_id, _), _)| *peak_guild_id == guild_id);
+        for ((_, channel_id), (users, at)) in peaks {
+            let stat = stats
+                .entry(*channel_id)
+                .or_insert_with(|| ChannelStats::new(*channel_id));
+            stat.peak_users = *users;
+            stat.peak_at = Some(*at);
+        }
+
+        Ok(stats.into_values().collect())
+    }
+
+    async fn channel_times(&self, member: GuildUser) -> Result<Vec<(ChannelId, Duration)>> {
+        let channel_times = self.channel_times.lock().await;
+
+        Ok(channel_times
+            .get(&member)
+            .map(|times| times.iter().map(|(id, time)| (*id, *time)).collect())
+            .unwrap_or_default())
+    }
+
+    async fn unlock_achievement(
+        &self,
+        member: GuildUser,
+        achievement: &'static str,
+        unlocked_at: DateTime<Utc>,
+    ) -> Result<()> {
+        let mut achievements = self.achievements.lock().await;
+        let unlocked = achievements.entry(member).or_default();
+        if !unlocked.iter().any(|u| u.achievement == achievement) {
+            unlocked.push(Unlocked {
+                achievement: achievement.to_string(),
+                unlocked_at,
+            });
+        }
+
+        Ok(())
+    }
+
+    async fn achievements(&self, member: GuildUser) -> Result<Vec<Unlocked>> {
+        let achievements = self.achievements.lock().await;
+
+        Ok(achievements.get(&member).cloned().unwrap_or_default())
+    }
+
+    async fn get_milestones(&self, guild_id: GuildId) -> Result<Milestones> {
+        let milestones = self.milestones.lock().await;
+
+        Ok(milestones.get(&guild_id).cloned().unwrap_or_default())
+    }
+
+    async fn set_milestones(
+        &self,
+        guild_id: GuildId,
+        tiers: Option<Vec<Milestone>>,
+        mode: Option<MilestoneMode>,
+    ) -> Result<()> {
+        let mut milestones = self.milestones.lock().await;
+        let milestones = milestones.entry(guild_id).or_default();
+
+        if let Some(mut tiers) = tiers {
+            tiers.sort_by_key(|tier| tier.hours);
+            milestones.tiers = tiers;
+        }
+        if let Some(mode) = mode {
+            milestones.mode = mode;
+        }
+
+        Ok(())
+    }
+
+    async fn get_activity_rules(&self, guild_id: GuildId) -> Result<ActivityRules> {
+        let activity_rules = self.activity_rules.lock().await;
+
+        Ok(activity_rules.get(&guild_id).copied().unwrap_or_default())
+    }
+
+    async fn set_activity_rules(&self, guild_id: GuildId, rules: ActivityRules) -> Result<()> {
+        let mut activity_rules = self.activity_rules.lock().await;
+        activity_rules.insert(guild_id, rules);
+
+        Ok(())
+    }
+
+    async fn forget_member(&self, member: GuildUser) -> Result<()> {
+        self.sessions.lock().await.remove(&member);
+        self.voice_log
+            .lock()
+            .await
+            .retain(|(logged, _), _| *logged != member);
+        for ((guild_id, _, _), scores) in self.period_scores.lock().await.iter_mut() {
+            if *guild_id == member.0 {
+                scores.remove(&member.1);
+            }
+        }
+        self.channel_times.lock().await.remove(&member);
+        self.achievements.lock().await.remove(&member);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GUILD: GuildId = GuildId::new(1);
+
+    fn member(user_id: u64) -> GuildUser {
+        GuildUser(GUILD, UserId::new(user_id))
+    }
+
+    #[tokio::test]
+    async fn incr_score_credits_by_score_type() {
+        let storage = MemoryStorage::default();
+        let extras = Extras::default();
+
+        storage
+            .incr_score(member(1), ScoreType::Voice, 60, None, extras)
+            .await
+            .unwrap();
+        let score = storage
+            .incr_score(member(1), ScoreType::Afk, 30, None, extras)
+            .await
+            .unwrap();
+
+        assert_eq!(score.score, Duration::from_secs(60));
+        assert_eq!(score.afk_score, Duration::from_secs(30));
+        assert_eq!(storage.get_score(member(1)).await.unwrap(), Some(score));
+        assert_eq!(storage.get_score(member(2)).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn incr_score_credits_extras_alongside() {
+        let storage = MemoryStorage::default();
+        let extras = Extras {
+            stream: true,
+            video: false,
+            stage: true,
+        };
+
+        let score = storage
+            .incr_score(member(1), ScoreType::Voice, 60, None, extras)
+            .await
+            .unwrap();
+
+        assert_eq!(score.score, Duration::from_secs(60));
+        assert_eq!(score.stream_score, Duration::from_secs(60));
+        assert_eq!(score.video_score, Duration::ZERO);
+        assert_eq!(score.stage_score, Duration::from_secs(60));
+    }
+
+    #[tokio::test]
+    async fn incr_score_keeps_the_longest_session() {
+        let storage = MemoryStorage::default();
+        let extras = Extras::default();
+
+        for session_length in [Some(300), None, Some(100)] {
+            storage
+                .incr_score(member(1), ScoreType::Voice, 10, session_length, extras)
+                .await
+                .unwrap();
+        }
+
+        let score = storage.get_score(member(1)).await.unwrap().unwrap();
+        assert_eq!(score.longest_session, Duration::from_secs(300));
+    }
+
+    #[tokio::test]
+    async fn leaderboard_is_sorted_and_per_guild() {
+        let storage = MemoryStorage::default();
+        let extras = Extras::default();
+
+        for (user_id, delta) in [(1, 10), (2, 30), (3, 20)] {
+            storage
+                .incr_score(member(user_id), ScoreType::Voice, delta, None, extras)
+                .await
+                .unwrap();
+        }
+        let elsewhere = GuildUser(GuildId::new(2), UserId::new(4));
+        storage
+            .incr_score(elsewhere, ScoreType::Voice, 40, None, extras)
+            .await
+            .unwrap();
+
+        let leaderboard = storage.leaderboard(GUILD).await.unwrap();
+        let user_ids = leaderboard
+            .iter()
+            .map(|score| score.user_id.get())
+            .collect::<Vec<_>>();
+        assert_eq!(user_ids, [2, 3, 1]);
+    }
+
+    #[tokio::test]
+    async fn first_seen_goes_with_the_last_score() {
+        let storage = MemoryStorage::default();
+        let extras = Extras::default();
+        let elsewhere = GuildUser(GuildId::new(2), UserId::new(1));
+
+        assert_eq!(storage.first_seen(UserId::new(1)).await.unwrap(), None);
+        for member in [member(1), elsewhere] {
+            storage
+                .incr_score(member, ScoreType::Voice, 10, None, extras)
+                .await
+                .unwrap();
+        }
+        let first_seen = storage.first_seen(UserId::new(1)).await.unwrap();
+        assert!(first_seen.is_some());
+
+        storage.delete_score(member(1)).await.unwrap();
+        assert_eq!(
+            storage.first_seen(UserId::new(1)).await.unwrap(),
+            first_seen
+        );
+        storage.delete_score(elsewhere).await.unwrap();
+        assert_eq!(storage.first_seen(UserId::new(1)).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn set_config_leaves_none_as_it_is() {
+        let storage = MemoryStorage::default();
+        let afk_channel = ChannelId::new(10);
+        let graveyard = ChannelId::new(20);
+
+        storage
+            .set_config(GUILD, Some(afk_channel), Some(graveyard), None)
+            .await
+            .unwrap();
+        let config = storage
+            .set_config(GUILD, None, None, Some(ChannelId::new(30)))
+            .await
+            .unwrap();
+
+        assert_eq!(config.afk_channel, Some(afk_channel));
+        assert_eq!(config.graveyard, Some(graveyard));
+        assert_eq!(config.achievement_channel, Some(ChannelId::new(30)));
+
+        let config = storage.get_config(GUILD).await.unwrap();
+        assert_eq!(config.afk_channel, Some(afk_channel));
+        assert_eq!(
+            storage.get_config(GuildId::new(2)).await.unwrap().graveyard,
+            None
+        );
+    }
+}
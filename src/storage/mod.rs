@@ -0,0 +1,204 @@
+mod memory;
+mod pocketbase;
+mod redis;
+mod sqlite;
+
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use poise::serenity_prelude::{ChannelId, GuildId, UserId};
+
+use crate::{
+    achievement::Unlocked,
+    activity::{ActivityRules, Extras},
+    channel::ChannelStats,
+    config::Config,
+    milestone::{Milestone, MilestoneMode, Milestones},
+    period::{EndedSeason, Period, Season},
+    score::{GuildUser, Score, ScoreType},
+    session::StoredSession,
+    VoiceSession,
+};
+
+pub use self::memory::MemoryStorage;
+pub use self::pocketbase::PocketbaseStorage;
+pub use self::redis::RedisStorage;
+pub use self::sqlite::SqliteStorage;
+
+/// Where everything the bot keeps is, from members' scores and guilds' configs to open
+/// sessions, the voice history log, period and channel scores, seasons, achievements,
+/// milestone roles and activity rules.
+///
+/// Writes that pocketbase failed but the outbox kept fail with `Outboxed`, see
+/// `outbox::or_outboxed`.
+#[async_trait]
+pub trait Storage: std::fmt::Debug + Send + Sync {
+    /// Credits `delta` seconds to a member, returning their score afterwards.
+    /// `session_length` is the whole session so far, if it's over.
+    async fn incr_score(
+        &self,
+        member: GuildUser,
+        score_type: ScoreType,
+        delta: u64,
+        session_length: Option<u64>,
+        extras: Extras,
+    ) -> Result<Score>;
+
+    /// Gets a member's score, `None` if they have never been credited.
+    async fn get_score(&self, member: GuildUser) -> Result<Option<Score>>;
+
+    /// Forgets a member's score, does nothing if they have none.
+    async fn delete_score(&self, member: GuildUser) -> Result<()>;
+
+    /// Gets every member's score in a guild, sorted from the highest voice time.
+    async fn leaderboard(&self, guild_id: GuildId) -> Result<Vec<Score>>;
+
+    /// When a user was first credited in any guild, `None` if they never were or it's unknown.
+    /// Forgotten along with their last score.
+    async fn first_seen(&self, user_id: UserId) -> Result<Option<DateTime<Utc>>>;
+
+    async fn get_config(&self, guild_id: GuildId) -> Result<Config>;
+
+    /// Saves a guild's channels, leaving whatever is `None` as it is.
+    async fn set_config(
+        &self,
+        guild_id: GuildId,
+        afk_channel: Option<ChannelId>,
+        graveyard: Option<ChannelId>,
+        achievement_channel: Option<ChannelId>,
+    ) -> Result<Config>;
+
+    /// Keeps the session a member is in, so it survives a restart.
+    async fn open_session(
+        &self,
+        member: GuildUser,
+        score_type: ScoreType,
+        session: VoiceSession,
+    ) -> Result<()>;
+
+    async fn close_session(&self, member: GuildUser) -> Result<()>;
+
+    /// Every session of a guild that was open when last kept.
+    async fn list_sessions(&self, guild_id: GuildId) -> Result<Vec<StoredSession>>;
+
+    /// Appends a closed session to the voice history log. Logging the same one again changes
+    /// nothing.
+    async fn log_session(
+        &self,
+        member: GuildUser,
+        score_type: ScoreType,
+        session: VoiceSession,
+        ended_at: DateTime<Utc>,
+    ) -> Result<()>;
+
+    /// Adds `delta` seconds to every one of the member's `buckets`.
+    async fn incr_period_scores(
+        &self,
+        member: GuildUser,
+        score_type: ScoreType,
+        delta: u64,
+        buckets: Vec<(Period, String)>,
+    ) -> Result<()>;
+
+    /// Gets every member's voice and AFK time in a period bucket, in no particular order.
+    async fn period_scores(
+        &self,
+        guild_id: GuildId,
+        period: Period,
+        bucket: String,
+    ) -> Result<Vec<Score>>;
+
+    /// The season running in a guild, if any.
+    async fn get_season(&self, guild_id: GuildId) -> Result<Option<Season>>;
+
+    /// Fails if a season is running already.
+    async fn start_season(
+        &self,
+        guild_id: GuildId,
+        name: String,
+        started_at: DateTime<Utc>,
+    ) -> Result<Season>;
+
+    /// Ends the running season with its standings as they are, fails if there is none.
+    async fn end_season(&self, guild_id: GuildId, ended_at: DateTime<Utc>) -> Result<EndedSeason>;
+
+    /// Adds `delta` seconds to the time a member has spent in a channel.
+    async fn incr_channel_score(
+        &self,
+        member: GuildUser,
+        channel_id: ChannelId,
+        delta: u64,
+    ) -> Result<()>;
+
+    /// Keeps `users` as the channel's peak, unless it had as many before.
+    async fn set_channel_peak(
+        &self,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        users: u64,
+        at: DateTime<Utc>,
+    ) -> Result<()>;
+
+    /// Every channel of a guild that has been used, in no particular order.
+    async fn channel_stats(&self, guild_id: GuildId) -> Result<Vec<ChannelStats>>;
+
+    /// The time a member has spent in each channel.
+    async fn channel_times(&self, member: GuildUser) -> Result<Vec<(ChannelId, Duration)>>;
+
+    async fn unlock_achievement(
+        &self,
+        member: GuildUser,
+        achievement: &'static str,
+        unlocked_at: DateTime<Utc>,
+    ) -> Result<()>;
+
+    async fn achievements(&self, member: GuildUser) -> Result<Vec<Unlocked>>;
+
+    /// Gets a guild's milestone roles, sorted from the lowest tier.
+    async fn get_milestones(&self, guild_id: GuildId) -> Result<Milestones>;
+
+    /// Saves a guild's milestone roles, leaving whatever is `None` as it is.
+    async fn set_milestones(
+        &self,
+        guild_id: GuildId,
+        tiers: Option<Vec<Milestone>>,
+        mode: Option<MilestoneMode>,
+    ) -> Result<()>;
+
+    async fn get_activity_rules(&self, guild_id: GuildId) -> Result<ActivityRules>;
+
+    async fn set_activity_rules(&self, guild_id: GuildId, rules: ActivityRules) -> Result<()>;
+
+    /// Deletes everything kept about a member in a guild besides their score, see
+    /// `delete_score` for that.
+    async fn forget_member(&self, member: GuildUser) -> Result<()>;
+}
+
+/// Which `Storage` the bot keeps everything in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum StorageKind {
+    #[default]
+    Pocketbase,
+    /// Scores and configs only, pocketbase keeps everything else.
+    Redis,
+    /// A local file, needs neither pocketbase nor redis.
+    Sqlite,
+    /// Gone on restart, for trying the bot out.
+    Memory,
+}
+
+impl std::str::FromStr for StorageKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "pocketbase" => Ok(StorageKind::Pocketbase),
+            "redis" => Ok(StorageKind::Redis),
+            "sqlite" => Ok(StorageKind::Sqlite),
+            "memory" => Ok(StorageKind::Memory),
+            _ => anyhow::bail!("Unknown storage `{s}`"),
+        }
+    }
+}
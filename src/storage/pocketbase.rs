@@ -0,0 +1,456 @@
+use std::{collections::HashMap, time::Duration};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use poise::serenity_prelude::{ChannelId, GuildId, UserId};
+use tokio::sync::{mpsc, oneshot};
+use tracing::warn;
+
+use crate::{
+    achievement::Unlocked,
+    activity::{ActivityRules, Extras},
+    channel::ChannelStats,
+    config::Config,
+    milestone::{Milestone, MilestoneMode, Milestones},
+    period::{EndedSeason, Period, Season},
+    pocketbase::{
+        self as pb,
+        manager::Responder,
+        records::{GuildRecord, Record, ScoreRecord, SeasonRecord, SessionRecord},
+    },
+    score::{GuildUser, Score, ScoreType},
+    session::StoredSession,
+    VoiceSession,
+};
+
+use super::Storage;
+
+/// Goes through the pocketbase `Manager`, a collection for each kind of record.
+#[derive(Debug, Clone)]
+pub struct PocketbaseStorage {
+    tx: mpsc::Sender<pb::Command>,
+}
+
+impl PocketbaseStorage {
+    pub fn new(tx: mpsc::Sender<pb::Command>) -> Self {
+        PocketbaseStorage { tx }
+    }
+
+    /// Sends the command `new` makes and waits for its answer.
+    async fn send<T>(&self, new: impl FnOnce(Responder<T>) -> pb::Command) -> Result<T> {
+        let (tx, rx) = oneshot::channel();
+        self.tx.send(new(tx)).await?;
+
+        rx.await?
+    }
+}
+
+fn to_season(record: &SeasonRecord) -> Season {
+    Season {
+        id: record.id().to_string(),
+        name: record.name.clone(),
+    }
+}
+
+fn to_stored_session(record: &SessionRecord) -> Option<StoredSession> {
+    let Ok(user_id) = record.user_id.parse::<u64>().map(UserId::new) else {
+        warn!(?record, "Stored session has an invalid user id");
+        return None;
+    };
+    // Closed sessions are kept with everything at zero.
+    let at = |secs: i64| DateTime::from_timestamp(secs, 0).filter(|_| secs > 0);
+
+    Some(StoredSession {
+        user_id,
+        kind: record.kind.parse::<ScoreType>().ok(),
+        channel_id: record.channel_id.parse::<u64>().ok().map(ChannelId::new),
+        joined_at: at(record.joined_at),
+        started_at: at(record.started_at),
+    })
+}
+
+fn to_milestones(record: &GuildRecord) -> Result<Milestones> {
+    let mut tiers: Vec<Milestone> = if record.milestone_roles.is_null() {
+        Vec::new()
+    } else {
+        serde_json::from_value(record.milestone_roles.clone())?
+    };
+    tiers.sort_by_key(|tier| tier.hours);
+
+    Ok(Milestones {
+        mode: record.milestone_mode.parse()?,
+        tiers,
+    })
+}
+
+fn to_score(member: GuildUser, record: &ScoreRecord) -> Score {
+    Score {
+        guild_id: member.0,
+        user_id: member.1,
+        score: Duration::from_secs(record.voice_time),
+        afk_score: Duration::from_secs(record.afk_time),
+        stream_score: Duration::from_secs(record.stream_time),
+        video_score: Duration::from_secs(record.video_time),
+        stage_score: Duration::from_secs(record.stage_time),
+        longest_session: Duration::from_secs(record.longest_session),
+    }
+}
+
+#[async_trait]
+impl Storage for PocketbaseStorage {
+    async fn incr_score(
+        &self,
+        member: GuildUser,
+        score_type: ScoreType,
+        delta: u64,
+        session_length: Option<u64>,
+        extras: Extras,
+    ) -> Result<Score> {
+        let (tx, rx) = oneshot::channel();
+        let cmd =
+            pb::Command::new_incr_score(member, delta, session_length, extras, tx, score_type);
+        self.tx.send(cmd).await?;
+        let record = rx.await??;
+
+        Ok(to_score(member, &record))
+    }
+
+    async fn get_score(&self, member: GuildUser) -> Result<Option<Score>> {
+        let (tx, rx) = oneshot::channel();
+        let cmd = pb::Command::new_get_score(member, tx);
+        self.tx.send(cmd).await?;
+        let record = rx.await??;
+
+        Ok(record.map(|record| to_score(member, &record)))
+    }
+
+    async fn delete_score(&self, member: GuildUser) -> Result<()> {
+        let (tx, rx) = oneshot::channel();
+        let cmd = pb::Command::new_delete_score(member, tx);
+        self.tx.send(cmd).await?;
+        rx.await??;
+
+        Ok(())
+    }
+
+    async fn leaderboard(&self, guild_id: GuildId) -> Result<Vec<Score>> {
+        let (tx, rx) = oneshot::channel();
+        let cmd = pb::Command::new_list_scores(guild_id, tx);
+        self.tx.send(cmd).await?;
+        let records = rx.await??;
+
+        let mut scores = records
+            .into_iter()
+            .map(|(player, record)| {
+                let user_id = UserId::new(player.user_id.parse::<u64>()?);
+                Ok(to_score(GuildUser(guild_id, user_id), &record))
+            })
+            .collect::<Result<Vec<Score>>>()?;
+        scores.sort_by(|a, b| b.cmp(a));
+
+        Ok(scores)
+    }
+
+    async fn first_seen(&self, user_id: UserId) -> Result<Option<DateTime<Utc>>> {
+        let (tx, rx) = oneshot::channel();
+        let cmd = pb::Command::new_get_player(user_id, tx);
+        self.tx.send(cmd).await?;
+        let player = rx.await??;
+
+        // Pocketbase stamps records when they are created. The player record is shared by every
+        // guild and made on the member's first credit in any of them.
+        Ok(player.and_then(|player| {
+            NaiveDateTime::parse_from_str(&player.default.created, "%Y-%m-%d %H:%M:%S%.fZ")
+                .ok()
+                .map(|created| created.and_utc())
+        }))
+    }
+
+    async fn get_config(&self, guild_id: GuildId) -> Result<Config> {
+        let (tx, rx) = oneshot::channel();
+        let cmd = pb::Command::new_get_config(guild_id, tx);
+        self.tx.send(cmd).await?;
+        let record = rx.await??;
+
+        Ok(Config::from(&record))
+    }
+
+    async fn set_config(
+        &self,
+        guild_id: GuildId,
+        afk_channel: Option<ChannelId>,
+        graveyard: Option<ChannelId>,
+        achievement_channel: Option<ChannelId>,
+    ) -> Result<Config> {
+        let (tx, rx) = oneshot::channel();
+        let cmd =
+            pb::Command::new_set_config(guild_id, afk_channel, graveyard, achievement_channel, tx);
+        self.tx.send(cmd).await?;
+        let record = rx.await??;
+
+        Ok(Config::from(&record))
+    }
+
+    async fn open_session(
+        &self,
+        member: GuildUser,
+        score_type: ScoreType,
+        session: VoiceSession,
+    ) -> Result<()> {
+        self.send(|tx| pb::Command::new_open_session(member, score_type, session, tx))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn close_session(&self, member: GuildUser) -> Result<()> {
+        self.send(|tx| pb::Command::new_close_session(member, tx))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn list_sessions(&self, guild_id: GuildId) -> Result<Vec<StoredSession>> {
+        let records = self
+            .send(|tx| pb::Command::new_list_sessions(guild_id, tx))
+            .await?;
+
+        Ok(records.iter().filter_map(to_stored_session).collect())
+    }
+
+    async fn log_session(
+        &self,
+        member: GuildUser,
+        score_type: ScoreType,
+        session: VoiceSession,
+        ended_at: DateTime<Utc>,
+    ) -> Result<()> {
+        self.send(|tx| pb::Command::new_log_session(member, score_type, session, ended_at, tx))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn incr_period_scores(
+        &self,
+        member: GuildUser,
+        score_type: ScoreType,
+        delta: u64,
+        buckets: Vec<(Period, String)>,
+    ) -> Result<()> {
+        self.send(|tx| pb::Command::new_incr_period_scores(member, delta, score_type, buckets, tx))
+            .await
+    }
+
+    async fn period_scores(
+        &self,
+        guild_id: GuildId,
+        period: Period,
+        bucket: String,
+    ) -> Result<Vec<Score>> {
+        let records = self
+            .send(|tx| pb::Command::new_list_period_scores(guild_id, period, bucket, tx))
+            .await?;
+
+        records
+            .into_iter()
+            .map(|record| {
+                let user_id = UserId::new(record.user_id.parse::<u64>()?);
+                Ok(Score {
+                    guild_id,
+                    user_id,
+                    score: Duration::from_secs(record.voice_time),
+                    afk_score: Duration::from_secs(record.afk_time),
+                    ..Default::default()
+                })
+            })
+            .collect()
+    }
+
+    async fn get_season(&self, guild_id: GuildId) -> Result<Option<Season>> {
+        let record = self
+            .send(|tx| pb::Command::new_get_season(guild_id, tx))
+            .await?;
+
+        Ok(record.as_ref().map(to_season))
+    }
+
+    async fn start_season(
+        &self,
+        guild_id: GuildId,
+        name: String,
+        started_at: DateTime<Utc>,
+    ) -> Result<Season> {
+        let record = self
+            .send(|tx| pb::Command::new_start_season(guild_id, name, started_at, tx))
+            .await?;
+
+        Ok(to_season(&record))
+    }
+
+    async fn end_season(&self, guild_id: GuildId, ended_at: DateTime<Utc>) -> Result<EndedSeason> {
+        let record = self
+            .send(|tx| pb::Command::new_end_season(guild_id, ended_at, tx))
+            .await?;
+
+        let standings = record.standings.as_array().cloned().unwrap_or_default();
+        let standings = standings
+            .iter()
+            .filter_map(|standing| {
+                let user_id = standing["user_id"].as_str()?.parse::<u64>().ok()?;
+                Some(Score {
+                    guild_id,
+                    user_id: UserId::new(user_id),
+                    score: Duration::from_secs(standing["voice_time"].as_u64()?),
+                    afk_score: Duration::from_secs(standing["afk_time"].as_u64().unwrap_or(0)),
+                    ..Default::default()
+                })
+            })
+            .collect();
+
+        Ok(EndedSeason {
+            name: record.name,
+            standings,
+        })
+    }
+
+    async fn incr_channel_score(
+        &self,
+        member: GuildUser,
+        channel_id: ChannelId,
+        delta: u64,
+    ) -> Result<()> {
+        self.send(|tx| pb::Command::new_incr_channel_score(member, channel_id, delta, tx))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn set_channel_peak(
+        &self,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        users: u64,
+        at: DateTime<Utc>,
+    ) -> Result<()> {
+        self.send(|tx| pb::Command::new_set_channel_peak(guild_id, channel_id, users, at, tx))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn channel_stats(&self, guild_id: GuildId) -> Result<Vec<ChannelStats>> {
+        let scores = self
+            .send(|tx| pb::Command::new_list_channel_scores(guild_id, None, tx))
+            .await?;
+        let channels = self
+            .send(|tx| pb::Command::new_list_channels(guild_id, tx))
+            .await?;
+
+        let mut stats: HashMap<ChannelId, ChannelStats> = HashMap::new();
+        for score in scores {
+            let channel_id = ChannelId::new(score.channel_id.parse::<u64>()?);
+            stats
+                .entry(channel_id)
+                .or_insert_with(|| ChannelStats::new(channel_id))
+                .time += Duration::from_secs(score.time);
+        }
+        for channel in channels {
+            let channel_id = ChannelId::new(channel.channel_id.parse::<u64>()?);
+            let stat = stats
+                .entry(channel_id)
+                .or_insert_with(|| ChannelStats::new(channel_id));
+            stat.peak_users = channel.peak_users;
+            stat.peak_at = DateTime::from_timestamp(channel.peak_at, 0);
+        }
+
+        Ok(stats.into_values().collect())
+    }
+
+    async fn channel_times(&self, member: GuildUser) -> Result<Vec<(ChannelId, Duration)>> {
+        let scores = self
+            .send(|tx| pb::Command::new_list_channel_scores(member.0, Some(member.1), tx))
+            .await?;
+
+        scores
+            .into_iter()
+            .map(|score| {
+                let channel_id = ChannelId::new(score.channel_id.parse::<u64>()?);
+                Ok((channel_id, Duration::from_secs(score.time)))
+            })
+            .collect()
+    }
+
+    async fn unlock_achievement(
+        &self,
+        member: GuildUser,
+        achievement: &'static str,
+        unlocked_at: DateTime<Utc>,
+    ) -> Result<()> {
+        self.send(|tx| pb::Command::new_unlock_achievement(member, achievement, unlocked_at, tx))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn achievements(&self, member: GuildUser) -> Result<Vec<Unlocked>> {
+        let records = self
+            .send(|tx| pb::Command::new_list_achievements(member, tx))
+            .await?;
+
+        Ok(records
+            .into_iter()
+            .map(|record| Unlocked {
+                achievement: record.achievement,
+                unlocked_at: DateTime::from_timestamp(record.unlocked_at, 0).unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    async fn get_milestones(&self, guild_id: GuildId) -> Result<Milestones> {
+        let record = self
+            .send(|tx| pb::Command::new_get_config(guild_id, tx))
+            .await?;
+
+        to_milestones(&record)
+    }
+
+    async fn set_milestones(
+        &self,
+        guild_id: GuildId,
+        tiers: Option<Vec<Milestone>>,
+        mode: Option<MilestoneMode>,
+    ) -> Result<()> {
+        let tiers = tiers.map(serde_json::to_value).transpose()?;
+        self.send(|tx| pb::Command::new_set_milestones(guild_id, tiers, mode, tx))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_activity_rules(&self, guild_id: GuildId) -> Result<ActivityRules> {
+        let record = self
+            .send(|tx| pb::Command::new_get_config(guild_id, tx))
+            .await?;
+
+        if record.activity_rules.is_null() {
+            return Ok(ActivityRules::default());
+        }
+
+        Ok(serde_json::from_value(record.activity_rules)?)
+    }
+
+    async fn set_activity_rules(&self, guild_id: GuildId, rules: ActivityRules) -> Result<()> {
+        let rules = serde_json::to_value(rules)?;
+        self.send(|tx| pb::Command::new_set_activity_rules(guild_id, rules, tx))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn forget_member(&self, member: GuildUser) -> Result<()> {
+        self.send(|tx| pb::Command::new_forget_member(member, tx))
+            .await
+    }
+}
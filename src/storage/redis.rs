@@ -0,0 +1,423 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use poise::serenity_prelude::{ChannelId, GuildId, UserId};
+use redis::{aio::Connection, AsyncCommands, Script};
+use tokio::sync::Mutex;
+
+use crate::{
+    achievement::Unlocked,
+    activity::{ActivityRules, Extras},
+    channel::ChannelStats,
+    config::Config,
+    database::Redis,
+    milestone::{Milestone, MilestoneMode, Milestones},
+    period::{EndedSeason, Period, Season},
+    score::{GuildUser, Score, ScoreType},
+    session::StoredSession,
+    VoiceSession,
+};
+
+use super::{PocketbaseStorage, Storage};
+
+/// Keeps voice time in the `score:{guild}:{user}` keys the bot always used, with the members
+/// of a guild in the `score:{guild}` set. Everything else a score has is in the
+/// `stats:{guild}:{user}` hash, and configs are in the `config:{guild}` hash. When a user was
+/// first credited is in `first_seen:{user}`, as unix seconds. Everything else goes to
+/// pocketbase, when there is one.
+#[derive(Debug, Clone)]
+pub struct RedisStorage {
+    db: Arc<Mutex<Redis>>,
+    pocketbase: Option<PocketbaseStorage>,
+}
+
+impl RedisStorage {
+    pub fn new(db: Arc<Mutex<Redis>>) -> Self {
+        RedisStorage {
+            db,
+            pocketbase: None,
+        }
+    }
+
+    /// Keeps what isn't a score or a config in `pocketbase`.
+    pub fn with_pocketbase(mut self, pocketbase: PocketbaseStorage) -> Self {
+        self.pocketbase = Some(pocketbase);
+        self
+    }
+
+    fn pocketbase(&self) -> Result<&PocketbaseStorage> {
+        self.pocketbase
+            .as_ref()
+            .context("Redis only keeps scores and configs, the rest needs pocketbase")
+    }
+
+    /// Every member that has a score, in any guild.
+    pub async fn members(&self) -> Result<Vec<GuildUser>> {
+        let mut conn = Redis::get_connection(self.db.clone()).await?;
+
+        scan_keys(&mut conn, "score:*:*")
+            .await?
+            .iter()
+            .map(|key| {
+                let mut ids = key.trim_start_matches("score:").split(':');
+                let (Some(guild_id), Some(user_id), None) = (ids.next(), ids.next(), ids.next())
+                else {
+                    anyhow::bail!("Malformed score key `{key}`");
+                };
+
+                Ok(GuildUser(
+                    GuildId::new(guild_id.parse::<u64>()?),
+                    UserId::new(user_id.parse::<u64>()?),
+                ))
+            })
+            .collect()
+    }
+
+    /// Every guild that has a config.
+    pub async fn guilds(&self) -> Result<Vec<GuildId>> {
+        let mut conn = Redis::get_connection(self.db.clone()).await?;
+
+        scan_keys(&mut conn, "config:*")
+            .await?
+            .iter()
+            .map(|key| {
+                let guild_id = key.trim_start_matches("config:").parse::<u64>()?;
+                Ok(GuildId::new(guild_id))
+            })
+            .collect()
+    }
+}
+
+/// Keeps the larger of the stored longest session and the given one, in a single step so
+/// sessions ending together can't undo each other.
+static LONGEST_SESSION: Lazy<Script> = Lazy::new(|| {
+    Script::new(
+        r#"
+        local longest = tonumber(redis.call("HGET", KEYS[1], "longest_session") or "0")
+        if longest < tonumber(ARGV[1]) then
+            redis.call("HSET", KEYS[1], "longest_session", ARGV[1])
+        end
+        "#,
+    )
+});
+
+async fn scan_keys(conn: &mut Connection, pattern: &str) -> Result<Vec<String>> {
+    let mut iter = conn.scan_match::<_, String>(pattern).await?;
+
+    let mut keys = Vec::new();
+    while let Some(key) = iter.next_item().await {
+        keys.push(key);
+    }
+
+    Ok(keys)
+}
+
+async fn read_score(conn: &mut Connection, member: GuildUser) -> Result<Option<Score>> {
+    let GuildUser(guild_id, user_id) = member;
+
+    let (voice_time, stats): (Option<u64>, HashMap<String, u64>) = redis::pipe()
+        .get(format!("score:{guild_id}:{user_id}"))
+        .hgetall(format!("stats:{guild_id}:{user_id}"))
+        .query_async(conn)
+        .await?;
+    if voice_time.is_none() && stats.is_empty() {
+        return Ok(None);
+    }
+
+    let stat = |field: &str| Duration::from_secs(stats.get(field).copied().unwrap_or_default());
+    Ok(Some(Score {
+        guild_id,
+        user_id,
+        score: Duration::from_secs(voice_time.unwrap_or_default()),
+        afk_score: stat("afk_time"),
+        stream_score: stat("stream_time"),
+        video_score: stat("video_time"),
+        stage_score: stat("stage_time"),
+        longest_session: stat("longest_session"),
+    }))
+}
+
+#[async_trait]
+impl Storage for RedisStorage {
+    async fn incr_score(
+        &self,
+        member: GuildUser,
+        score_type: ScoreType,
+        delta: u64,
+        session_length: Option<u64>,
+        extras: Extras,
+    ) -> Result<Score> {
+        let mut conn = Redis::get_connection(self.db.clone()).await?;
+        let GuildUser(guild_id, user_id) = member;
+        let stats_key = format!("stats:{guild_id}:{user_id}");
+
+        let mut pipe = redis::pipe();
+        pipe.sadd(format!("score:{guild_id}"), user_id.get())
+            .ignore()
+            .set_nx(format!("first_seen:{user_id}"), Utc::now().timestamp())
+            .ignore();
+        match score_type {
+            ScoreType::Voice => pipe
+                .incr(format!("score:{guild_id}:{user_id}"), delta)
+                .ignore(),
+            ScoreType::Afk => pipe.hincr(&stats_key, "afk_time", delta).ignore(),
+        };
+        for (field, enabled) in [
+            ("stream_time", extras.stream),
+            ("video_time", extras.video),
+            ("stage_time", extras.stage),
+        ] {
+            if enabled {
+                pipe.hincr(&stats_key, field, delta).ignore();
+            }
+        }
+        pipe.query_async::<_, ()>(&mut conn).await?;
+
+        if let Some(session_length) = session_length {
+            let _: () = LONGEST_SESSION
+                .key(&stats_key)
+                .arg(session_length)
+                .invoke_async(&mut conn)
+                .await?;
+        }
+
+        read_score(&mut conn, member)
+            .await?
+            .context("Score is missing right after being credited")
+    }
+
+    async fn get_score(&self, member: GuildUser) -> Result<Option<Score>> {
+        let mut conn = Redis::get_connection(self.db.clone()).await?;
+
+        read_score(&mut conn, member).await
+    }
+
+    async fn delete_score(&self, member: GuildUser) -> Result<()> {
+        let mut conn = Redis::get_connection(self.db.clone()).await?;
+        let GuildUser(guild_id, user_id) = member;
+
+        redis::pipe()
+            .srem(format!("score:{guild_id}"), user_id.get())
+            .ignore()
+            .del(format!("score:{guild_id}:{user_id}"))
+            .ignore()
+            .del(format!("stats:{guild_id}:{user_id}"))
+            .ignore()
+            .query_async::<_, ()>(&mut conn)
+            .await?;
+
+        if scan_keys(&mut conn, &format!("score:*:{user_id}"))
+            .await?
+            .is_empty()
+        {
+            let _: () = conn.del(format!("first_seen:{user_id}")).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn leaderboard(&self, guild_id: GuildId) -> Result<Vec<Score>> {
+        let mut conn = Redis::get_connection(self.db.clone()).await?;
+        let user_ids: Vec<u64> = conn.smembers(format!("score:{guild_id}")).await?;
+
+        let mut scores = Vec::with_capacity(user_ids.len());
+        for user_id in user_ids {
+            let member = GuildUser(guild_id, UserId::new(user_id));
+            if let Some(score) = read_score(&mut conn, member).await? {
+                scores.push(score);
+            }
+        }
+        scores.sort_by(|a, b| b.cmp(a));
+
+        Ok(scores)
+    }
+
+    async fn first_seen(&self, user_id: UserId) -> Result<Option<DateTime<Utc>>> {
+        let mut conn = Redis::get_connection(self.db.clone()).await?;
+        let first_seen: Option<i64> = conn.get(format!("first_seen:{user_id}")).await?;
+
+        Ok(first_seen.and_then(|secs| DateTime::from_timestamp(secs, 0)))
+    }
+
+    async fn get_config(&self, guild_id: GuildId) -> Result<Config> {
+        let mut conn = Redis::get_connection(self.db.clone()).await?;
+        let config: Config = conn.hgetall(format!("config:{guild_id}")).await?;
+
+        Ok(config)
+    }
+
+    async fn set_config(
+        &self,
+        guild_id: GuildId,
+        afk_channel: Option<ChannelId>,
+        graveyard: Option<ChannelId>,
+        achievement_channel: Option<ChannelId>,
+    ) -> Result<Config> {
+        let fields = [
+            ("afk_channel", afk_channel),
+            ("graveyard", graveyard),
+            ("achievement_channel", achievement_channel),
+        ]
+        .into_iter()
+        .filter_map(|(field, channel_id)| Some((field, channel_id?.get())))
+        .collect::<Vec<_>>();
+
+        if !fields.is_empty() {
+            let mut conn = Redis::get_connection(self.db.clone()).await?;
+            let _: () = conn
+                .hset_multiple(format!("config:{guild_id}"), &fields)
+                .await?;
+        }
+
+        self.get_config(guild_id).await
+    }
+
+    async fn open_session(
+        &self,
+        member: GuildUser,
+        score_type: ScoreType,
+        session: VoiceSession,
+    ) -> Result<()> {
+        self.pocketbase()?
+            .open_session(member, score_type, session)
+            .await
+    }
+
+    async fn close_session(&self, member: GuildUser) -> Result<()> {
+        self.pocketbase()?.close_session(member).await
+    }
+
+    async fn list_sessions(&self, guild_id: GuildId) -> Result<Vec<StoredSession>> {
+        self.pocketbase()?.list_sessions(guild_id).await
+    }
+
+    async fn log_session(
+        &self,
+        member: GuildUser,
+        score_type: ScoreType,
+        session: VoiceSession,
+        ended_at: DateTime<Utc>,
+    ) -> Result<()> {
+        self.pocketbase()?
+            .log_session(member, score_type, session, ended_at)
+            .await
+    }
+
+    async fn incr_period_scores(
+        &self,
+        member: GuildUser,
+        score_type: ScoreType,
+        delta: u64,
+        buckets: Vec<(Period, String)>,
+    ) -> Result<()> {
+        self.pocketbase()?
+            .incr_period_scores(member, score_type, delta, buckets)
+            .await
+    }
+
+    async fn period_scores(
+        &self,
+        guild_id: GuildId,
+        period: Period,
+        bucket: String,
+    ) -> Result<Vec<Score>> {
+        self.pocketbase()?
+            .period_scores(guild_id, period, bucket)
+            .await
+    }
+
+    async fn get_season(&self, guild_id: GuildId) -> Result<Option<Season>> {
+        self.pocketbase()?.get_season(guild_id).await
+    }
+
+    async fn start_season(
+        &self,
+        guild_id: GuildId,
+        name: String,
+        started_at: DateTime<Utc>,
+    ) -> Result<Season> {
+        self.pocketbase()?
+            .start_season(guild_id, name, started_at)
+            .await
+    }
+
+    async fn end_season(&self, guild_id: GuildId, ended_at: DateTime<Utc>) -> Result<EndedSeason> {
+        self.pocketbase()?.end_season(guild_id, ended_at).await
+    }
+
+    async fn incr_channel_score(
+        &self,
+        member: GuildUser,
+        channel_id: ChannelId,
+        delta: u64,
+    ) -> Result<()> {
+        self.pocketbase()?
+            .incr_channel_score(member, channel_id, delta)
+            .await
+    }
+
+    async fn set_channel_peak(
+        &self,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        users: u64,
+        at: DateTime<Utc>,
+    ) -> Result<()> {
+        self.pocketbase()?
+            .set_channel_peak(guild_id, channel_id, users, at)
+            .await
+    }
+
+    async fn channel_stats(&self, guild_id: GuildId) -> Result<Vec<ChannelStats>> {
+        self.pocketbase()?.channel_stats(guild_id).await
+    }
+
+    async fn channel_times(&self, member: GuildUser) -> Result<Vec<(ChannelId, Duration)>> {
+        self.pocketbase()?.channel_times(member).await
+    }
+
+    async fn unlock_achievement(
+        &self,
+        member: GuildUser,
+        achievement: &'static str,
+        unlocked_at: DateTime<Utc>,
+    ) -> Result<()> {
+        self.pocketbase()?
+            .unlock_achievement(member, achievement, unlocked_at)
+            .await
+    }
+
+    async fn achievements(&self, member: GuildUser) -> Result<Vec<Unlocked>> {
+        self.pocketbase()?.achievements(member).await
+    }
+
+    async fn get_milestones(&self, guild_id: GuildId) -> Result<Milestones> {
+        self.pocketbase()?.get_milestones(guild_id).await
+    }
+
+    async fn set_milestones(
+        &self,
+        guild_id: GuildId,
+        tiers: Option<Vec<Milestone>>,
+        mode: Option<MilestoneMode>,
+    ) -> Result<()> {
+        self.pocketbase()?
+            .set_milestones(guild_id, tiers, mode)
+            .await
+    }
+
+    async fn get_activity_rules(&self, guild_id: GuildId) -> Result<ActivityRules> {
+        self.pocketbase()?.get_activity_rules(guild_id).await
+    }
+
+    async fn set_activity_rules(&self, guild_id: GuildId, rules: ActivityRules) -> Result<()> {
+        self.pocketbase()?.set_activity_rules(guild_id, rules).await
+    }
+
+    async fn forget_member(&self, member: GuildUser) -> Result<()> {
+        self.pocketbase()?.forget_member(member).await
+    }
+}
@@ -0,0 +1,837 @@
+use std::{str::FromStr, time::Duration};
+
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use poise::serenity_prelude::{ChannelId, GuildId, UserId};
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions},
+    FromRow,
+};
+
+use crate::{
+    achievement::Unlocked,
+    activity::{ActivityRules, Extras},
+    channel::ChannelStats,
+    config::Config,
+    milestone::{Milestone, MilestoneMode, Milestones},
+    period::{EndedSeason, Period, Season},
+    score::{GuildUser, Score, ScoreType},
+    session::StoredSession,
+    VoiceSession,
+};
+
+use super::Storage;
+
+/// Keeps everything in a single SQLite file.
+/// The schema lives in `migrations/` and is brought up to date on connect.
+#[derive(Debug, Clone)]
+pub struct SqliteStorage {
+    pool: SqlitePool,
+}
+
+impl SqliteStorage {
+    /// Opens the database at `url`, creating it if it doesn't exist yet.
+    pub async fn connect(url: &str) -> Result<Self> {
+        let options = SqliteConnectOptions::from_str(url)?.create_if_missing(true);
+        let pool = SqlitePoolOptions::new().connect_with(options).await?;
+        sqlx::migrate!().run(&pool).await?;
+
+        Ok(SqliteStorage { pool })
+    }
+}
+
+#[derive(Debug, FromRow)]
+struct ScoreRow {
+    user_id: i64,
+    voice_time: i64,
+    afk_time: i64,
+    longest_session: i64,
+    stream_time: i64,
+    video_time: i64,
+    stage_time: i64,
+}
+
+impl ScoreRow {
+    fn into_score(self, guild_id: GuildId) -> Score {
+        let secs = |secs: i64| Duration::from_secs(secs as u64);
+
+        Score {
+            guild_id,
+            user_id: UserId::new(self.user_id as u64),
+            score: secs(self.voice_time),
+            afk_score: secs(self.afk_time),
+            stream_score: secs(self.stream_time),
+            video_score: secs(self.video_time),
+            stage_score: secs(self.stage_time),
+            longest_session: secs(self.longest_session),
+        }
+    }
+}
+
+fn at(secs: i64) -> Option<DateTime<Utc>> {
+    DateTime::from_timestamp(secs, 0)
+}
+
+#[derive(Debug, FromRow)]
+struct SessionRow {
+    user_id: i64,
+    channel_id: i64,
+    kind: String,
+    joined_at: i64,
+    started_at: i64,
+}
+
+impl From<SessionRow> for StoredSession {
+    fn from(row: SessionRow) -> Self {
+        StoredSession {
+            user_id: UserId::new(row.user_id as u64),
+            kind: row.kind.parse().ok(),
+            channel_id: Some(ChannelId::new(row.channel_id as u64)),
+            joined_at: at(row.joined_at),
+            started_at: at(row.started_at),
+        }
+    }
+}
+
+#[derive(Debug, FromRow)]
+struct PeriodScoreRow {
+    user_id: i64,
+    voice_time: i64,
+    afk_time: i64,
+}
+
+impl PeriodScoreRow {
+    fn into_score(self, guild_id: GuildId) -> Score {
+        Score {
+            guild_id,
+            user_id: UserId::new(self.user_id as u64),
+            score: Duration::from_secs(self.voice_time as u64),
+            afk_score: Duration::from_secs(self.afk_time as u64),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, FromRow)]
+struct SeasonRow {
+    id: i64,
+    name: String,
+}
+
+impl From<SeasonRow> for Season {
+    fn from(row: SeasonRow) -> Self {
+        Season {
+            id: row.id.to_string(),
+            name: row.name,
+        }
+    }
+}
+
+#[derive(Debug, FromRow)]
+struct GuildRow {
+    afk_channel: Option<i64>,
+    graveyard: Option<i64>,
+    achievement_channel: Option<i64>,
+}
+
+impl From<GuildRow> for Config {
+    fn from(row: GuildRow) -> Self {
+        let channel = |id: Option<i64>| id.map(|id| ChannelId::new(id as u64));
+
+        Config {
+            graveyard: channel(row.graveyard),
+            afk_channel: channel(row.afk_channel),
+            achievement_channel: channel(row.achievement_channel),
+        }
+    }
+}
+
+#[async_trait]
+impl Storage for SqliteStorage {
+    async fn incr_score(
+        &self,
+        member: GuildUser,
+        score_type: ScoreType,
+        delta: u64,
+        session_length: Option<u64>,
+        extras: Extras,
+    ) -> Result<Score> {
+        let GuildUser(guild_id, user_id) = member;
+        let delta = delta as i64;
+        let only = |enabled: bool| if enabled { delta } else { 0 };
+
+        sqlx::query("INSERT OR IGNORE INTO players (user_id, first_seen) VALUES (?, ?)")
+            .bind(user_id.get() as i64)
+            .bind(Utc::now().timestamp())
+            .execute(&self.pool)
+            .await?;
+
+        let row = sqlx::query_as::<_, ScoreRow>(
+            "INSERT INTO scores (guild_id, user_id, voice_time, afk_time, longest_session, \
+                stream_time, video_time, stage_time) \
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?) \
+            ON CONFLICT (guild_id, user_id) DO UPDATE SET \
+                voice_time = voice_time + excluded.voice_time, \
+                afk_time = afk_time + excluded.afk_time, \
+                longest_session = MAX(longest_session, excluded.longest_session), \
+                stream_time = stream_time + excluded.stream_time, \
+                video_time = video_time + excluded.video_time, \
+                stage_time = stage_time + excluded.stage_time \
+            RETURNING *",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(user_id.get() as i64)
+        .bind(only(score_type == ScoreType::Voice))
+        .bind(only(score_type == ScoreType::Afk))
+        .bind(session_length.unwrap_or_default() as i64)
+        .bind(only(extras.stream))
+        .bind(only(extras.video))
+        .bind(only(extras.stage))
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(row.into_score(guild_id))
+    }
+
+    async fn get_score(&self, member: GuildUser) -> Result<Option<Score>> {
+        let GuildUser(guild_id, user_id) = member;
+
+        let row = sqlx::query_as::<_, ScoreRow>(
+            "SELECT * FROM scores WHERE guild_id = ? AND user_id = ?",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(user_id.get() as i64)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|row| row.into_score(guild_id)))
+    }
+
+    async fn delete_score(&self, member: GuildUser) -> Result<()> {
+        let GuildUser(guild_id, user_id) = member;
+
+        sqlx::query("DELETE FROM scores WHERE guild_id = ? AND user_id = ?")
+            .bind(guild_id.get() as i64)
+            .bind(user_id.get() as i64)
+            .execute(&self.pool)
+            .await?;
+        sqlx::query(
+            "DELETE FROM players WHERE user_id = ?1 \
+            AND NOT EXISTS (SELECT 1 FROM scores WHERE user_id = ?1)",
+        )
+        .bind(user_id.get() as i64)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn leaderboard(&self, guild_id: GuildId) -> Result<Vec<Score>> {
+        let rows = sqlx::query_as::<_, ScoreRow>(
+            "SELECT * FROM scores WHERE guild_id = ? ORDER BY voice_time DESC",
+        )
+        .bind(guild_id.get() as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| row.into_score(guild_id))
+            .collect())
+    }
+
+    async fn first_seen(&self, user_id: UserId) -> Result<Option<DateTime<Utc>>> {
+        let first_seen: Option<i64> =
+            sqlx::query_scalar("SELECT first_seen FROM players WHERE user_id = ?")
+                .bind(user_id.get() as i64)
+                .fetch_optional(&self.pool)
+                .await?;
+
+        Ok(first_seen.and_then(|secs| DateTime::from_timestamp(secs, 0)))
+    }
+
+    async fn get_config(&self, guild_id: GuildId) -> Result<Config> {
+        let row = sqlx::query_as::<_, GuildRow>("SELECT * FROM guilds WHERE guild_id = ?")
+            .bind(guild_id.get() as i64)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.map(Config::from).unwrap_or_default())
+    }
+
+    async fn set_config(
+        &self,
+        guild_id: GuildId,
+        afk_channel: Option<ChannelId>,
+        graveyard: Option<ChannelId>,
+        achievement_channel: Option<ChannelId>,
+    ) -> Result<Config> {
+        let id = |channel_id: Option<ChannelId>| channel_id.map(|id| id.get() as i64);
+
+        let row = sqlx::query_as::<_, GuildRow>(
+            "INSERT INTO guilds (guild_id, afk_channel, graveyard, achievement_channel) \
+            VALUES (?, ?, ?, ?) \
+            ON CONFLICT (guild_id) DO UPDATE SET \
+                afk_channel = COALESCE(excluded.afk_channel, afk_channel), \
+                graveyard = COALESCE(excluded.graveyard, graveyard), \
+                achievement_channel = COALESCE(excluded.achievement_channel, achievement_channel) \
+            RETURNING *",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(id(afk_channel))
+        .bind(id(graveyard))
+        .bind(id(achievement_channel))
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(Config::from(row))
+    }
+
+    async fn open_session(
+        &self,
+        member: GuildUser,
+        score_type: ScoreType,
+        session: VoiceSession,
+    ) -> Result<()> {
+        let GuildUser(guild_id, user_id) = member;
+
+        sqlx::query(
+            "INSERT OR REPLACE INTO sessions \
+                (guild_id, user_id, channel_id, kind, joined_at, started_at) \
+            VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(user_id.get() as i64)
+        .bind(session.channel_id.get() as i64)
+        .bind(score_type.as_str())
+        .bind(session.joined_at.timestamp())
+        .bind(session.started_at.timestamp())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn close_session(&self, member: GuildUser) -> Result<()> {
+        let GuildUser(guild_id, user_id) = member;
+
+        sqlx::query("DELETE FROM sessions WHERE guild_id = ? AND user_id = ?")
+            .bind(guild_id.get() as i64)
+            .bind(user_id.get() as i64)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn list_sessions(&self, guild_id: GuildId) -> Result<Vec<StoredSession>> {
+        let rows = sqlx::query_as::<_, SessionRow>("SELECT * FROM sessions WHERE guild_id = ?")
+            .bind(guild_id.get() as i64)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.into_iter().map(StoredSession::from).collect())
+    }
+
+    async fn log_session(
+        &self,
+        member: GuildUser,
+        score_type: ScoreType,
+        session: VoiceSession,
+        ended_at: DateTime<Utc>,
+    ) -> Result<()> {
+        let GuildUser(guild_id, user_id) = member;
+
+        sqlx::query(
+            "INSERT OR REPLACE INTO voice_sessions \
+                (guild_id, user_id, channel_id, kind, started_at, ended_at) \
+            VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(user_id.get() as i64)
+        .bind(session.channel_id.get() as i64)
+        .bind(score_type.as_str())
+        .bind(session.joined_at.timestamp())
+        .bind(ended_at.timestamp())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn incr_period_scores(
+        &self,
+        member: GuildUser,
+        score_type: ScoreType,
+        delta: u64,
+        buckets: Vec<(Period, String)>,
+    ) -> Result<()> {
+        let GuildUser(guild_id, user_id) = member;
+        let delta = delta as i64;
+        let only = |kind: ScoreType| if kind == score_type { delta } else { 0 };
+
+        let mut tx = self.pool.begin().await?;
+        for (period, bucket) in buckets {
+            sqlx::query(
+                "INSERT INTO period_scores \
+                    (guild_id, user_id, period, bucket, voice_time, afk_time) \
+                VALUES (?, ?, ?, ?, ?, ?) \
+                ON CONFLICT (guild_id, period, bucket, user_id) DO UPDATE SET \
+                    voice_time = voice_time + excluded.voice_time, \
+                    afk_time = afk_time + excluded.afk_time",
+            )
+            .bind(guild_id.get() as i64)
+            .bind(user_id.get() as i64)
+            .bind(period.as_str())
+            .bind(bucket)
+            .bind(only(ScoreType::Voice))
+            .bind(only(ScoreType::Afk))
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn period_scores(
+        &self,
+        guild_id: GuildId,
+        period: Period,
+        bucket: String,
+    ) -> Result<Vec<Score>> {
+        let rows = sqlx::query_as::<_, PeriodScoreRow>(
+            "SELECT user_id, voice_time, afk_time FROM period_scores \
+            WHERE guild_id = ? AND period = ? AND bucket = ?",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(period.as_str())
+        .bind(bucket)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| row.into_score(guild_id))
+            .collect())
+    }
+
+    async fn get_season(&self, guild_id: GuildId) -> Result<Option<Season>> {
+        let row = sqlx::query_as::<_, SeasonRow>(
+            "SELECT id, name FROM seasons WHERE guild_id = ? AND ended_at IS NULL",
+        )
+        .bind(guild_id.get() as i64)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(Season::from))
+    }
+
+    async fn start_season(
+        &self,
+        guild_id: GuildId,
+        name: String,
+        started_at: DateTime<Utc>,
+    ) -> Result<Season> {
+        if let Some(season) = self.get_season(guild_id).await? {
+            bail!("Season `{}` is still running!", season.name);
+        }
+
+        let row = sqlx::query_as::<_, SeasonRow>(
+            "INSERT INTO seasons (guild_id, name, started_at) VALUES (?, ?, ?) \
+            RETURNING id, name",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(name)
+        .bind(started_at.timestamp())
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(Season::from(row))
+    }
+
+    async fn end_season(&self, guild_id: GuildId, ended_at: DateTime<Utc>) -> Result<EndedSeason> {
+        let season = sqlx::query_as::<_, SeasonRow>(
+            "UPDATE seasons SET ended_at = ? WHERE guild_id = ? AND ended_at IS NULL \
+            RETURNING id, name",
+        )
+        .bind(ended_at.timestamp())
+        .bind(guild_id.get() as i64)
+        .fetch_optional(&self.pool)
+        .await?
+        .context("There is no season running right now!")?;
+
+        let mut standings = self
+            .period_scores(guild_id, Period::Season, season.id.to_string())
+            .await?;
+        standings.sort_by(|a, b| b.cmp(a));
+
+        Ok(EndedSeason {
+            name: season.name,
+            standings,
+        })
+    }
+
+    async fn incr_channel_score(
+        &self,
+        member: GuildUser,
+        channel_id: ChannelId,
+        delta: u64,
+    ) -> Result<()> {
+        let GuildUser(guild_id, user_id) = member;
+
+        sqlx::query(
+            "INSERT INTO channel_scores (guild_id, channel_id, user_id, time) \
+            VALUES (?, ?, ?, ?) \
+            ON CONFLICT (guild_id, channel_id, user_id) DO UPDATE SET \
+                time = time + excluded.time",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(channel_id.get() as i64)
+        .bind(user_id.get() as i64)
+        .bind(delta as i64)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn set_channel_peak(
+        &self,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        users: u64,
+        at: DateTime<Utc>,
+    ) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO channels (guild_id, channel_id, peak_users, peak_at) \
+            VALUES (?, ?, ?, ?) \
+            ON CONFLICT (guild_id, channel_id) DO UPDATE SET \
+                peak_users = excluded.peak_users, \
+                peak_at = excluded.peak_at \
+            WHERE excluded.peak_users > peak_users",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(channel_id.get() as i64)
+        .bind(users as i64)
+        .bind(at.timestamp())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn channel_stats(&self, guild_id: GuildId) -> Result<Vec<ChannelStats>> {
+        let rows: Vec<(i64, i64, Option<i64>, Option<i64>)> = sqlx::query_as(
+            "SELECT channel_id, SUM(time), MAX(peak_users), MAX(peak_at) FROM ( \
+                SELECT channel_id, time, NULL AS peak_users, NULL AS peak_at \
+                FROM channel_scores WHERE guild_id = ?1 \
+                UNION ALL \
+                SELECT channel_id, 0, peak_users, peak_at \
+                FROM channels WHERE guild_id = ?1 \
+            ) GROUP BY channel_id",
+        )
+        .bind(guild_id.get() as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(channel_id, time, peak_users, peak_at)| ChannelStats {
+                channel_id: ChannelId::new(channel_id as u64),
+                time: Duration::from_secs(time as u64),
+                peak_users: peak_users.unwrap_or_default() as u64,
+                peak_at: peak_at.and_then(at),
+            })
+            .collect())
+    }
+
+    async fn channel_times(&self, member: GuildUser) -> Result<Vec<(ChannelId, Duration)>> {
+        let GuildUser(guild_id, user_id) = member;
+
+        let rows: Vec<(i64, i64)> = sqlx::query_as(
+            "SELECT channel_id, time FROM channel_scores WHERE guild_id = ? AND user_id = ?",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(user_id.get() as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(channel_id, time)| {
+                (
+                    ChannelId::new(channel_id as u64),
+                    Duration::from_secs(time as u64),
+                )
+            })
+            .collect())
+    }
+
+    async fn unlock_achievement(
+        &self,
+        member: GuildUser,
+        achievement: &'static str,
+        unlocked_at: DateTime<Utc>,
+    ) -> Result<()> {
+        let GuildUser(guild_id, user_id) = member;
+
+        sqlx::query(
+            "INSERT OR IGNORE INTO achievements (guild_id, user_id, achievement, unlocked_at) \
+            VALUES (?, ?, ?, ?)",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(user_id.get() as i64)
+        .bind(achievement)
+        .bind(unlocked_at.timestamp())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn achievements(&self, member: GuildUser) -> Result<Vec<Unlocked>> {
+        let GuildUser(guild_id, user_id) = member;
+
+        let rows: Vec<(String, i64)> = sqlx::query_as(
+            "SELECT achievement, unlocked_at FROM achievements \
+            WHERE guild_id = ? AND user_id = ? ORDER BY unlocked_at",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(user_id.get() as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(achievement, unlocked_at)| Unlocked {
+                achievement,
+                unlocked_at: at(unlocked_at).unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    async fn get_milestones(&self, guild_id: GuildId) -> Result<Milestones> {
+        let row: Option<(Option<String>, String)> =
+            sqlx::query_as("SELECT milestone_roles, milestone_mode FROM guilds WHERE guild_id = ?")
+                .bind(guild_id.get() as i64)
+                .fetch_optional(&self.pool)
+                .await?;
+        let Some((tiers, mode)) = row else {
+            return Ok(Milestones::default());
+        };
+
+        let mut tiers: Vec<Milestone> = match tiers {
+            Some(tiers) => serde_json::from_str(&tiers)?,
+            None => Vec::new(),
+        };
+        tiers.sort_by_key(|tier| tier.hours);
+
+        Ok(Milestones {
+            mode: mode.parse()?,
+            tiers,
+        })
+    }
+
+    async fn set_milestones(
+        &self,
+        guild_id: GuildId,
+        tiers: Option<Vec<Milestone>>,
+        mode: Option<MilestoneMode>,
+    ) -> Result<()> {
+        let tiers = tiers.as_ref().map(serde_json::to_string).transpose()?;
+
+        sqlx::query(
+            "INSERT INTO guilds (guild_id, milestone_roles, milestone_mode) \
+            VALUES (?1, ?2, COALESCE(?3, 'all')) \
+            ON CONFLICT (guild_id) DO UPDATE SET \
+                milestone_roles = COALESCE(?2, milestone_roles), \
+                milestone_mode = COALESCE(?3, milestone_mode)",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(tiers)
+        .bind(mode.map(|mode| mode.as_str()))
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_activity_rules(&self, guild_id: GuildId) -> Result<ActivityRules> {
+        let rules: Option<Option<String>> =
+            sqlx::query_scalar("SELECT activity_rules FROM guilds WHERE guild_id = ?")
+                .bind(guild_id.get() as i64)
+                .fetch_optional(&self.pool)
+                .await?;
+
+        match rules.flatten() {
+            Some(rules) => Ok(serde_json::from_str(&rules)?),
+            None => Ok(ActivityRules::default()),
+        }
+    }
+
+    async fn set_activity_rules(&self, guild_id: GuildId, rules: ActivityRules) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO guilds (guild_id, activity_rules) VALUES (?1, ?2) \
+            ON CONFLICT (guild_id) DO UPDATE SET activity_rules = ?2",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(serde_json::to_string(&rules)?)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn forget_member(&self, member: GuildUser) -> Result<()> {
+        let GuildUser(guild_id, user_id) = member;
+
+        let mut tx = self.pool.begin().await?;
+        for table in [
+            "sessions",
+            "voice_sessions",
+            "period_scores",
+            "channel_scores",
+            "achievements",
+        ] {
+            sqlx::query(&format!(
+                "DELETE FROM {table} WHERE guild_id = ? AND user_id = ?"
+            ))
+            .bind(guild_id.get() as i64)
+            .bind(user_id.get() as i64)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poise::serenity_prelude::RoleId;
+
+    use super::*;
+
+    const GUILD: GuildId = GuildId::new(1);
+
+    fn member(user_id: u64) -> GuildUser {
+        GuildUser(GUILD, UserId::new(user_id))
+    }
+
+    #[tokio::test]
+    async fn end_season_ranks_its_period_scores() {
+        let storage = SqliteStorage::connect("sqlite::memory:").await.unwrap();
+        let now = Utc::now();
+
+        let season = storage
+            .start_season(GUILD, "Spring".to_string(), now)
+            .await
+            .unwrap();
+        assert!(storage
+            .start_season(GUILD, "Summer".to_string(), now)
+            .await
+            .is_err());
+        for (user_id, delta) in [(1, 60), (2, 120), (1, 30)] {
+            let buckets = vec![(Period::Season, season.id.clone())];
+            storage
+                .incr_period_scores(member(user_id), ScoreType::Voice, delta, buckets)
+                .await
+                .unwrap();
+        }
+
+        let ended = storage.end_season(GUILD, now).await.unwrap();
+
+        let standings = ended
+            .standings
+            .iter()
+            .map(|score| (score.user_id.get(), score.score.as_secs()))
+            .collect::<Vec<_>>();
+        assert_eq!(ended.name, "Spring");
+        assert_eq!(standings, [(2, 120), (1, 90)]);
+        assert!(storage.get_season(GUILD).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn channel_stats_adds_up_time_and_keeps_the_highest_peak() {
+        let storage = SqliteStorage::connect("sqlite::memory:").await.unwrap();
+        let now = Utc::now();
+        let (busy, empty) = (ChannelId::new(10), ChannelId::new(20));
+
+        storage
+            .incr_channel_score(member(1), busy, 60)
+            .await
+            .unwrap();
+        storage
+            .incr_channel_score(member(2), busy, 30)
+            .await
+            .unwrap();
+        storage.set_channel_peak(GUILD, busy, 2, now).await.unwrap();
+        storage.set_channel_peak(GUILD, busy, 1, now).await.unwrap();
+        storage
+            .set_channel_peak(GUILD, empty, 1, now)
+            .await
+            .unwrap();
+
+        let mut stats = storage.channel_stats(GUILD).await.unwrap();
+        stats.sort_by_key(|stat| stat.channel_id);
+
+        assert_eq!(stats[0].channel_id, busy);
+        assert_eq!(stats[0].time, Duration::from_secs(90));
+        assert_eq!(stats[0].peak_users, 2);
+        assert_eq!(stats[1].channel_id, empty);
+        assert_eq!(stats[1].time, Duration::ZERO);
+        assert_eq!(
+            storage.channel_times(member(1)).await.unwrap(),
+            [(busy, Duration::from_secs(60))]
+        );
+    }
+
+    #[tokio::test]
+    async fn guild_settings_leave_each_other_alone() {
+        let storage = SqliteStorage::connect("sqlite::memory:").await.unwrap();
+        let afk_channel = Some(ChannelId::new(10));
+        let tiers = vec![
+            Milestone {
+                hours: 10,
+                role_id: RoleId::new(2),
+            },
+            Milestone {
+                hours: 1,
+                role_id: RoleId::new(1),
+            },
+        ];
+        let rules = ActivityRules {
+            solo: true,
+            ..Default::default()
+        };
+
+        storage
+            .set_config(GUILD, afk_channel, None, None)
+            .await
+            .unwrap();
+        storage
+            .set_milestones(GUILD, Some(tiers), None)
+            .await
+            .unwrap();
+        storage
+            .set_milestones(GUILD, None, Some(MilestoneMode::Highest))
+            .await
+            .unwrap();
+        storage.set_activity_rules(GUILD, rules).await.unwrap();
+
+        let milestones = storage.get_milestones(GUILD).await.unwrap();
+        assert_eq!(milestones.mode, MilestoneMode::Highest);
+        assert_eq!(
+            milestones.tiers.iter().map(|t| t.hours).collect::<Vec<_>>(),
+            [1, 10]
+        );
+        assert_eq!(storage.get_activity_rules(GUILD).await.unwrap(), rules);
+        assert_eq!(
+            storage.get_config(GUILD).await.unwrap().afk_channel,
+            afk_channel
+        );
+    }
+}
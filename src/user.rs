@@ -16,6 +16,28 @@ impl Username {
 
         Ok(Username { username })
     }
+
+    /// The name members see in the guild: nickname if the member is cached,
+    /// otherwise the global display name, otherwise the username.
+    pub async fn display_name_from_user_id(ctx: Context<'_>, user_id: UserId) -> Result<Username> {
+        let nickname = ctx.guild_id().and_then(|guild_id| {
+            ctx.cache().guild(guild_id).and_then(|guild| {
+                guild
+                    .members
+                    .get(&user_id)
+                    .map(|member| member.display_name().to_string())
+            })
+        });
+        let username: Arc<str> = match nickname {
+            Some(nickname) => nickname.into(),
+            None => {
+                let user = user_id.to_user(ctx).await?;
+                user.global_name.unwrap_or(user.name).into()
+            }
+        };
+
+        Ok(Username { username })
+    }
 }
 
 impl std::ops::Deref for Username {